@@ -49,9 +49,9 @@ fn main() {
     check_pair();
 
     let ty = Ty::Func(Ty::Unit.into(), Ty::Unit.into());
-    let ctx = ListContext::<Ty>::empty().append(ty.clone());
+    let ctx = ListContext::from_iter([ty.clone()]);
     let expr = Expr::Var(0);
-    assert!(Expr::check(&expr, &ty, &ctx).unwrap());
+    assert!(Expr::check(&expr, &ty, &ctx, &()).unwrap());
 
     println!("Success");
 }
@@ -62,7 +62,8 @@ fn check_lambda() {
     let ty = Ty::Func(Ty::Unit.into(), Ty::Unit.into());
 
     assert!(
-        CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty()).unwrap()
+        CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty(), &())
+            .unwrap()
     );
 }
 
@@ -76,7 +77,7 @@ fn check_pair() {
         Prod(Unit.into(), Unit.into())
     };
     assert_eq!(
-        PartialSynthAttribute::<Ty>::try_synth(&expr, &Context::empty())
+        PartialSynthAttribute::<Ty>::try_synth(&expr, &Context::empty(), &())
             .unwrap(),
         Some(ty)
     )
@@ -86,7 +87,7 @@ fn check_lambda_app() {
     use Expr::*;
     let expr = App(Lam(Unit.into()).into(), Unit.into());
     assert_eq!(
-        PartialSynthAttribute::<Ty>::try_synth(&expr, &Context::empty())
+        PartialSynthAttribute::<Ty>::try_synth(&expr, &Context::empty(), &())
             .unwrap(),
         Some(Ty::Unit)
     )
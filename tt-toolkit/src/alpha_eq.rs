@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::Interned;
+
+/// Equality of terms up to the names of their bound variables.
+///
+/// Derived implementations compare terms structurally, skipping the fields
+/// marked as `#[metadata]`, `#[var_name]` or `#[binding_name]`. Variables are
+/// de Bruijn indices, so alpha-equivalent terms have equal indices. Fields
+/// whose types don't implement `AlphaEquivalent` are compared with
+/// `PartialEq`.
+pub trait AlphaEquivalent {
+    fn alpha_eq(&self, other: &Self) -> bool;
+}
+
+impl<T: AlphaEquivalent + ?Sized> AlphaEquivalent for Box<T> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        (**self).alpha_eq(other)
+    }
+}
+
+impl<T: AlphaEquivalent + ?Sized> AlphaEquivalent for Rc<T> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(self, other) || (**self).alpha_eq(other)
+    }
+}
+
+impl<T: AlphaEquivalent + ?Sized> AlphaEquivalent for Arc<T> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(self, other) || (**self).alpha_eq(other)
+    }
+}
+
+impl<T: AlphaEquivalent> AlphaEquivalent for Interned<T> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        self == other || (**self).alpha_eq(other)
+    }
+}
+
+impl<T: AlphaEquivalent> AlphaEquivalent for Option<T> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(lhs), Some(rhs)) => lhs.alpha_eq(rhs),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: AlphaEquivalent> AlphaEquivalent for [T] {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other).all(|(lhs, rhs)| lhs.alpha_eq(rhs))
+    }
+}
+
+impl<T: AlphaEquivalent, const N: usize> AlphaEquivalent for [T; N] {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        self.as_slice().alpha_eq(other.as_slice())
+    }
+}
+
+impl<T: AlphaEquivalent> AlphaEquivalent for Vec<T> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        self.as_slice().alpha_eq(other.as_slice())
+    }
+}
+
+/// Maps are compared by their keys, and the values are compared up to
+/// alpha-equivalence.
+impl<K: Eq, V: AlphaEquivalent> AlphaEquivalent for BTreeMap<K, V> {
+    fn alpha_eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other)
+                .all(|((lhs_key, lhs), (rhs_key, rhs))| {
+                    lhs_key == rhs_key && lhs.alpha_eq(rhs)
+                })
+    }
+}
+
+macro_rules! tuple_impl {
+    ($($param:ident $index:tt),*) => {
+        impl<$($param: AlphaEquivalent),*> AlphaEquivalent for ($($param,)*) {
+            fn alpha_eq(&self, other: &Self) -> bool {
+                true $(&& self.$index.alpha_eq(&other.$index))*
+            }
+        }
+    };
+}
+
+tuple_impl!(T0 0);
+tuple_impl!(T0 0, T1 1);
+tuple_impl!(T0 0, T1 1, T2 2);
+tuple_impl!(T0 0, T1 1, T2 2, T3 3);
+tuple_impl!(T0 0, T1 1, T2 2, T3 3, T4 4);
+tuple_impl!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5);
@@ -1,15 +1,29 @@
 use crate::{Context, ContextualEq};
 
 /// Represents a syntax type which can synthesize an attribute of type `Attr` from its syntax tree.
+///
+/// Attributes are synthesized in a local context of the variables in scope,
+/// and a signature `Sig` of the global declarations terms may refer to, such
+/// as a [`Signature`](crate::Signature). Types without global references use
+/// `()` as their signature.
 pub trait SynthAttribute<Attr> {
     type Error;
     type Entry;
     type Ctx: Context<Self::Entry>;
+    type Sig;
 
-    fn synth(&self, ctx: &Self::Ctx) -> Result<Attr, Self::Error>;
-
-    fn synth_closed(&self) -> Result<Attr, Self::Error> {
-        self.synth(&Context::empty())
+    fn synth(
+        &self,
+        ctx: &Self::Ctx,
+        sig: &Self::Sig,
+    ) -> Result<Attr, Self::Error>;
+
+    /// Synthesize the attribute in an empty context and signature.
+    fn synth_closed(&self) -> Result<Attr, Self::Error>
+    where
+        Self::Sig: Default,
+    {
+        self.synth(&Context::empty(), &Default::default())
     }
 }
 
@@ -17,11 +31,19 @@ pub trait PartialSynthAttribute<Attr> {
     type Error;
     type Entry;
     type Ctx: Context<Self::Entry>;
+    type Sig;
 
-    fn try_synth(&self, ctx: &Self::Ctx) -> Result<Option<Attr>, Self::Error>;
-
-    fn try_synth_closed(&self) -> Result<Option<Attr>, Self::Error> {
-        self.try_synth(&Context::empty())
+    fn try_synth(
+        &self,
+        ctx: &Self::Ctx,
+        sig: &Self::Sig,
+    ) -> Result<Option<Attr>, Self::Error>;
+
+    fn try_synth_closed(&self) -> Result<Option<Attr>, Self::Error>
+    where
+        Self::Sig: Default,
+    {
+        self.try_synth(&Context::empty(), &Default::default())
     }
 }
 
@@ -30,15 +52,20 @@ pub trait CheckAttribute<Attr> {
     type Error;
     type Entry;
     type Ctx: Context<Self::Entry>;
+    type Sig;
 
     fn check(
         &self,
         attr: &Attr,
         ctx: &Self::Ctx,
+        sig: &Self::Sig,
     ) -> Result<Self::Check, Self::Error>;
 
-    fn check_closed(&self, attr: &Attr) -> Result<Self::Check, Self::Error> {
-        self.check(attr, &Context::empty())
+    fn check_closed(&self, attr: &Attr) -> Result<Self::Check, Self::Error>
+    where
+        Self::Sig: Default,
+    {
+        self.check(attr, &Context::empty(), &Default::default())
     }
 }
 
@@ -53,8 +80,14 @@ where
 
     type Ctx = Expr::Ctx;
 
-    fn try_synth(&self, ctx: &Self::Ctx) -> Result<Option<Attr>, Self::Error> {
-        Ok(Some(self.synth(ctx)?))
+    type Sig = Expr::Sig;
+
+    fn try_synth(
+        &self,
+        ctx: &Self::Ctx,
+        sig: &Self::Sig,
+    ) -> Result<Option<Attr>, Self::Error> {
+        Ok(Some(self.synth(ctx, sig)?))
     }
 }
 
@@ -64,23 +97,26 @@ pub trait BidirAttribute<Attr>:
         Error = <Self as BidirAttribute<Attr>>::Error,
         Entry = <Self as BidirAttribute<Attr>>::Entry,
         Ctx = <Self as BidirAttribute<Attr>>::Ctx,
+        Sig = <Self as BidirAttribute<Attr>>::Sig,
     > + PartialSynthAttribute<
         Attr,
         Error = <Self as BidirAttribute<Attr>>::Error,
         Entry = <Self as BidirAttribute<Attr>>::Entry,
         Ctx = <Self as BidirAttribute<Attr>>::Ctx,
+        Sig = <Self as BidirAttribute<Attr>>::Sig,
     >
 {
     type Error;
     type Entry;
     type Ctx;
+    type Sig;
 }
 
 #[diagnostic::do_not_recommend]
 impl<Expr, Attr> CheckAttribute<Attr> for Expr
 where
     Expr: SynthAttribute<Attr>,
-    Attr: ContextualEq<Expr::Entry, Expr::Ctx>,
+    Attr: ContextualEq<Expr::Entry, Expr::Ctx, Expr::Sig>,
     Expr::Error: From<Attr::Error>,
 {
     type Check = Attr::Check;
@@ -88,24 +124,27 @@ where
     type Error = Expr::Error;
     type Entry = Expr::Entry;
     type Ctx = Expr::Ctx;
+    type Sig = Expr::Sig;
 
     fn check(
         &self,
         attr: &Attr,
         ctx: &Self::Ctx,
+        sig: &Self::Sig,
     ) -> Result<Self::Check, Self::Error> {
-        Ok(Attr::equiv(ctx, &self.synth(ctx)?, attr)?)
+        Ok(Attr::equiv(ctx, sig, &self.synth(ctx, sig)?, attr)?)
     }
 }
 
 #[diagnostic::do_not_recommend]
 impl<Expr, Attr> BidirAttribute<Attr> for Expr
 where
-    Attr: ContextualEq<Expr::Entry, Expr::Ctx>,
+    Attr: ContextualEq<Expr::Entry, Expr::Ctx, Expr::Sig>,
     Expr: SynthAttribute<Attr>,
     Expr::Error: From<Attr::Error>,
 {
     type Error = Expr::Error;
     type Entry = Expr::Entry;
     type Ctx = Expr::Ctx;
+    type Sig = Expr::Sig;
 }
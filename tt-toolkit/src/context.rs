@@ -1,31 +1,282 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::ControlFlow;
+use std::rc::{Rc, Weak};
+
 use cons_list::ConsList;
+use thiserror::Error;
+
+use crate::debruijn_indexed::level_to_index;
+use crate::{DeBruijnIndexed, Sort, VarKind};
 
-use crate::DeBruijnIndexed;
+/// A variable whose index is out of range of the context it was looked up
+/// in.
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+#[error("Unbound variable index {var} in a context of length {len}")]
+pub struct UnboundVariable {
+    pub var: usize,
+    /// The length of the context.
+    pub len: usize,
+}
 
 pub trait Context<Entry> {
     fn iter(&self) -> impl Iterator<Item = Entry>;
     fn append(&self, variable: Entry) -> Self;
     fn empty() -> Self;
 
+    /// Add a variable to the context along with the name it was bound with.
+    /// Contexts which don't record names ignore it.
+    fn append_named(&self, name: &str, variable: Entry) -> Self
+    where Self: Sized,
+    {
+        let _ = name;
+        self.append(variable)
+    }
+
+    /// The number of entries in the context.
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add several variables to the context, oldest first.
+    fn extend(&self, variables: impl IntoIterator<Item = Entry>) -> Self
+    where Self: Sized + Clone,
+    {
+        variables
+            .into_iter()
+            .fold(self.clone(), |ctx, variable| ctx.append(variable))
+    }
+
+    /// Remove the `n` most recent variables from the context, or all of them
+    /// if it has fewer than `n`.
+    fn drop_n(&self, n: usize) -> Self
+    where Self: Sized,
+    {
+        let entries: Vec<Entry> = self.iter().skip(n).collect();
+        entries
+            .into_iter()
+            .rev()
+            .fold(Self::empty(), |ctx, variable| ctx.append(variable))
+    }
+
+    /// Keep only the `len` oldest variables of the context.
+    fn truncate(&self, len: usize) -> Self
+    where Self: Sized,
+    {
+        self.drop_n(self.len().saturating_sub(len))
+    }
+
     fn get(&self, var: usize) -> Option<Entry>
     where Entry: Clone,
     {
         self.iter().nth(var)
     }
 
+    /// Look up a variable by its de Bruijn index, returning an error if it
+    /// isn't bound by the context.
+    fn lookup(&self, var: usize) -> Result<Entry, UnboundVariable>
+    where Entry: Clone,
+    {
+        self.get(var).ok_or_else(|| UnboundVariable { var, len: self.len() })
+    }
+
     fn get_shifted(&self, var: usize) -> Option<Entry> 
     where Entry: DeBruijnIndexed
     {
         self.iter().nth(var).map(|expr| expr.increment_indices_by(var + 1))
     }
+
+    /// Look up a variable by its de Bruijn level, counting from the oldest
+    /// entry in the context.
+    fn get_level(&self, level: usize) -> Option<Entry>
+    where Entry: Clone,
+    {
+        self.get(level_to_index(level, self.len())?)
+    }
+
+    /// Look up a variable by its de Bruijn level, shifting the indices of
+    /// the entry to be valid in the whole context as in [`Context::get_shifted`].
+    fn get_level_shifted(&self, level: usize) -> Option<Entry>
+    where Entry: DeBruijnIndexed
+    {
+        self.get_shifted(level_to_index(level, self.len())?)
+    }
+}
+
+/// A context entry holding the type of a variable, and its value if it was
+/// bound by a definition such as a `let`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Def<Ty, Val> {
+    pub ty: Ty,
+    pub value: Option<Val>,
+}
+
+impl<Ty, Val> Def<Ty, Val> {
+    /// A variable of type `ty` with no value, such as one bound by a lambda.
+    pub fn assumption(ty: Ty) -> Self {
+        Def { ty, value: None }
+    }
+
+    /// A variable of type `ty` defined to be `value`.
+    pub fn definition(ty: Ty, value: Val) -> Self {
+        Def {
+            ty,
+            value: Some(value),
+        }
+    }
+}
+
+impl<Ty, Val> DeBruijnIndexed for Def<Ty, Val>
+where
+    Ty: DeBruijnIndexed,
+    Val: DeBruijnIndexed,
+{
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        Def {
+            ty: self.ty.map_sort_indices_from(sort, start, map_fn.clone()),
+            value: self.value.map_sort_indices_from(sort, start, map_fn),
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        Def {
+            ty: self.ty.map_vars_at_depth(depth, map_fn.clone()),
+            value: self.value.map_vars_at_depth(depth, map_fn),
+        }
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.ty.visit_sort_free_vars_from(sort, start, visit_fn)?;
+        self.value.visit_sort_free_vars_from(sort, start, visit_fn)
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        self.ty
+            .max_sort_free_index_from(sort, start)
+            .max(self.value.max_sort_free_index_from(sort, start))
+    }
+
+    fn interned_size(&self) -> usize {
+        self.ty.interned_size() + self.value.interned_size()
+    }
+
+    fn get_var(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Context entries which can record the definition of a variable bound by a
+/// `#[let_binding]` field, used by the derived attribute implementations.
+pub trait LetEntry<Ty, Val> {
+    fn let_entry(ty: Ty, value: &Val) -> Self;
+}
+
+impl<Ty, Val: Clone> LetEntry<Ty, Val> for Def<Ty, Val> {
+    fn let_entry(ty: Ty, value: &Val) -> Self {
+        Def::definition(ty, value.clone())
+    }
 }
 
 pub trait PartialContext<Entry>: Context<Option<Entry>> {}
 
 impl<Entry, Ctx> PartialContext<Entry> for Ctx where Ctx: Context<Option<Entry>> {}
 
+#[derive(Clone, PartialEq, Debug)]
 pub struct ListContext<T>(ConsList<T>);
 
+impl<T> FromIterator<T> for ListContext<T> {
+    /// Build a context from its entries, oldest first.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        ListContext(iter.into_iter().collect())
+    }
+}
+
+/// Renders the context as `x0 : T0, x1 : T1 ⊢`, oldest first, naming each
+/// variable by its de Bruijn level. Entries are printed as stored, relative
+/// to the context they were appended to; use
+/// [`ListContext::display_shifted`] to print them valid in the whole context.
+impl<T: fmt::Display> fmt::Display for ListContext<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<&T> = self.0.iter().collect();
+        write_entries(f, entries.into_iter().rev())
+    }
+}
+
+impl<T: DeBruijnIndexed> ListContext<T> {
+    /// Displays the context like its `Display` implementation, but with each
+    /// entry shifted to be valid in the whole context, as by
+    /// [`Context::get_shifted`].
+    pub fn display_shifted(&self) -> ShiftedListContext<'_, T> {
+        ShiftedListContext(self)
+    }
+}
+
+/// Display adaptor returned by [`ListContext::display_shifted`].
+pub struct ShiftedListContext<'a, T>(&'a ListContext<T>);
+
+impl<T> fmt::Display for ShiftedListContext<'_, T>
+where
+    T: DeBruijnIndexed + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shifted: Vec<T> = self
+            .0
+            .0
+            .iter()
+            .enumerate()
+            .map(|(var, entry)| entry.increment_indices_by(var + 1))
+            .collect();
+        write_entries(f, shifted.iter().rev())
+    }
+}
+
+fn write_entries<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    entries: impl Iterator<Item = T>,
+) -> fmt::Result {
+    let mut empty = true;
+    for (level, entry) in entries.enumerate() {
+        if level > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "x{level} : {entry}")?;
+        empty = false;
+    }
+    if empty {
+        write!(f, "⊢")
+    } else {
+        write!(f, " ⊢")
+    }
+}
+
 impl<Entry> Context<Entry> for ListContext<Entry> where Entry: Clone {
     fn iter(&self) -> impl Iterator<Item = Entry> {
         self.0.iter().cloned()
@@ -38,4 +289,390 @@ impl<Entry> Context<Entry> for ListContext<Entry> where Entry: Clone {
     fn empty() -> Self {
         ListContext(ConsList::new())
     }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn drop_n(&self, n: usize) -> Self {
+        ListContext(self.0.tailn(n))
+    }
+}
+
+/// A context which records the name of each variable, for use in error
+/// messages and when printing terms.
+///
+/// Names added with [`Context::append_named`] are freshened so that each
+/// name in the context is distinct, and [`NamedContext::name_of`] refers to
+/// exactly one variable. Variables added with [`Context::append`] have no
+/// name.
+#[derive(Clone, Debug)]
+pub struct NamedContext<T>(ConsList<NamedEntry<T>>);
+
+#[derive(Clone, Debug)]
+struct NamedEntry<T> {
+    /// The name the variable was bound with.
+    bound_name: Option<String>,
+    /// The bound name, freshened to be distinct from the names of the
+    /// variables bound before it.
+    name: Option<String>,
+    entry: T,
+}
+
+impl<T> NamedContext<T> {
+    /// The name of the variable with de Bruijn index `var`, if it was
+    /// bound with one.
+    pub fn name_of(&self, var: usize) -> Option<&str> {
+        self.0.iter().nth(var)?.name.as_deref()
+    }
+
+    /// The names of the variables in the context, most recent first.
+    pub fn names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.0.iter().map(|named| named.name.as_deref())
+    }
+
+    /// The de Bruijn index of the innermost variable bound with the name
+    /// `name`, as it shadows any outer variables bound with the same name.
+    /// Names are compared with the names given to [`Context::append_named`],
+    /// before they are freshened.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|named| named.bound_name.as_deref() == Some(name))
+    }
+
+    /// `name`, or `name` followed by the smallest number which makes it
+    /// distinct from the names in the context.
+    pub fn fresh_name(&self, name: &str) -> String {
+        let names: HashSet<&str> = self.names().flatten().collect();
+        if !names.contains(name) {
+            return name.to_string();
+        }
+        (1..)
+            .map(|suffix| format!("{name}{suffix}"))
+            .find(|fresh| !names.contains(fresh.as_str()))
+            .expect("A context has finitely many names")
+    }
+}
+
+impl<Entry> Context<Entry> for NamedContext<Entry> where Entry: Clone {
+    fn iter(&self) -> impl Iterator<Item = Entry> {
+        self.0.iter().map(|named| named.entry.clone())
+    }
+
+    fn append(&self, variable: Entry) -> Self {
+        NamedContext(self.0.append(NamedEntry {
+            bound_name: None,
+            name: None,
+            entry: variable,
+        }))
+    }
+
+    fn empty() -> Self {
+        NamedContext(ConsList::new())
+    }
+
+    fn append_named(&self, name: &str, variable: Entry) -> Self
+    where Self: Sized,
+    {
+        NamedContext(self.0.append(NamedEntry {
+            bound_name: Some(name.to_string()),
+            name: Some(self.fresh_name(name)),
+            entry: variable,
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn drop_n(&self, n: usize) -> Self {
+        NamedContext(self.0.tailn(n))
+    }
+}
+
+/// A context stored in a vector, with the most recent entry last.
+///
+/// Looking up a variable takes constant time. Contexts extended from one
+/// another share a single vector, each using a prefix of it, and
+/// [`Context::append`] pushes onto the vector in place unless another live
+/// context uses the entries past the end of this one. Appending takes
+/// amortised constant time when contexts are used like a stack, as in a
+/// derived type checker, which drops the context under a binder before
+/// extending its own again. Appending to a context which has a live
+/// extension copies its entries, so use [`TreeContext`] when many contexts
+/// extended from a common prefix are kept alive at once.
+pub struct VecContext<T> {
+    shared: Rc<RefCell<SharedVec<T>>>,
+    len: usize,
+    /// Keeps the first `len` entries of the shared vector from being
+    /// removed while this context is alive.
+    claim: Rc<()>,
+}
+
+struct SharedVec<T> {
+    entries: Vec<T>,
+    /// For each length, the claim held by the contexts of that length, so
+    /// that entries past the end of a context can be reused once no
+    /// context of a greater length is alive.
+    claims: Vec<Weak<()>>,
+}
+
+impl<T> SharedVec<T> {
+    /// The claim held by the contexts of length `len`, which is created if
+    /// none of them are alive.
+    fn claim(&mut self, len: usize) -> Rc<()> {
+        let Some(index) = len.checked_sub(1) else {
+            return Rc::new(());
+        };
+        if let Some(claim) = self.claims[index].upgrade() {
+            return claim;
+        }
+        let claim = Rc::new(());
+        self.claims[index] = Rc::downgrade(&claim);
+        claim
+    }
+
+    /// Remove the entries past the first `len`, if no context which uses
+    /// them is alive, returning whether they were removed.
+    fn reclaim(&mut self, len: usize) -> bool {
+        let live = self.claims[len..]
+            .iter()
+            .rposition(|claim| claim.strong_count() > 0);
+        if live.is_some() {
+            return false;
+        }
+        self.entries.truncate(len);
+        self.claims.truncate(len);
+        true
+    }
+}
+
+impl<T> VecContext<T> {
+    fn from_shared(shared: Rc<RefCell<SharedVec<T>>>, len: usize) -> Self {
+        let claim = shared.borrow_mut().claim(len);
+        VecContext { shared, len, claim }
+    }
+
+    fn from_vec(entries: Vec<T>) -> Self {
+        let claims = entries.iter().map(|_| Weak::new()).collect();
+        let len = entries.len();
+        VecContext::from_shared(
+            Rc::new(RefCell::new(SharedVec { entries, claims })),
+            len,
+        )
+    }
+}
+
+impl<T: Clone> VecContext<T> {
+    /// Add a variable to the context in place.
+    pub fn push(&mut self, variable: T) {
+        *self = self.extend(std::iter::once(variable));
+    }
+}
+
+impl<T> Clone for VecContext<T> {
+    fn clone(&self) -> Self {
+        VecContext {
+            shared: Rc::clone(&self.shared),
+            len: self.len,
+            claim: Rc::clone(&self.claim),
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for VecContext<T> {
+    /// Build a context from its entries, oldest first.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        VecContext::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<Entry> Context<Entry> for VecContext<Entry> where Entry: Clone {
+    fn iter(&self) -> impl Iterator<Item = Entry> {
+        (0..self.len).map(|var| {
+            self.get(var)
+                .expect("A context uses a prefix of its entries")
+        })
+    }
+
+    fn append(&self, variable: Entry) -> Self {
+        self.extend(std::iter::once(variable))
+    }
+
+    fn empty() -> Self {
+        VecContext::from_vec(Vec::new())
+    }
+
+    fn extend(&self, variables: impl IntoIterator<Item = Entry>) -> Self {
+        // The variables may be read from this context, so they are collected
+        // before the shared vector is borrowed.
+        let variables: Vec<Entry> = variables.into_iter().collect();
+        let mut shared = self.shared.borrow_mut();
+        if !shared.reclaim(self.len) {
+            drop(shared);
+            let entries: Vec<Entry> = self.iter().collect();
+            let ctx: Self = entries.into_iter().rev().collect();
+            return ctx.extend(variables);
+        }
+        for variable in variables {
+            shared.entries.push(variable);
+            shared.claims.push(Weak::new());
+        }
+        let len = shared.entries.len();
+        drop(shared);
+        VecContext::from_shared(Rc::clone(&self.shared), len)
+    }
+
+    fn drop_n(&self, n: usize) -> Self {
+        self.truncate(self.len.saturating_sub(n))
+    }
+
+    fn truncate(&self, len: usize) -> Self {
+        if len >= self.len {
+            return self.clone();
+        }
+        VecContext::from_shared(Rc::clone(&self.shared), len)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, var: usize) -> Option<Entry> {
+        let level = self.len.checked_sub(var + 1)?;
+        self.shared.borrow().entries.get(level).cloned()
+    }
+}
+
+/// A persistent context with constant time [`Context::append`] and
+/// logarithmic time lookup.
+///
+/// Entries are stored in a skew binary random access list: a list of
+/// complete binary trees, whose sizes are strictly increasing except that
+/// the first two may be equal. Contexts extended from a common prefix share
+/// its trees.
+pub struct TreeContext<T> {
+    spine: Option<Rc<Spine<T>>>,
+    len: usize,
+}
+
+struct Spine<T> {
+    /// The number of entries in `tree`.
+    size: usize,
+    tree: Rc<Tree<T>>,
+    rest: Option<Rc<Spine<T>>>,
+}
+
+/// A complete binary tree, holding entries in pre-order from the most
+/// recent.
+enum Tree<T> {
+    Leaf(T),
+    Node(T, Rc<Tree<T>>, Rc<Tree<T>>),
+}
+
+impl<T> Tree<T> {
+    fn get(&self, mut size: usize, mut index: usize) -> Option<&T> {
+        let mut tree = self;
+        loop {
+            match tree {
+                Tree::Leaf(entry) => return (index == 0).then_some(entry),
+                Tree::Node(entry, _, _) if index == 0 => return Some(entry),
+                Tree::Node(_, left, right) => {
+                    size /= 2;
+                    if index <= size {
+                        tree = left;
+                        index -= 1;
+                    } else {
+                        tree = right;
+                        index -= size + 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Clone for TreeContext<T> {
+    fn clone(&self) -> Self {
+        TreeContext {
+            spine: self.spine.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<Entry> Context<Entry> for TreeContext<Entry> where Entry: Clone {
+    fn iter(&self) -> impl Iterator<Item = Entry> {
+        let mut spine = self.spine.as_deref();
+        let mut trees: Vec<&Tree<Entry>> = Vec::new();
+        std::iter::from_fn(move || {
+            let tree = match trees.pop() {
+                Some(tree) => tree,
+                None => {
+                    let Spine { tree, rest, .. } = spine?;
+                    spine = rest.as_deref();
+                    tree
+                }
+            };
+            match tree {
+                Tree::Leaf(entry) => Some(entry.clone()),
+                Tree::Node(entry, left, right) => {
+                    trees.push(right);
+                    trees.push(left);
+                    Some(entry.clone())
+                }
+            }
+        })
+    }
+
+    fn append(&self, variable: Entry) -> Self {
+        let spine = match self.spine.as_deref() {
+            Some(Spine {
+                size,
+                tree: left,
+                rest: Some(rest),
+            }) if *size == rest.size => Spine {
+                size: 2 * size + 1,
+                tree: Rc::new(Tree::Node(
+                    variable,
+                    Rc::clone(left),
+                    Rc::clone(&rest.tree),
+                )),
+                rest: rest.rest.clone(),
+            },
+            _ => Spine {
+                size: 1,
+                tree: Rc::new(Tree::Leaf(variable)),
+                rest: self.spine.clone(),
+            },
+        };
+        TreeContext {
+            spine: Some(Rc::new(spine)),
+            len: self.len + 1,
+        }
+    }
+
+    fn empty() -> Self {
+        TreeContext {
+            spine: None,
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, mut var: usize) -> Option<Entry> {
+        let mut spine = self.spine.as_deref();
+        while let Some(Spine { size, tree, rest }) = spine {
+            if var < *size {
+                return tree.get(*size, var).cloned();
+            }
+            var -= size;
+            spine = rest.as_deref();
+        }
+        None
+    }
 }
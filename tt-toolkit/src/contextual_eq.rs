@@ -1,8 +1,11 @@
 use std::marker::PhantomData;
 
-use crate::{Context, Evaluate, Never};
+use crate::{AlphaEquivalent, Context, Evaluate, Never, PartialContext};
 
-pub trait ContextualEq<Entry, Ctx>
+/// Equivalence of attributes in a context, and a signature `Sig` of global
+/// declarations, which is `()` for attributes of types without global
+/// references.
+pub trait ContextualEq<Entry, Ctx, Sig = ()>
 where
     Ctx: Context<Entry>,
 {
@@ -11,40 +14,76 @@ where
 
     fn equiv(
         ctx: &Ctx,
+        sig: &Sig,
         lhs: &Self,
         rhs: &Self,
     ) -> Result<Self::Check, Self::Error>;
 }
 
-pub trait AutoContextualEqImpl<Entry, Ctx: Context<Entry>, T: ?Sized> {
+pub trait AutoContextualEqImpl<Entry, Ctx: Context<Entry>, T: ?Sized, Sig = ()>
+{
     type Check;
     type Error;
 
-    fn equiv(ctx: &Ctx, lhs: &T, rhs: &T) -> Result<Self::Check, Self::Error>;
+    fn equiv(
+        ctx: &Ctx,
+        sig: &Sig,
+        lhs: &T,
+        rhs: &T,
+    ) -> Result<Self::Check, Self::Error>;
 }
 
 pub struct SyntacticEq<T: PartialEq>(PhantomData<T>);
 
+/// Compares terms up to the names of their bound variables, ignoring
+/// metadata, using a derived [`AlphaEquivalent`] implementation.
+pub struct AlphaEq<T: AlphaEquivalent>(PhantomData<T>);
+
 pub struct NormalFormEq<T>(PhantomData<T>)
 where
     T: Evaluate,
     T::Target: PartialEq;
 
-impl<Entry, Ctx: Context<Entry>, T: PartialEq>
-    AutoContextualEqImpl<Entry, Ctx, T> for SyntacticEq<T>
+impl<Entry, Ctx: Context<Entry>, T: PartialEq, Sig>
+    AutoContextualEqImpl<Entry, Ctx, T, Sig> for SyntacticEq<T>
 {
     type Check = bool;
     type Error = Never;
 
-    fn equiv(_ctx: &Ctx, lhs: &T, rhs: &T) -> Result<Self::Check, Self::Error> {
+    fn equiv(
+        _ctx: &Ctx,
+        _sig: &Sig,
+        lhs: &T,
+        rhs: &T,
+    ) -> Result<Self::Check, Self::Error> {
         Ok(*lhs == *rhs)
     }
 }
 
-impl<T: Evaluate> AutoContextualEqImpl<Option<T::Target>, T::Context, T>
+impl<Entry, Ctx: Context<Entry>, T: AlphaEquivalent, Sig>
+    AutoContextualEqImpl<Entry, Ctx, T, Sig> for AlphaEq<T>
+{
+    type Check = bool;
+    type Error = Never;
+
+    fn equiv(
+        _ctx: &Ctx,
+        _sig: &Sig,
+        lhs: &T,
+        rhs: &T,
+    ) -> Result<Self::Check, Self::Error> {
+        Ok(lhs.alpha_eq(rhs))
+    }
+}
+
+/// Compares the normal forms of terms, unfolding the global definitions of
+/// the signature they are evaluated with.
+impl<T: Evaluate>
+    AutoContextualEqImpl<Option<T::Target>, T::Context, T, T::Signature>
     for NormalFormEq<T>
 where
     T::Target: PartialEq,
+    T::Context: PartialContext<T::Target>,
 {
     type Check = bool;
 
@@ -52,30 +91,37 @@ where
 
     fn equiv(
         ctx: &T::Context,
+        sig: &T::Signature,
         lhs: &T,
         rhs: &T,
     ) -> Result<Self::Check, Self::Error> {
-        Ok(lhs.evaluate(ctx, true)? == rhs.evaluate(ctx, true)?)
+        Ok(lhs.evaluate(ctx, sig, true)? == rhs.evaluate(ctx, sig, true)?)
     }
 }
 
-pub trait AutoContextualEq<Entry, Ctx: Context<Entry>> {
-    type Impl: AutoContextualEqImpl<Entry, Ctx, Self>;
+/// Selects the [`AutoContextualEqImpl`] which compares a type's values in
+/// the context `Ctx` and signature `Sig`.
+///
+/// Types compared in contexts with a signature need an implementation for
+/// its type, such as one for every `Sig`.
+pub trait AutoContextualEq<Entry, Ctx: Context<Entry>, Sig = ()> {
+    type Impl: AutoContextualEqImpl<Entry, Ctx, Self, Sig>;
 }
 
-impl<Entry, Ctx: Context<Entry>, T> ContextualEq<Entry, Ctx> for T
+impl<Entry, Ctx: Context<Entry>, Sig, T> ContextualEq<Entry, Ctx, Sig> for T
 where
-    T: AutoContextualEq<Entry, Ctx>,
+    T: AutoContextualEq<Entry, Ctx, Sig>,
 {
-    type Check = <T::Impl as AutoContextualEqImpl<Entry, Ctx, T>>::Check;
-    type Error = <T::Impl as AutoContextualEqImpl<Entry, Ctx, T>>::Error;
+    type Check = <T::Impl as AutoContextualEqImpl<Entry, Ctx, T, Sig>>::Check;
+    type Error = <T::Impl as AutoContextualEqImpl<Entry, Ctx, T, Sig>>::Error;
 
     fn equiv(
         ctx: &Ctx,
+        sig: &Sig,
         lhs: &Self,
         rhs: &Self,
     ) -> Result<Self::Check, Self::Error> {
-        T::Impl::equiv(ctx, lhs, rhs)
+        T::Impl::equiv(ctx, sig, lhs, rhs)
     }
 }
 
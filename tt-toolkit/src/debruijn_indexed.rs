@@ -1,11 +1,346 @@
+use std::any::{TypeId, type_name};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::ControlFlow;
+
+use thiserror::Error;
+
+use crate::Renaming;
+
+/// The error returned when removing a variable from the scope of a term in
+/// which it occurs.
+#[derive(Debug, Error, PartialEq, Clone)]
+#[error("Cannot remove variable {var} from scope, as it occurs in the term")]
+pub struct StrengthenError {
+    /// The de Bruijn index of the variable which occurs in the term.
+    pub var: usize,
+}
+
+/// Types which can hold a de Bruijn index or level, in fields annotated with
+/// `#[var_index]` or `#[var_level]`.
+///
+/// This is implemented for `usize`, `u32` and `u16`, and may be implemented
+/// for newtypes over these to make variables more compact, or to prevent
+/// different kinds of variable from being mixed up.
+///
+/// # Panics
+/// The derived implementations convert every index they produce with
+/// [`from_index`](VarIndex::from_index), so shifting or substituting a
+/// variable to an index which doesn't fit in a `u32` or `u16` panics.
+#[diagnostic::on_unimplemented(
+    message = "Type `{Self}` cannot be used as a de Bruijn variable",
+    note = "Fields annotated with #[var_index] or #[var_level] should have a type implementing `VarIndex`, such as `usize`",
+    note = "Maybe you meant to use the #[variable] annotation instead"
+)]
+pub trait VarIndex: Copy {
+    fn to_index(self) -> usize;
+
+    /// Panics if `index` can't be represented by this type.
+    fn from_index(index: usize) -> Self;
+
+    /// Adds `amount` to the index.
+    ///
+    /// Panics if the result can't be represented by this type.
+    fn shift(self, amount: usize) -> Self {
+        Self::from_index(self.to_index() + amount)
+    }
+}
+
+impl VarIndex for usize {
+    fn to_index(self) -> usize {
+        self
+    }
+
+    fn from_index(index: usize) -> Self {
+        index
+    }
+}
+
+macro_rules! var_index_impl {
+    ($ty:ty) => {
+        impl VarIndex for $ty {
+            fn to_index(self) -> usize {
+                self as usize
+            }
+
+            fn from_index(index: usize) -> Self {
+                <$ty>::try_from(index).unwrap_or_else(|_| {
+                    panic!(
+                        "de Bruijn variable {index} is too large for type `{}`",
+                        stringify!($ty)
+                    )
+                })
+            }
+        }
+    };
+}
+
+var_index_impl!(u32);
+var_index_impl!(u16);
+
+/// Whether a variable field holds a de Bruijn index or a de Bruijn level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    /// The variable counts binders outwards from its own position, and is
+    /// annotated with `#[var_index]`.
+    Index,
+    /// The variable counts binders inwards from the root of the context, and
+    /// is annotated with `#[var_level]`.
+    Level,
+}
+
+/// Identifies one of the index spaces of a syntax with several sorts of
+/// variable, as named by `#[var_index(sort = T)]` and `#[binding(sort = T)]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl Sort {
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        Sort {
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Whether variables of sorts `a` and `b` share an index space, where a
+    /// sort of `None` stands for every sort.
+    pub fn overlap(a: Option<Sort>, b: Option<Sort>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        }
+    }
+}
+
+/// The de Bruijn level of the variable with index `index`, in a scope of
+/// `depth` variables, or `None` if the variable is not in scope.
+pub fn index_to_level(index: usize, depth: usize) -> Option<usize> {
+    depth.checked_sub(index)?.checked_sub(1)
+}
+
+/// The de Bruijn index of the variable with level `level`, in a scope of
+/// `depth` variables, or `None` if the variable is not in scope.
+pub fn level_to_index(level: usize, depth: usize) -> Option<usize> {
+    depth.checked_sub(level)?.checked_sub(1)
+}
+
 /// Syntax nodes which have free variables represented by DeBruijn indices
 pub trait DeBruijnIndexed: Sized {
-    fn map_indices_from<F>(&self, start: usize, map_fn: F) -> Self
+    /// Apply `map_fn` to the de Bruijn indices of sort `sort` in the term
+    /// which are at least `start`, where `start` is raised by the number of
+    /// variables of that sort bound between the root of the term and the
+    /// index.
+    /// A `sort` of `None` stands for every sort, so treats all of the
+    /// variables in the term as sharing one index space.
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
     where
         F: Fn(usize) -> usize + Clone;
 
+    fn map_indices_from<F>(&self, start: usize, map_fn: F) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.map_sort_indices_from(None, start, map_fn)
+    }
+
+    /// Like [`DeBruijnIndexed::map_sort_indices_from`], but returns `None` if
+    /// `map_fn` doesn't change any of the indices, so that unchanged terms are
+    /// not rebuilt. Shared nodes such as `Rc` use this to keep the same
+    /// pointer when nothing below them changes.
+    ///
+    /// The default implementation always returns `Some`, so a term which
+    /// doesn't override it is rebuilt whether or not it changes. The derived
+    /// implementations, and those for containers such as `Box` and `Vec`,
+    /// only rebuild the parts of the term which change.
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        Some(self.map_sort_indices_from(sort, start, map_fn))
+    }
+
+    /// A copy of the term, which
+    /// [`DeBruijnIndexed::map_changed_sort_indices_from`] uses for the parts
+    /// of a changed term which are unchanged. Unlike `Clone`, shared nodes
+    /// such as `Rc` copy the pointer, and terms which can't be cloned are
+    /// copied by mapping their indices to themselves, which is what the
+    /// default implementation does.
+    fn clone_unchanged(&self) -> Self {
+        self.map_sort_indices_from(None, 0, |index| index)
+    }
+
+    /// Like [`DeBruijnIndexed::map_sort_indices_from`], but modifies the term
+    /// in place. The derived implementations, and those for containers such
+    /// as `Box` and `Vec`, reuse the allocations of the term rather than
+    /// building a copy of it.
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        *self = self.map_sort_indices_from(sort, start, map_fn);
+    }
+
+    /// Like [`DeBruijnIndexed::map_indices_from`], but modifies the term in
+    /// place.
+    fn map_indices_in_place_from<F>(&mut self, start: usize, map_fn: F)
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.map_sort_indices_in_place_from(None, start, map_fn)
+    }
+
     fn get_var(&self) -> Option<usize>;
 
+    /// Replace each variable in the term with the result of `map_fn`, which
+    /// is passed the kind of the variable, its value, and the number of
+    /// variables in scope at its position.
+    /// The term itself is assumed to be in a scope of `depth` variables.
+    ///
+    /// Unlike [`DeBruijnIndexed::map_indices_from`] this visits both
+    /// `#[var_index]` and `#[var_level]` fields, including bound variables.
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone;
+
+    /// Call `visit_fn` on each de Bruijn index of sort `sort` in the term
+    /// which is at least `start`, where `start` is raised by the number of
+    /// variables of that sort bound between the root of the term and the
+    /// index. These are exactly the indices which
+    /// [`DeBruijnIndexed::map_sort_indices_from`] would pass to its map
+    /// function.
+    /// Indices are passed to `visit_fn` relative to the (raised) value of
+    /// `start`, so that with a `start` of zero they are the free variables of
+    /// the term as seen from outside of it.
+    ///
+    /// The traversal stops early if `visit_fn` returns `ControlFlow::Break`.
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>;
+
+    /// Call `visit_fn` on each de Bruijn index in the term which is at least
+    /// `start`, treating every sort of variable as sharing one index space.
+    fn visit_free_vars_from<F>(
+        &self,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.visit_sort_free_vars_from(None, start, visit_fn)
+    }
+
+    /// Whether [`DeBruijnIndexed::map_sort_indices_from`] would change any
+    /// index in the term, that is whether it has any free variables of sort
+    /// `sort` which are at least `start`.
+    fn has_sort_indices_from(&self, sort: Option<Sort>, start: usize) -> bool {
+        self.visit_sort_free_vars_from(sort, start, &mut |_| {
+            ControlFlow::Break(())
+        })
+        .is_break()
+    }
+
+    /// Call `visit_fn` on each free variable in the term.
+    fn visit_free_vars<F>(&self, mut visit_fn: F) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.visit_free_vars_from(0, &mut visit_fn)
+    }
+
+    /// The set of de Bruijn indices which occur free in the term.
+    fn free_vars(&self) -> BTreeSet<usize> {
+        let mut vars = BTreeSet::new();
+        let _ = self.visit_free_vars(|var| {
+            vars.insert(var);
+            ControlFlow::Continue(())
+        });
+        vars
+    }
+
+    /// Whether the variable with de Bruijn index `var` occurs free in the term.
+    fn occurs(&self, var: usize) -> bool {
+        self.visit_free_vars(|free_var| {
+            if free_var == var {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .is_break()
+    }
+
+    /// Whether the term has no free variables.
+    fn is_closed(&self) -> bool {
+        self.visit_free_vars(|_| ControlFlow::Break(()))
+            .is_continue()
+    }
+
+    /// The largest de Bruijn index which occurs free in the term, or `None`
+    /// if the term is closed.
+    fn max_free_index(&self) -> Option<usize> {
+        self.max_sort_free_index_from(None, 0)
+    }
+
+    /// The largest of the indices which
+    /// [`DeBruijnIndexed::visit_sort_free_vars_from`] would visit, or `None`
+    /// if there are none.
+    ///
+    /// The default implementation visits each of them. The derived
+    /// implementations, and those for containers such as `Box` and `Vec`,
+    /// take the largest of the bounds of their children instead, so that an
+    /// [`Interned`](crate::Interned) child only reports the bound it cached
+    /// when it was interned.
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        let mut max = None;
+        let _ = self.visit_sort_free_vars_from(sort, start, &mut |var| {
+            max = max.max(Some(var));
+            ControlFlow::Continue(())
+        });
+        max
+    }
+
+    /// The number of [`Interned`](crate::Interned) nodes in the term,
+    /// counting shared nodes once for each time they occur.
+    ///
+    /// The default implementation returns zero, so a type which doesn't
+    /// override it hides any interned nodes inside of it. The derived
+    /// implementations, and those for containers such as `Box` and `Vec`,
+    /// add up the sizes of their children.
+    fn interned_size(&self) -> usize {
+        0
+    }
+
     fn map_indices<F>(&self, map_fn: F) -> Self
     where
         F: Fn(usize) -> usize + Clone,
@@ -13,6 +348,81 @@ pub trait DeBruijnIndexed: Sized {
         self.map_indices_from(0, map_fn)
     }
 
+    /// Like [`DeBruijnIndexed::map_indices_from`], but with a map function
+    /// which may fail. The first error returned by `map_fn` is returned.
+    ///
+    /// `map_fn` is not called again once it has failed, but the rest of the
+    /// term is still traversed before the error is returned.
+    fn try_map_indices_from<F, E>(
+        &self,
+        start: usize,
+        map_fn: F,
+    ) -> Result<Self, E>
+    where
+        F: FnMut(usize) -> Result<usize, E>,
+    {
+        let map_fn = RefCell::new(map_fn);
+        let error = RefCell::new(None);
+        let mapped = self.map_indices_from(start, |index| {
+            let mut error = error.borrow_mut();
+            if error.is_some() {
+                return index;
+            }
+            (map_fn.borrow_mut())(index).unwrap_or_else(|e| {
+                *error = Some(e);
+                index
+            })
+        });
+        match error.into_inner() {
+            Some(e) => Err(e),
+            None => Ok(mapped),
+        }
+    }
+
+    /// Rename the free variables of the term by `renaming`, lifting it under
+    /// any binders.
+    fn rename(&self, renaming: &Renaming) -> Self {
+        self.map_vars_at_depth(0, |kind, var, depth| match kind {
+            VarKind::Index if var >= depth => {
+                renaming.apply(var - depth) + depth
+            }
+            _ => var,
+        })
+    }
+
+    /// Convert the de Bruijn indices in `#[var_index]` fields of the term into
+    /// de Bruijn levels, for a term in a scope of `depth` variables.
+    /// The levels are stored back into the same fields.
+    /// Free variables which are not in the scope are left unchanged.
+    fn indices_to_levels(&self, depth: usize) -> Self {
+        self.map_vars_at_depth(depth, |kind, var, depth| match kind {
+            VarKind::Index => index_to_level(var, depth).unwrap_or(var),
+            VarKind::Level => var,
+        })
+    }
+
+    /// Convert the de Bruijn levels in `#[var_level]` fields of the term into
+    /// de Bruijn indices, for a term in a scope of `depth` variables.
+    /// The indices are stored back into the same fields.
+    /// Levels which are not in the scope are left unchanged.
+    fn levels_to_indices(&self, depth: usize) -> Self {
+        self.map_vars_at_depth(depth, |kind, var, depth| match kind {
+            VarKind::Index => var,
+            VarKind::Level => level_to_index(var, depth).unwrap_or(var),
+        })
+    }
+
+    /// Remove the variable with de Bruijn index `var` from the scope of the
+    /// term, by decrementing the indices of the variables bound outside of it.
+    /// This fails if `var` occurs in the term.
+    fn try_strengthen(&self, var: usize) -> Result<Self, StrengthenError> {
+        if self.occurs(var) {
+            Err(StrengthenError { var })
+        } else {
+            Ok(self.map_indices_from(var + 1, |i| i - 1))
+        }
+    }
+
     // type VarType: std::cmp::Ord;
     /// Increment de Bruijn indices in the term which are at least equal to `start`
     /// by `amount`.
@@ -27,18 +437,98 @@ pub trait DeBruijnIndexed: Sized {
         self.increment_indices_from_by(0, amount)
     }
 
+    /// Increment de Bruijn indices of sort `sort` in the term by `amount`.
+    fn increment_sort_indices_by(
+        &self,
+        sort: Option<Sort>,
+        amount: usize,
+    ) -> Self {
+        self.map_sort_indices_from(sort, 0, |i| i + amount)
+    }
+
     /// Increment de Bruijn indices in the term by one.
     fn increment_indices(&self) -> Self {
         self.increment_indices_by(1)
     }
+
+    /// Increment de Bruijn indices in the term by `amount`, modifying the term
+    /// in place.
+    fn increment_indices_in_place_by(&mut self, amount: usize) {
+        self.map_indices_in_place_from(0, |i| i + amount)
+    }
 }
 
 impl<T: DeBruijnIndexed> DeBruijnIndexed for Box<T> {
-    fn map_indices_from<F>(&self, start: usize, map_fn: F) -> Self
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        (**self).map_sort_indices_from(sort, start, map_fn).into()
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
     where
         F: Fn(usize) -> usize + Clone,
     {
-        (**self).map_indices_from(start, map_fn).into()
+        (**self)
+            .map_changed_sort_indices_from(sort, start, map_fn)
+            .map(Box::new)
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        Box::new((**self).clone_unchanged())
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        (**self).map_sort_indices_in_place_from(sort, start, map_fn)
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        (**self).map_vars_at_depth(depth, map_fn).into()
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        (**self).visit_sort_free_vars_from(sort, start, visit_fn)
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        (**self).max_sort_free_index_from(sort, start)
+    }
+
+    fn interned_size(&self) -> usize {
+        (**self).interned_size()
     }
 
     fn get_var(&self) -> Option<usize> {
@@ -47,11 +537,89 @@ impl<T: DeBruijnIndexed> DeBruijnIndexed for Box<T> {
 }
 
 impl<T: DeBruijnIndexed> DeBruijnIndexed for std::rc::Rc<T> {
-    fn map_indices_from<F>(&self, start: usize, map_fn: F) -> Self
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        // Unchanged subterms keep sharing the same allocation.
+        self.map_changed_sort_indices_from(sort, start, map_fn)
+            .unwrap_or_else(|| std::rc::Rc::clone(self))
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
     where
         F: Fn(usize) -> usize + Clone,
     {
-        (**self).map_indices_from(start, map_fn).into()
+        (**self)
+            .map_changed_sort_indices_from(sort, start, map_fn)
+            .map(std::rc::Rc::new)
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        std::rc::Rc::clone(self)
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        match std::rc::Rc::get_mut(self) {
+            Some(inner) => {
+                inner.map_sort_indices_in_place_from(sort, start, map_fn)
+            }
+            None => {
+                if let Some(mapped) =
+                    self.map_changed_sort_indices_from(sort, start, map_fn)
+                {
+                    *self = mapped;
+                }
+            }
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        (**self).map_vars_at_depth(depth, map_fn).into()
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        (**self).visit_sort_free_vars_from(sort, start, visit_fn)
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        (**self).max_sort_free_index_from(sort, start)
+    }
+
+    fn interned_size(&self) -> usize {
+        (**self).interned_size()
     }
 
     fn get_var(&self) -> Option<usize> {
@@ -60,15 +628,606 @@ impl<T: DeBruijnIndexed> DeBruijnIndexed for std::rc::Rc<T> {
 }
 
 impl<T: DeBruijnIndexed> DeBruijnIndexed for Option<T> {
-    fn map_indices_from<F>(&self, start: usize, map_fn: F) -> Self
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
     where
         F: Fn(usize) -> usize + Clone,
     {
         self.as_ref()
-            .map(|expr| expr.map_indices_from(start, map_fn))
+            .map(|expr| expr.map_sort_indices_from(sort, start, map_fn))
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.as_ref()?
+            .map_changed_sort_indices_from(sort, start, map_fn)
+            .map(Some)
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        self.as_ref().map(T::clone_unchanged)
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        if let Some(expr) = self {
+            expr.map_sort_indices_in_place_from(sort, start, map_fn)
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        self.as_ref()
+            .map(|expr| expr.map_vars_at_depth(depth, map_fn))
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.iter().try_for_each(|expr| {
+            expr.visit_sort_free_vars_from(sort, start, visit_fn)
+        })
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        self.as_ref()?.max_sort_free_index_from(sort, start)
+    }
+
+    fn interned_size(&self) -> usize {
+        self.as_ref().map_or(0, T::interned_size)
     }
 
     fn get_var(&self) -> Option<usize> {
         self.as_ref().and_then(|expr| expr.get_var())
     }
 }
+
+impl<T: DeBruijnIndexed> DeBruijnIndexed for std::sync::Arc<T> {
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        // Unchanged subterms keep sharing the same allocation.
+        self.map_changed_sort_indices_from(sort, start, map_fn)
+            .unwrap_or_else(|| std::sync::Arc::clone(self))
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        (**self)
+            .map_changed_sort_indices_from(sort, start, map_fn)
+            .map(std::sync::Arc::new)
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        std::sync::Arc::clone(self)
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        match std::sync::Arc::get_mut(self) {
+            Some(inner) => {
+                inner.map_sort_indices_in_place_from(sort, start, map_fn)
+            }
+            None => {
+                if let Some(mapped) =
+                    self.map_changed_sort_indices_from(sort, start, map_fn)
+                {
+                    *self = mapped;
+                }
+            }
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        (**self).map_vars_at_depth(depth, map_fn).into()
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        (**self).visit_sort_free_vars_from(sort, start, visit_fn)
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        (**self).max_sort_free_index_from(sort, start)
+    }
+
+    fn interned_size(&self) -> usize {
+        (**self).interned_size()
+    }
+
+    fn get_var(&self) -> Option<usize> {
+        DeBruijnIndexed::get_var(&**self)
+    }
+}
+
+impl<T: DeBruijnIndexed> DeBruijnIndexed for Vec<T> {
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.iter()
+            .map(|expr| expr.map_sort_indices_from(sort, start, map_fn.clone()))
+            .collect()
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        let changed: Vec<_> = self
+            .iter()
+            .map(|expr| {
+                expr.map_changed_sort_indices_from(sort, start, map_fn.clone())
+            })
+            .collect();
+        if changed.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(
+            changed
+                .into_iter()
+                .zip(self)
+                .map(|(changed, expr)| {
+                    changed.unwrap_or_else(|| expr.clone_unchanged())
+                })
+                .collect(),
+        )
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        self.iter().map(T::clone_unchanged).collect()
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        for expr in self.iter_mut() {
+            expr.map_sort_indices_in_place_from(sort, start, map_fn.clone())
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        self.iter()
+            .map(|expr| expr.map_vars_at_depth(depth, map_fn.clone()))
+            .collect()
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.iter().try_for_each(|expr| {
+            expr.visit_sort_free_vars_from(sort, start, visit_fn)
+        })
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        self.iter()
+            .filter_map(|expr| expr.max_sort_free_index_from(sort, start))
+            .max()
+    }
+
+    fn interned_size(&self) -> usize {
+        self.iter().map(T::interned_size).sum()
+    }
+
+    fn get_var(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<T: DeBruijnIndexed, const N: usize> DeBruijnIndexed for [T; N] {
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.each_ref()
+            .map(|expr| expr.map_sort_indices_from(sort, start, map_fn.clone()))
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        let mut changed = self.each_ref().map(|expr| {
+            expr.map_changed_sort_indices_from(sort, start, map_fn.clone())
+        });
+        if changed.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(std::array::from_fn(|i| {
+            changed[i]
+                .take()
+                .unwrap_or_else(|| self[i].clone_unchanged())
+        }))
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        self.each_ref().map(T::clone_unchanged)
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        for expr in self.iter_mut() {
+            expr.map_sort_indices_in_place_from(sort, start, map_fn.clone())
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        self.each_ref()
+            .map(|expr| expr.map_vars_at_depth(depth, map_fn.clone()))
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.iter().try_for_each(|expr| {
+            expr.visit_sort_free_vars_from(sort, start, visit_fn)
+        })
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        self.iter()
+            .filter_map(|expr| expr.max_sort_free_index_from(sort, start))
+            .max()
+    }
+
+    fn interned_size(&self) -> usize {
+        self.iter().map(T::interned_size).sum()
+    }
+
+    fn get_var(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<K, T> DeBruijnIndexed for BTreeMap<K, T>
+where
+    K: Ord + Clone,
+    T: DeBruijnIndexed,
+{
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.iter()
+            .map(|(key, expr)| {
+                (
+                    key.clone(),
+                    expr.map_sort_indices_from(sort, start, map_fn.clone()),
+                )
+            })
+            .collect()
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        let changed: Vec<_> = self
+            .values()
+            .map(|expr| {
+                expr.map_changed_sort_indices_from(sort, start, map_fn.clone())
+            })
+            .collect();
+        if changed.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(
+            changed
+                .into_iter()
+                .zip(self)
+                .map(|(changed, (key, expr))| {
+                    let expr =
+                        changed.unwrap_or_else(|| expr.clone_unchanged());
+                    (key.clone(), expr)
+                })
+                .collect(),
+        )
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        self.iter()
+            .map(|(key, expr)| (key.clone(), expr.clone_unchanged()))
+            .collect()
+    }
+
+    fn map_sort_indices_in_place_from<F>(
+        &mut self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) where
+        F: Fn(usize) -> usize + Clone,
+    {
+        for expr in self.values_mut() {
+            expr.map_sort_indices_in_place_from(sort, start, map_fn.clone())
+        }
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        self.iter()
+            .map(|(key, expr)| {
+                (key.clone(), expr.map_vars_at_depth(depth, map_fn.clone()))
+            })
+            .collect()
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        self.values().try_for_each(|expr| {
+            expr.visit_sort_free_vars_from(sort, start, visit_fn)
+        })
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        self.values()
+            .filter_map(|expr| expr.max_sort_free_index_from(sort, start))
+            .max()
+    }
+
+    fn interned_size(&self) -> usize {
+        self.values().map(T::interned_size).sum()
+    }
+
+    fn get_var(&self) -> Option<usize> {
+        None
+    }
+}
+
+macro_rules! tuple_impl {
+    ($($param:ident),*) => {
+        impl<$($param: DeBruijnIndexed),*> DeBruijnIndexed for ($($param,)*) {
+            fn map_sort_indices_from<F>(
+                &self,
+                sort: Option<Sort>,
+                start: usize,
+                map_fn: F,
+            ) -> Self
+            where
+                F: Fn(usize) -> usize + Clone,
+            {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                ($($param.map_sort_indices_from(sort, start, map_fn.clone()),)*)
+            }
+
+            fn map_changed_sort_indices_from<F>(
+                &self,
+                sort: Option<Sort>,
+                start: usize,
+                map_fn: F,
+            ) -> Option<Self>
+            where
+                F: Fn(usize) -> usize + Clone,
+            {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                #[allow(non_snake_case)]
+                let ($($param,)*) = ($((
+                    $param,
+                    $param.map_changed_sort_indices_from(sort, start, map_fn.clone()),
+                ),)*);
+                if [$($param.1.is_none()),*].into_iter().all(|none| none) {
+                    return None;
+                }
+                Some(($($param.1.unwrap_or_else(|| $param.0.clone_unchanged()),)*))
+            }
+
+            fn clone_unchanged(&self) -> Self {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                ($($param.clone_unchanged(),)*)
+            }
+
+            fn map_sort_indices_in_place_from<F>(
+                &mut self,
+                sort: Option<Sort>,
+                start: usize,
+                map_fn: F,
+            ) where
+                F: Fn(usize) -> usize + Clone,
+            {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                $($param.map_sort_indices_in_place_from(sort, start, map_fn.clone());)*
+            }
+
+            fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+            where
+                F: Fn(VarKind, usize, usize) -> usize + Clone,
+            {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                ($($param.map_vars_at_depth(depth, map_fn.clone()),)*)
+            }
+
+            fn visit_sort_free_vars_from<F>(
+                &self,
+                sort: Option<Sort>,
+                start: usize,
+                visit_fn: &mut F,
+            ) -> ControlFlow<()>
+            where
+                F: FnMut(usize) -> ControlFlow<()>,
+            {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                $($param.visit_sort_free_vars_from(sort, start, visit_fn)?;)*
+                ControlFlow::Continue(())
+            }
+
+                    fn max_sort_free_index_from(
+                &self,
+                sort: Option<Sort>,
+                start: usize,
+            ) -> Option<usize> {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                [$($param.max_sort_free_index_from(sort, start)),*]
+                    .into_iter()
+                    .flatten()
+                    .max()
+            }
+
+            fn interned_size(&self) -> usize {
+                #[allow(non_snake_case)]
+                let ($($param,)*) = self;
+                0 $(+ $param.interned_size())*
+            }
+
+    fn get_var(&self) -> Option<usize> {
+                None
+            }
+        }
+    };
+}
+
+tuple_impl!(T0);
+tuple_impl!(T0, T1);
+tuple_impl!(T0, T1, T2);
+tuple_impl!(T0, T1, T2, T3);
+tuple_impl!(T0, T1, T2, T3, T4);
+tuple_impl!(T0, T1, T2, T3, T4, T5);
@@ -1,49 +1,80 @@
+use std::collections::BTreeMap;
+
 use thiserror::Error;
 
-use crate::{Context, PartialContext, SubstError};
+use crate::{
+    Context, PartialContext, SubstError, UnboundGlobal, UnboundVariable,
+};
 
 #[derive(Debug, Error, PartialEq)]
 pub enum EvalError {
     #[error(transparent)]
     SubstError(#[from] SubstError),
-    #[error("Unbound variable index {}", 0)]
-    UnboundVariableIndex(usize),
+    #[error(transparent)]
+    UnboundVariable(#[from] UnboundVariable),
+    #[error(transparent)]
+    UnboundGlobal(#[from] UnboundGlobal),
 }
 
 pub trait Evaluate: Clone {
     type Target;
     type Error;
-    type Context: PartialContext<Self::Target>;
+    /// The context variables are evaluated in. This is usually a
+    /// [`PartialContext<Self::Target>`](PartialContext), but containers of
+    /// syntax nodes share the context of their elements, whose entries have a
+    /// different type.
+    type Context;
+    /// The signature of the global declarations which terms may refer to,
+    /// whose definitions are unfolded during evaluation. Types without
+    /// global references use `()`.
+    type Signature;
 
     fn evaluate(
         &self,
         ctx: &Self::Context,
+        sig: &Self::Signature,
         under_binders: bool,
     ) -> Result<Self::Target, Self::Error>;
 
     fn normalise(
         &self,
         ctx: &Self::Context,
+        sig: &Self::Signature,
         under_binders: bool,
     ) -> Result<Self, Self::Error>
     where
         Self::Target: Into<Self>,
     {
-        self.evaluate(ctx, under_binders).map(Into::into)
+        self.evaluate(ctx, sig, under_binders).map(Into::into)
     }
 
+    /// Evaluate the term in an empty context and signature.
     fn evaluate_closed(
         &self,
         under_binders: bool,
-    ) -> Result<Self::Target, Self::Error> {
-        self.evaluate(&Self::Context::empty(), under_binders)
+    ) -> Result<Self::Target, Self::Error>
+    where
+        Self::Context: PartialContext<Self::Target>,
+        Self::Signature: Default,
+    {
+        self.evaluate(
+            &Self::Context::empty(),
+            &Default::default(),
+            under_binders,
+        )
     }
 
     fn normalise_closed(&self, under_binders: bool) -> Result<Self, Self::Error>
     where
         Self::Target: Into<Self>,
+        Self::Context: PartialContext<Self::Target>,
+        Self::Signature: Default,
     {
-        self.normalise(&Self::Context::empty(), under_binders)
+        self.normalise(
+            &Self::Context::empty(),
+            &Default::default(),
+            under_binders,
+        )
     }
 }
 
@@ -54,12 +85,15 @@ impl<T: Evaluate> Evaluate for Box<T> {
 
     type Context = T::Context;
 
+    type Signature = T::Signature;
+
     fn evaluate(
         &self,
         ctx: &Self::Context,
+        sig: &Self::Signature,
         under_binders: bool,
     ) -> Result<Self::Target, Self::Error> {
-        (**self).evaluate(ctx, under_binders)
+        (**self).evaluate(ctx, sig, under_binders)
     }
 }
 
@@ -70,11 +104,170 @@ impl<T: Evaluate> Evaluate for std::rc::Rc<T> {
 
     type Context = T::Context;
 
+    type Signature = T::Signature;
+
+    fn evaluate(
+        &self,
+        ctx: &Self::Context,
+        sig: &Self::Signature,
+        under_binders: bool,
+    ) -> Result<Self::Target, Self::Error> {
+        (**self).evaluate(ctx, sig, under_binders)
+    }
+}
+
+impl<T: Evaluate> Evaluate for std::sync::Arc<T> {
+    type Target = T::Target;
+
+    type Error = T::Error;
+
+    type Context = T::Context;
+
+    type Signature = T::Signature;
+
+    fn evaluate(
+        &self,
+        ctx: &Self::Context,
+        sig: &Self::Signature,
+        under_binders: bool,
+    ) -> Result<Self::Target, Self::Error> {
+        (**self).evaluate(ctx, sig, under_binders)
+    }
+}
+
+impl<T: Evaluate> Evaluate for Option<T> {
+    type Target = Option<T::Target>;
+
+    type Error = T::Error;
+
+    type Context = T::Context;
+
+    type Signature = T::Signature;
+
+    fn evaluate(
+        &self,
+        ctx: &Self::Context,
+        sig: &Self::Signature,
+        under_binders: bool,
+    ) -> Result<Self::Target, Self::Error> {
+        self.as_ref()
+            .map(|expr| expr.evaluate(ctx, sig, under_binders))
+            .transpose()
+    }
+}
+
+impl<T: Evaluate> Evaluate for Vec<T> {
+    type Target = Vec<T::Target>;
+
+    type Error = T::Error;
+
+    type Context = T::Context;
+
+    type Signature = T::Signature;
+
+    fn evaluate(
+        &self,
+        ctx: &Self::Context,
+        sig: &Self::Signature,
+        under_binders: bool,
+    ) -> Result<Self::Target, Self::Error> {
+        self.iter()
+            .map(|expr| expr.evaluate(ctx, sig, under_binders))
+            .collect()
+    }
+}
+
+impl<T: Evaluate, const N: usize> Evaluate for [T; N] {
+    type Target = [T::Target; N];
+
+    type Error = T::Error;
+
+    type Context = T::Context;
+
+    type Signature = T::Signature;
+
     fn evaluate(
         &self,
         ctx: &Self::Context,
+        sig: &Self::Signature,
         under_binders: bool,
     ) -> Result<Self::Target, Self::Error> {
-        (**self).evaluate(ctx, under_binders)
+        let evaluated = self
+            .iter()
+            .map(|expr| expr.evaluate(ctx, sig, under_binders))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(evaluated
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("Array length is preserved")))
     }
 }
+
+impl<K, T> Evaluate for BTreeMap<K, T>
+where
+    K: Ord + Clone,
+    T: Evaluate,
+{
+    type Target = BTreeMap<K, T::Target>;
+
+    type Error = T::Error;
+
+    type Context = T::Context;
+
+    type Signature = T::Signature;
+
+    fn evaluate(
+        &self,
+        ctx: &Self::Context,
+        sig: &Self::Signature,
+        under_binders: bool,
+    ) -> Result<Self::Target, Self::Error> {
+        self.iter()
+            .map(|(key, expr)| {
+                Ok((key.clone(), expr.evaluate(ctx, sig, under_binders)?))
+            })
+            .collect()
+    }
+}
+
+macro_rules! tuple_impl {
+    ($first:ident $(, $param:ident)*) => {
+        impl<$first, $($param),*> Evaluate for ($first, $($param,)*)
+        where
+            $first: Evaluate,
+            $($param: Evaluate<
+                Error = $first::Error,
+                Context = $first::Context,
+                Signature = $first::Signature,
+            >,)*
+        {
+            type Target = ($first::Target, $($param::Target,)*);
+
+            type Error = $first::Error;
+
+            type Context = $first::Context;
+
+            type Signature = $first::Signature;
+
+            fn evaluate(
+                &self,
+                ctx: &Self::Context,
+                sig: &Self::Signature,
+                under_binders: bool,
+            ) -> Result<Self::Target, Self::Error> {
+                #[allow(non_snake_case)]
+                let ($first, $($param,)*) = self;
+                Ok((
+                    $first.evaluate(ctx, sig, under_binders)?,
+                    $($param.evaluate(ctx, sig, under_binders)?,)*
+                ))
+            }
+        }
+    };
+}
+
+tuple_impl!(T0);
+tuple_impl!(T0, T1);
+tuple_impl!(T0, T1, T2);
+tuple_impl!(T0, T1, T2, T3);
+tuple_impl!(T0, T1, T2, T3, T4);
+tuple_impl!(T0, T1, T2, T3, T4, T5);
@@ -0,0 +1,399 @@
+use std::any::{Any, TypeId};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::{ControlFlow, Deref};
+use std::rc::{Rc, Weak};
+
+use crate::{
+    DeBruijnIndexed, Evaluate, Sort, Subst, Substitute, SubstituteInPlace,
+    VarKind,
+};
+
+/// A hash-consed syntax node.
+///
+/// Every `Interned<T>` holding an equal value shares the same allocation, so
+/// equality and hashing compare pointers and take constant time. Since the
+/// children of a node are compared by pointer too, interning a new node only
+/// needs to hash and compare the node itself.
+///
+/// `Interned<T>` may be used as a field wrapper in the same way as `Box` or
+/// `Rc`, with the `DeBruijnIndexed`, `Substitute` and `Evaluate` derives.
+/// Derived `PartialEq` implementations of nodes with interned fields then
+/// compare the fields by pointer, which makes checking conversion by
+/// [`SyntacticEq`](crate::SyntacticEq) cheap.
+///
+/// Each node caches its size and its largest free index, which are computed
+/// from those of its children when it is interned, so that shifting or
+/// substituting into a subterm which has no affected variables returns the
+/// same node without traversing it.
+///
+/// Nodes are interned in a table local to the current thread, and are freed
+/// when the last handle to them is dropped.
+pub struct Interned<T>(Rc<Node<T>>);
+
+struct Node<T> {
+    value: T,
+    hash: u64,
+    size: usize,
+    /// The largest free index, treating all sorts as sharing one index space.
+    max_free_index: Option<usize>,
+    /// The largest free index of each sort which has been queried, computed
+    /// when it is first needed.
+    max_sort_free_indices: RefCell<Vec<(Sort, Option<usize>)>>,
+}
+
+struct Table<T> {
+    buckets: HashMap<u64, Vec<Weak<Node<T>>>>,
+    len: usize,
+    /// The number of entries at which the table is next cleared of nodes
+    /// which have been dropped.
+    sweep_at: usize,
+}
+
+const MIN_SWEEP: usize = 1024;
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Table {
+            buckets: HashMap::new(),
+            len: 0,
+            sweep_at: MIN_SWEEP,
+        }
+    }
+}
+
+impl<T> Table<T> {
+    fn sweep(&mut self) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|node| node.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.len = self.buckets.values().map(Vec::len).sum();
+        self.sweep_at = (2 * self.len).max(MIN_SWEEP);
+    }
+}
+
+thread_local! {
+    static TABLES: RefCell<HashMap<TypeId, Box<dyn Any>>> =
+        RefCell::new(HashMap::new());
+}
+
+impl<T: DeBruijnIndexed + Eq + Hash + 'static> Interned<T> {
+    /// Intern `value`, returning the existing node if an equal value has
+    /// already been interned.
+    pub fn new(value: T) -> Self {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        TABLES.with_borrow_mut(|tables| {
+            let table = tables
+                .entry(TypeId::of::<T>())
+                .or_insert_with(|| Box::new(Table::<T>::default()))
+                .downcast_mut::<Table<T>>()
+                .expect("Interning tables are indexed by their node type");
+
+            let bucket = table.buckets.entry(hash).or_default();
+            let existing = bucket
+                .iter()
+                .filter_map(Weak::upgrade)
+                .find(|node| node.value == value);
+            if let Some(node) = existing {
+                return Interned(node);
+            }
+
+            let size = 1 + value.interned_size();
+            let max_free_index = value.max_sort_free_index_from(None, 0);
+            let node = Rc::new(Node {
+                value,
+                hash,
+                size,
+                max_free_index,
+                max_sort_free_indices: RefCell::new(Vec::new()),
+            });
+            bucket.push(Rc::downgrade(&node));
+            table.len += 1;
+            if table.len >= table.sweep_at {
+                table.sweep();
+            }
+            Interned(node)
+        })
+    }
+}
+
+impl<T> Interned<T> {
+    /// The number of interned nodes in the term, counting shared subterms
+    /// once for each time they occur.
+    pub fn size(&self) -> usize {
+        self.0.size
+    }
+
+    /// The hash of the value of the node, which is consistent with its
+    /// `Eq` implementation rather than the pointer of the node.
+    pub fn structural_hash(&self) -> u64 {
+        self.0.hash
+    }
+}
+
+impl<T: DeBruijnIndexed> Interned<T> {
+    /// The largest free de Bruijn index of sort `sort` in the term, as with
+    /// [`DeBruijnIndexed::max_free_index`]. The bound over all sorts is
+    /// computed when the node is interned; the bound of a particular sort is
+    /// computed from those of the children of the node when it is first
+    /// needed.
+    pub fn max_sort_free_index(&self, sort: Option<Sort>) -> Option<usize> {
+        let Some(sort) = sort else {
+            return self.0.max_free_index;
+        };
+        self.0.max_free_index?;
+
+        let cached = self
+            .0
+            .max_sort_free_indices
+            .borrow()
+            .iter()
+            .find(|(cached_sort, _)| *cached_sort == sort)
+            .map(|(_, max)| *max);
+        if let Some(max) = cached {
+            return max;
+        }
+
+        let max = self.0.value.max_sort_free_index_from(Some(sort), 0);
+        self.0.max_sort_free_indices.borrow_mut().push((sort, max));
+        max
+    }
+}
+
+impl<T: DeBruijnIndexed + Eq + Hash + 'static> From<T> for Interned<T> {
+    fn from(value: T) -> Self {
+        Interned::new(value)
+    }
+}
+
+impl<T> Clone for Interned<T> {
+    fn clone(&self) -> Self {
+        Interned(Rc::clone(&self.0))
+    }
+}
+
+impl<T> Deref for Interned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T> AsRef<T> for Interned<T> {
+    fn as_ref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T> PartialEq for Interned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for Interned<T> {}
+
+impl<T> Hash for Interned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state);
+    }
+}
+
+impl<T: Debug> Debug for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.value.fmt(f)
+    }
+}
+
+impl<T: Display> Display for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.value.fmt(f)
+    }
+}
+
+impl<T> DeBruijnIndexed for Interned<T>
+where
+    T: DeBruijnIndexed + Eq + Hash + 'static,
+{
+    fn map_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        self.map_changed_sort_indices_from(sort, start, map_fn)
+            .unwrap_or_else(|| self.clone())
+    }
+
+    fn map_changed_sort_indices_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        map_fn: F,
+    ) -> Option<Self>
+    where
+        F: Fn(usize) -> usize + Clone,
+    {
+        if !self.has_sort_indices_from(sort, start) {
+            return None;
+        }
+        (**self)
+            .map_changed_sort_indices_from(sort, start, map_fn)
+            .map(Interned::new)
+    }
+
+    fn clone_unchanged(&self) -> Self {
+        self.clone()
+    }
+
+    fn map_vars_at_depth<F>(&self, depth: usize, map_fn: F) -> Self
+    where
+        F: Fn(VarKind, usize, usize) -> usize + Clone,
+    {
+        // Comparing with the node is cheaper than looking up the table, as
+        // the children of the node are compared by pointer.
+        let mapped = (**self).map_vars_at_depth(depth, map_fn);
+        if mapped == **self {
+            self.clone()
+        } else {
+            Interned::new(mapped)
+        }
+    }
+
+    fn visit_sort_free_vars_from<F>(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+        visit_fn: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        match self.max_sort_free_index(sort) {
+            Some(max) if max >= start => {
+                (**self).visit_sort_free_vars_from(sort, start, visit_fn)
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    }
+
+    fn has_sort_indices_from(&self, sort: Option<Sort>, start: usize) -> bool {
+        self.max_sort_free_index(sort)
+            .is_some_and(|max| max >= start)
+    }
+
+    fn max_sort_free_index_from(
+        &self,
+        sort: Option<Sort>,
+        start: usize,
+    ) -> Option<usize> {
+        self.max_sort_free_index(sort)?.checked_sub(start)
+    }
+
+    fn interned_size(&self) -> usize {
+        self.size()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.max_free_index().is_none()
+    }
+
+    fn get_var(&self) -> Option<usize> {
+        DeBruijnIndexed::get_var(&**self)
+    }
+}
+
+/// Substitution into an interned node returns the same node if no variables
+/// of the substituted sort would be changed.
+impl<T, U> Substitute<U> for Interned<T>
+where
+    T: Substitute<U, Target = T> + DeBruijnIndexed + Eq + Hash + 'static,
+    U: 'static,
+{
+    type Target = Interned<T>;
+    type Error = T::Error;
+
+    fn substitute(
+        &self,
+        expr: &U,
+        var: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        if self.has_sort_indices_from(Some(Sort::of::<U>()), var) {
+            Ok(Interned::new((**self).substitute(expr, var)?))
+        } else {
+            Ok(self.clone())
+        }
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        Ok(self
+            .subst_changed_from(subst, depth)?
+            .unwrap_or_else(|| self.clone()))
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        if !self.has_sort_indices_from(Some(Sort::of::<U>()), depth) {
+            return Ok(None);
+        }
+        Ok((**self)
+            .subst_changed_from(subst, depth)?
+            .map(Interned::new))
+    }
+}
+
+/// Interned nodes are never modified in place, as they are shared by every
+/// equal term, so the handle is replaced if the substitution changes it.
+impl<T, U> SubstituteInPlace<U> for Interned<T>
+where
+    T: SubstituteInPlace<U> + DeBruijnIndexed + Eq + Hash + 'static,
+    U: 'static,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        if let Some(substituted) = self.subst_changed_from(subst, depth)? {
+            *self = substituted;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Evaluate> Evaluate for Interned<T> {
+    type Target = T::Target;
+
+    type Error = T::Error;
+
+    type Context = T::Context;
+
+    type Signature = T::Signature;
+
+    fn evaluate(
+        &self,
+        ctx: &Self::Context,
+        sig: &Self::Signature,
+        under_binders: bool,
+    ) -> Result<Self::Target, Self::Error> {
+        (**self).evaluate(ctx, sig, under_binders)
+    }
+}
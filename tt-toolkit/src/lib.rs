@@ -1,25 +1,51 @@
 pub mod debruijn_indexed;
 #[doc(inline)]
-pub use debruijn_indexed::DeBruijnIndexed;
+pub use debruijn_indexed::{
+    DeBruijnIndexed, Sort, StrengthenError, VarIndex, VarKind,
+};
 #[doc = include_str!("../docs/debruijn_indexed.md")]
 #[doc(inline)]
 pub use ttt_derive::DeBruijnIndexed;
 
+pub mod renaming;
+#[doc(inline)]
+pub use renaming::Renaming;
+
 pub mod substitute;
 #[doc(inline)]
-pub use substitute::{SubstError, Substitute};
+pub use substitute::{
+    Instantiate, Subst, SubstError, Substitute, SubstituteInPlace,
+};
 #[doc = include_str!("../docs/substitute.md")]
 #[doc(inline)]
 pub use ttt_derive::Substitute;
 
+pub mod intern;
+#[doc(inline)]
+pub use intern::Interned;
+
 pub mod context;
 #[doc(inline)]
-pub use context::{Context, ListContext, PartialContext};
+pub use context::{
+    Context, Def, LetEntry, ListContext, NamedContext, PartialContext,
+    ShiftedListContext, TreeContext, UnboundVariable, VecContext,
+};
+
+pub mod signature;
+#[doc(inline)]
+pub use signature::{GlobalDef, Signature, UnboundGlobal};
+
+pub mod alpha_eq;
+#[doc(inline)]
+pub use alpha_eq::AlphaEquivalent;
+#[doc = include_str!("../docs/alpha_eq.md")]
+#[doc(inline)]
+pub use ttt_derive::AlphaEq;
 
 pub mod contextual_eq;
 #[doc(inline)]
 pub use contextual_eq::{
-    AutoContextualEq, ContextualEq, NormalFormEq, SyntacticEq,
+    AlphaEq, AutoContextualEq, ContextualEq, NormalFormEq, SyntacticEq,
 };
 
 pub mod evaluate;
@@ -42,6 +68,9 @@ pub mod resolve_vars;
 pub use resolve_vars::*;
 pub use ttt_derive::ResolveVars;
 
+#[cfg(feature = "stack_safe")]
+pub mod stack;
+
 #[doc(hidden)]
 pub use ::spez;
 
@@ -62,8 +91,19 @@ mod never {
 
 pub use never::Never;
 
-#[derive(Clone, PartialEq, Debug)]
-pub struct DefaultError;
+/// The error type of derived attribute implementations.
+#[derive(Clone, PartialEq, Debug, thiserror::Error)]
+pub enum DefaultError {
+    #[error(transparent)]
+    UnboundVariable(#[from] UnboundVariable),
+    #[error(transparent)]
+    UnboundGlobal(#[from] UnboundGlobal),
+    /// The attribute of the definition of a `#[let_binding]` field couldn't
+    /// be synthesized, so its variable couldn't be added to the context to
+    /// check the body.
+    #[error("Cannot synthesize the attribute of a let-bound definition")]
+    UnsynthesizedDefinition,
+}
 
 impl From<Never> for DefaultError {
     fn from(value: Never) -> Self {
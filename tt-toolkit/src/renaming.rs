@@ -0,0 +1,150 @@
+use std::fmt::Display;
+
+/// A renaming of de Bruijn indices.
+///
+/// A renaming maps each of the first few indices to an arbitrary index, and
+/// shifts all of the indices after them by a constant amount. This is enough
+/// to represent weakening, exchange and permutation of variables, and is
+/// closed under composition and lifting under binders.
+///
+/// Renamings are applied to terms with [`DeBruijnIndexed::rename`](crate::DeBruijnIndexed::rename).
+///
+/// Renamings are kept in a normal form, so two renamings are equal exactly
+/// when they map every index to the same image.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Renaming {
+    /// The images of the indices `0..map.len()`.
+    map: Vec<usize>,
+    /// The image of the index `map.len()`. Later indices are mapped to
+    /// consecutive indices after this.
+    shift: usize,
+}
+
+impl Renaming {
+    /// Drop entries from the end of `map` which agree with the shifted part
+    /// of the renaming, so that equal renamings have equal representations.
+    fn normalised(mut self) -> Self {
+        while self.shift > 0 && self.map.last() == Some(&(self.shift - 1)) {
+            self.map.pop();
+            self.shift -= 1;
+        }
+        self
+    }
+
+    /// The renaming which leaves every index unchanged.
+    pub fn identity() -> Self {
+        Renaming::default()
+    }
+
+    /// The renaming which increments every index by `n`, as when adding `n`
+    /// new variables to the context of a term.
+    pub fn weaken(n: usize) -> Self {
+        Renaming {
+            map: Vec::new(),
+            shift: n,
+        }
+    }
+
+    /// The renaming which exchanges the variables with indices `i` and `j`.
+    pub fn swap(i: usize, j: usize) -> Self {
+        let len = i.max(j) + 1;
+        let mut map: Vec<usize> = (0..len).collect();
+        map.swap(i, j);
+        Renaming { map, shift: len }.normalised()
+    }
+
+    /// The renaming which maps the index `i` to `permutation[i]`, for each `i`
+    /// less than `permutation.len()`.
+    ///
+    /// Panics if `permutation` is not a permutation of
+    /// `0..permutation.len()`.
+    pub fn permute(permutation: &[usize]) -> Self {
+        let mut seen = vec![false; permutation.len()];
+        for &index in permutation {
+            assert!(
+                index < permutation.len() && !seen[index],
+                "{permutation:?} is not a permutation"
+            );
+            seen[index] = true;
+        }
+        Renaming {
+            map: permutation.to_vec(),
+            shift: permutation.len(),
+        }
+        .normalised()
+    }
+
+    /// The image of the index `index` under this renaming.
+    pub fn apply(&self, index: usize) -> usize {
+        match self.map.get(index) {
+            Some(&image) => image,
+            None => index - self.map.len() + self.shift,
+        }
+    }
+
+    /// The renaming to apply under a binder, which leaves the bound variable
+    /// unchanged and renames the variables outside of it by `self`.
+    pub fn lift(&self) -> Self {
+        self.lift_by(1)
+    }
+
+    /// The renaming to apply under `n` binders.
+    pub fn lift_by(&self, n: usize) -> Self {
+        let map = (0..n)
+            .chain(self.map.iter().map(|index| index + n))
+            .collect();
+        Renaming {
+            map,
+            shift: self.shift + n,
+        }
+        .normalised()
+    }
+
+    /// The renaming which first renames by `self` and then by `other`.
+    pub fn compose(&self, other: &Renaming) -> Self {
+        // Past this length, every index is mapped into the shifted part of
+        // both renamings.
+        let len = self.map.len() + other.map.len().saturating_sub(self.shift);
+        let map = (0..len)
+            .map(|index| other.apply(self.apply(index)))
+            .collect();
+        Renaming {
+            map,
+            shift: other.apply(self.apply(len)),
+        }
+        .normalised()
+    }
+
+    /// The inverse of this renaming, if it is a bijection on indices.
+    pub fn inverse(&self) -> Option<Self> {
+        if self.shift != self.map.len() {
+            return None;
+        }
+        let mut inverse = vec![None; self.map.len()];
+        for (index, &image) in self.map.iter().enumerate() {
+            let slot = inverse.get_mut(image)?;
+            if slot.replace(index).is_some() {
+                return None;
+            }
+        }
+        Some(Renaming {
+            map: inverse.into_iter().collect::<Option<_>>()?,
+            shift: self.shift,
+        })
+    }
+
+    /// Whether this renaming leaves every index unchanged.
+    pub fn is_identity(&self) -> bool {
+        *self == Renaming::identity()
+    }
+}
+
+impl Display for Renaming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for image in &self.map {
+            write!(f, "{image}, ")?;
+        }
+        write!(f, "↑{}]", self.shift)
+    }
+}
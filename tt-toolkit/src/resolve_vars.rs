@@ -7,6 +7,9 @@ pub type NameContext = ConsList<String>;
 #[derive(Debug)]
 pub enum ResolveVarsError {
     UnboundVariable(String),
+    /// A field binding several variables was given a different number of
+    /// names.
+    BinderCountMismatch { binders: usize, names: usize },
 }
 
 impl Display for ResolveVarsError {
@@ -15,6 +18,9 @@ impl Display for ResolveVarsError {
             ResolveVarsError::UnboundVariable(var) => {
                 write!(f, "Unbound variable name: {var}")
             }
+            ResolveVarsError::BinderCountMismatch { binders, names } => {
+                write!(f, "Expected {binders} binder names, found {names}")
+            }
         }
     }
 }
@@ -0,0 +1,93 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::Def;
+
+/// A global name which isn't declared in the signature it was looked up in.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[error("Unbound global name {name}")]
+pub struct UnboundGlobal {
+    pub name: String,
+}
+
+/// The declarations of top-level definitions, datatype constructors and
+/// axioms, referred to by name rather than by de Bruijn index.
+///
+/// Global references are annotated with `#[global_ref]`, so their names are
+/// never shifted, and are looked up in the signature passed alongside the
+/// local context to the derived attribute and evaluation traits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature<Name, Decl>(BTreeMap<Name, Decl>);
+
+impl<Name: Ord, Decl> Signature<Name, Decl> {
+    pub fn new() -> Self {
+        Signature(BTreeMap::new())
+    }
+
+    /// Add a declaration to the signature, returning the declaration it
+    /// replaces if `name` was already declared.
+    pub fn declare(&mut self, name: Name, decl: Decl) -> Option<Decl> {
+        self.0.insert(name, decl)
+    }
+
+    pub fn get<Q>(&self, name: &Q) -> Option<&Decl>
+    where
+        Name: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.0.get(name)
+    }
+
+    /// Look up the declaration of `name`, returning an error if it isn't
+    /// declared.
+    pub fn lookup<Q>(&self, name: &Q) -> Result<&Decl, UnboundGlobal>
+    where
+        Name: Borrow<Q>,
+        Q: Ord + Display + ?Sized,
+    {
+        self.get(name).ok_or_else(|| UnboundGlobal {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Name, &Decl)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<Name: Ord, Decl> Default for Signature<Name, Decl> {
+    fn default() -> Self {
+        Signature::new()
+    }
+}
+
+impl<Name: Ord, Decl> FromIterator<(Name, Decl)> for Signature<Name, Decl> {
+    fn from_iter<I: IntoIterator<Item = (Name, Decl)>>(iter: I) -> Self {
+        Signature(iter.into_iter().collect())
+    }
+}
+
+/// Global declarations which may define the global to be a term, which
+/// derived [`Evaluate`](crate::Evaluate) implementations unfold.
+pub trait GlobalDef<Val> {
+    /// The term the global is defined to be, or `None` if it is declared
+    /// without a definition, such as an axiom or a datatype constructor.
+    fn definition(&self) -> Option<&Val>;
+}
+
+impl<Ty, Val> GlobalDef<Val> for Def<Ty, Val> {
+    fn definition(&self) -> Option<&Val> {
+        self.value.as_ref()
+    }
+}
@@ -0,0 +1,47 @@
+//! Support for traversing very deep terms without overflowing the stack.
+//!
+//! The derived implementations of the traits in this crate recurse on the
+//! Rust stack, once for each level of nesting in a term. Long lists or large
+//! numerals represented in unary can be nested deeply enough to overflow it.
+//!
+//! Annotating a type with `#[ttt(stack_safe)]` makes each of its derived
+//! traversals check the amount of stack remaining before recursing into the
+//! fields of a node. When it runs low, a new segment of stack is allocated on
+//! the heap, so terms nested millions of levels deep can be processed.
+//!
+//! This module and the `#[ttt(stack_safe)]` option require the `stack_safe`
+//! feature of this crate, which adds a dependency on `stacker`.
+//!
+//! ```rust
+//! use ttt::DeBruijnIndexed;
+//!
+//! #[derive(DeBruijnIndexed)]
+//! #[ttt(stack_safe)]
+//! enum Nat {
+//!     Var(#[var_index] usize),
+//!     Zero,
+//!     Succ(Box<Nat>),
+//! }
+//! ```
+//!
+//! Only the derives from this crate are affected. In particular the drop glue
+//! and any derived `Clone`, `PartialEq` or `Debug` implementations of a type
+//! still recurse on the stack, so deeply nested terms may need a hand-written
+//! `Drop` implementation which dismantles them iteratively.
+
+/// The amount of stack which must remain before a new segment is allocated.
+const RED_ZONE: usize = 128 * 1024;
+
+/// The size of each new segment of stack.
+const STACK_SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// Run `f`, first moving onto a new segment of stack if the current one is
+/// close to running out.
+///
+/// This is called by the derived implementations of types annotated with
+/// `#[ttt(stack_safe)]`, and may also be used by hand-written recursive
+/// functions over terms, such as type checkers.
+#[inline]
+pub fn ensure_sufficient_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT_SIZE, f)
+}
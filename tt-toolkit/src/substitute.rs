@@ -1,18 +1,40 @@
 use std::any::{TypeId, type_name};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
 
 use thiserror::Error;
 
+use crate::{DeBruijnIndexed, Sort};
+
 #[derive(Debug, Error, PartialEq, Clone)]
-#[error(
-    "Tried to substitute a variable of kind {} for an expression of kind {}",
-    substitutand_type_name,
-    substitutee_type_name
-)]
+#[error(transparent)]
 pub struct SubstError {
-    substitutee_type: TypeId,
-    substitutand_type: TypeId,
-    substitutee_type_name: &'static str,
-    substitutand_type_name: &'static str,
+    kind: SubstErrorKind,
+}
+
+#[derive(Debug, Error, PartialEq, Clone)]
+enum SubstErrorKind {
+    #[error(
+        "Tried to substitute a variable of kind {} for an expression of kind {}",
+        substitutand_type_name,
+        substitutee_type_name
+    )]
+    KindMismatch {
+        substitutee_type: TypeId,
+        substitutand_type: TypeId,
+        substitutee_type_name: &'static str,
+        substitutand_type_name: &'static str,
+    },
+    #[error(
+        "Tried to substitute an expression of kind {} under a binder, but it does not implement `DeBruijnIndexed`",
+        substitutee_type_name
+    )]
+    NotShiftable {
+        substitutee_type: TypeId,
+        substitutee_type_name: &'static str,
+    },
 }
 
 impl SubstError {
@@ -22,14 +44,254 @@ impl SubstError {
         U: 'static,
     {
         Self {
-            substitutee_type: TypeId::of::<T>(),
-            substitutand_type: TypeId::of::<U>(),
-            substitutee_type_name: type_name::<T>(),
-            substitutand_type_name: type_name::<U>(),
+            kind: SubstErrorKind::KindMismatch {
+                substitutee_type: TypeId::of::<T>(),
+                substitutand_type: TypeId::of::<U>(),
+                substitutee_type_name: type_name::<T>(),
+                substitutand_type_name: type_name::<U>(),
+            },
+        }
+    }
+
+    /// The error for substituting an expression of type `T` under a binder,
+    /// when `T` can't be shifted as it doesn't implement [`DeBruijnIndexed`].
+    pub fn not_shiftable<T>() -> Self
+    where
+        T: 'static,
+    {
+        Self {
+            kind: SubstErrorKind::NotShiftable {
+                substitutee_type: TypeId::of::<T>(),
+                substitutee_type_name: type_name::<T>(),
+            },
+        }
+    }
+}
+
+/// An environment for simultaneous substitution, which replaces each of the
+/// variables with indices `0..terms.len()` by the corresponding term, and
+/// renumbers the variables after them to start from `shift`.
+///
+/// Substitutions are applied to terms with [`Substitute::subst_all`].
+/// The terms are only shifted when they replace a variable, by the number of
+/// binders between the variable and the root of the term, and closed terms are
+/// never shifted.
+///
+/// The terms may be stored as any type which borrows as the substituted type,
+/// such as references to terms which are owned elsewhere.
+#[derive(Clone)]
+pub struct Subst<T> {
+    terms: Terms<T>,
+    shift: usize,
+    /// The number of variables before the replaced variables which are left
+    /// unchanged. The terms are in the scope of these variables.
+    skip: usize,
+}
+
+/// A term of a substitution, with whether it is closed, which is only checked
+/// the first time the term is placed under a binder.
+#[derive(Clone)]
+struct Entry<T> {
+    term: T,
+    closed: OnceLock<bool>,
+}
+
+impl<T> Entry<T> {
+    fn new(term: T) -> Self {
+        Entry {
+            term,
+            closed: OnceLock::new(),
+        }
+    }
+}
+
+/// The terms of a substitution in reverse, with the term for the variable
+/// with the largest index first, so that [`Subst::cons`] adds a term at the
+/// end. A single term is stored inline, so that substituting for a single
+/// variable doesn't allocate.
+#[derive(Clone)]
+enum Terms<T> {
+    Single([Entry<T>; 1]),
+    Many(Vec<Entry<T>>),
+}
+
+impl<T> Terms<T> {
+    fn as_slice(&self) -> &[Entry<T>] {
+        match self {
+            Terms::Single(entry) => entry,
+            Terms::Many(entries) => entries,
+        }
+    }
+
+    fn push(self, term: T) -> Self {
+        let mut entries = match self {
+            Terms::Single(entry) => Vec::from(entry),
+            Terms::Many(entries) => entries,
+        };
+        entries.push(Entry::new(term));
+        Terms::Many(entries)
+    }
+}
+
+impl<T> Subst<T> {
+    /// The substitution which replaces the variable with index `i` by
+    /// `terms[i]`, and renumbers the variables after them to start from
+    /// `shift`.
+    pub fn new(terms: Vec<T>, shift: usize) -> Self {
+        Subst {
+            terms: Terms::Many(
+                terms.into_iter().rev().map(Entry::new).collect(),
+            ),
+            shift,
+            skip: 0,
+        }
+    }
+
+    /// The substitution which leaves every variable unchanged.
+    pub fn identity() -> Self {
+        Subst::weaken(0)
+    }
+
+    /// The substitution which increments every variable by `n`.
+    pub fn weaken(n: usize) -> Self {
+        Subst::new(Vec::new(), n)
+    }
+
+    /// The substitution which replaces the variables with indices
+    /// `0..terms.len()` by `terms`, and removes them from scope.
+    /// This instantiates the body of a binder over `terms.len()` variables,
+    /// where `terms[0]` is the argument for the innermost binder.
+    pub fn instantiate(terms: Vec<T>) -> Self {
+        Subst::new(terms, 0)
+    }
+
+    /// The substitution which replaces the variable with index `var` by
+    /// `term`, and decrements the variables after it, as in
+    /// [`Substitute::substitute`].
+    /// Unlike the other substitutions, the variables before `var` are in scope
+    /// in `term`.
+    pub fn single(var: usize, term: T) -> Self {
+        Subst {
+            terms: Terms::Single([Entry::new(term)]),
+            shift: 0,
+            skip: var,
+        }
+    }
+
+    /// Extend the substitution with a term for a new variable with index 0,
+    /// incrementing the indices of the variables which are already replaced.
+    pub fn cons(self, term: T) -> Self {
+        Subst {
+            terms: self.terms.push(term),
+            ..self
+        }
+    }
+
+    /// The terms of the substitution, starting with the term which replaces
+    /// the variable with the smallest index.
+    pub fn terms(&self) -> impl ExactSizeIterator<Item = &T> {
+        self.terms.as_slice().iter().rev().map(|entry| &entry.term)
+    }
+
+    pub fn shift(&self) -> usize {
+        self.shift
+    }
+
+    /// The entry for the term which replaces the variable with index `var`,
+    /// or the new index of the variable if it isn't replaced.
+    fn entry(&self, var: usize) -> Result<&Entry<T>, usize> {
+        if var < self.skip {
+            return Err(var);
+        }
+        let offset = var - self.skip;
+        let entries = self.terms.as_slice();
+        if offset < entries.len() {
+            Ok(&entries[entries.len() - 1 - offset])
+        } else {
+            Err(offset - entries.len() + self.shift + self.skip)
+        }
+    }
+
+    /// The term which replaces the variable with index `var`, or the new
+    /// index of the variable if it isn't replaced.
+    pub fn lookup(&self, var: usize) -> Result<&T, usize> {
+        self.entry(var).map(|entry| &entry.term)
+    }
+
+    /// Whether the term which replaces the variable with index `var` is
+    /// closed, and so is never shifted. This is only checked once for each
+    /// term, rather than every time it replaces a variable.
+    /// Variables which aren't replaced are not closed.
+    pub fn is_closed_term<U>(&self, var: usize) -> bool
+    where
+        T: Borrow<U>,
+        U: DeBruijnIndexed,
+    {
+        self.entry(var).is_ok_and(|entry| {
+            *entry.closed.get_or_init(|| entry.term.borrow().is_closed())
+        })
+    }
+
+    /// Increment the indices of sort `sort` in each of the terms by `amount`,
+    /// for use under a binder of a sort which isn't being substituted.
+    pub fn shift_terms<U>(&self, sort: Option<Sort>, amount: usize) -> Subst<U>
+    where
+        T: Borrow<U>,
+        U: DeBruijnIndexed,
+    {
+        // Shifting a term doesn't change whether it is closed.
+        let shift_entry = |entry: &Entry<T>| Entry {
+            term: entry.term.borrow().increment_sort_indices_by(sort, amount),
+            closed: entry.closed.clone(),
+        };
+        let terms = match &self.terms {
+            Terms::Single([entry]) => Terms::Single([shift_entry(entry)]),
+            Terms::Many(entries) => {
+                Terms::Many(entries.iter().map(shift_entry).collect())
+            }
+        };
+        Subst {
+            terms,
+            shift: self.shift,
+            skip: self.skip,
         }
     }
 }
 
+impl<'a, T> Subst<&'a T> {
+    /// Like [`Subst::instantiate`], for terms borrowed from a slice, where
+    /// `terms[0]` replaces the variable with index 0.
+    pub fn instantiate_slice(terms: &'a [T]) -> Self {
+        let terms = match terms {
+            [term] => Terms::Single([Entry::new(term)]),
+            _ => Terms::Many(terms.iter().rev().map(Entry::new).collect()),
+        };
+        Subst {
+            terms,
+            shift: 0,
+            skip: 0,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Subst<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subst")
+            .field("terms", &self.terms().collect::<Vec<_>>())
+            .field("shift", &self.shift)
+            .field("skip", &self.skip)
+            .finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Subst<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.terms().eq(other.terms())
+            && self.shift == other.shift
+            && self.skip == other.skip
+    }
+}
+
 /// A syntax node which admits a substitution operation.
 pub trait Substitute<SubstExpr> {
     /// The result of substituting an expression for a variable in this term. For
@@ -41,7 +303,7 @@ pub trait Substitute<SubstExpr> {
     /// `std::convert::Infallible`.
     /// However, in more complex cases you may want to implement `Substitute<E>` for other values of `E` (such as where you have mutually recursive expression types, each which may contain variables).
     /// In such cases `substitute` must handle the case where the variable index refers to a position in the wrong type of expression.
-    /// N.B. currently the derive macro for `Substitute` will always use `SubstError` as the error type.
+    /// The derive macro for `Substitute` uses `SubstError` as the error type, unless another type is given with `#[subst_error_type(...)]`, or `Infallible` for types annotated with `#[subst_infallible]`.
     type Error;
 
     /// Substitute `expr` into any variables in the expression matching the specified
@@ -51,6 +313,167 @@ pub trait Substitute<SubstExpr> {
         expr: &SubstExpr,
         var: usize,
     ) -> Result<Self::Target, Self::Error>;
+
+    /// Simultaneously replace every free variable of the term according to
+    /// `subst`, where the term is under `depth` binders which `subst` should
+    /// be lifted over.
+    /// The substitution may hold the terms by reference.
+    fn subst_all_from<S: Borrow<SubstExpr>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error>;
+
+    /// Like [`Substitute::subst_all_from`], but returns `None` if the
+    /// substitution doesn't change the term, so that unchanged terms are not
+    /// rebuilt. Shared nodes such as `Rc` use this to keep the same pointer
+    /// when nothing below them changes.
+    ///
+    /// `None` means the result is equal to the term, so is only returned when
+    /// `Target` is `Self`. The default implementation always returns `Some`;
+    /// the derived implementations, and those for containers such as `Box`
+    /// and `Vec`, only rebuild the parts of the term which change.
+    fn subst_changed_from<S: Borrow<SubstExpr>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        self.subst_all_from(subst, depth).map(Some)
+    }
+
+    /// Simultaneously replace every free variable of the term according to
+    /// `subst`, in a single traversal.
+    fn subst_all(
+        &self,
+        subst: &Subst<SubstExpr>,
+    ) -> Result<Self::Target, Self::Error> {
+        self.subst_all_from(subst, 0)
+    }
+
+    /// Like [`Substitute::subst_all_from`], for a term shared by an `Rc`.
+    /// The derived implementations return the same pointer if the
+    /// substitution doesn't change the term, so that sharing is preserved.
+    fn subst_all_rc_from<S: Borrow<SubstExpr>>(
+        this: &std::rc::Rc<Self>,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<std::rc::Rc<Self::Target>, Self::Error>
+    where
+        Self: Sized,
+    {
+        Ok(std::rc::Rc::new(Self::subst_all_from(this, subst, depth)?))
+    }
+
+    /// Like [`Substitute::subst_all_rc_from`], for a term shared by an `Arc`.
+    fn subst_all_arc_from<S: Borrow<SubstExpr>>(
+        this: &std::sync::Arc<Self>,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<std::sync::Arc<Self::Target>, Self::Error>
+    where
+        Self: Sized,
+    {
+        Ok(std::sync::Arc::new(Self::subst_all_from(
+            this, subst, depth,
+        )?))
+    }
+}
+
+/// Substitution which modifies a term in place, for terms whose substitution
+/// has the same type as the term.
+///
+/// This is derived along with `Substitute` for types annotated with
+/// `#[ttt(in_place)]`. The derived implementations, and those for containers
+/// such as `Box` and `Vec`, reuse the allocations of the term rather than
+/// building a copy of it. Every method has a default implementation in terms
+/// of [`Substitute`], so a type with a hand-written `Substitute`
+/// implementation can be used as a field of such a type with an empty
+/// `SubstituteInPlace` implementation.
+///
+/// If an error is returned, the term may have been partially substituted.
+pub trait SubstituteInPlace<SubstExpr>:
+    Substitute<SubstExpr, Target = Self> + Sized
+{
+    /// Like [`Substitute::subst_all_from`], but modifies the term in place.
+    fn subst_all_in_place_from<S: Borrow<SubstExpr>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        *self = self.subst_all_from(subst, depth)?;
+        Ok(())
+    }
+
+    /// Like [`Substitute::subst_all`], but modifies the term in place.
+    fn subst_all_in_place(
+        &mut self,
+        subst: &Subst<SubstExpr>,
+    ) -> Result<(), Self::Error> {
+        self.subst_all_in_place_from(subst, 0)
+    }
+
+    /// Like [`Substitute::substitute`], but modifies the term in place.
+    fn substitute_in_place(
+        &mut self,
+        expr: &SubstExpr,
+        var: usize,
+    ) -> Result<(), Self::Error> {
+        self.subst_all_in_place_from(&Subst::single(var, expr), 0)
+    }
+
+    /// Like [`Substitute::substitute`], but consumes the term.
+    fn substitute_owned(
+        mut self,
+        expr: &SubstExpr,
+        var: usize,
+    ) -> Result<Self, Self::Error> {
+        self.substitute_in_place(expr, var)?;
+        Ok(self)
+    }
+}
+
+/// Instantiating the body of a binder with arguments for its bound
+/// variables, as in beta reduction.
+///
+/// This is implemented by the derive macro for `Substitute`, for each type
+/// which can be substituted.
+pub trait Instantiate<Arg> {
+    /// The result of instantiating the term. This is
+    /// `Result<Target, Error>` for the `Target` and `Error` types of the
+    /// substitution, or just `Target` for types annotated with
+    /// `#[subst_infallible]`.
+    type Output;
+
+    /// Substitute `arg` for the variable bound by a single binder over this
+    /// term. The free variables of `arg` are shifted under any binders inside
+    /// the term, and the other free variables of the term are decremented to
+    /// remove the binder.
+    fn instantiate(&self, arg: &Arg) -> Self::Output;
+
+    /// Substitute `args` for the variables bound by a binder over
+    /// `args.len()` variables, where `args[0]` is the argument for the
+    /// innermost variable.
+    fn instantiate_many(&self, args: &[Arg]) -> Self::Output;
+}
+
+/// The substituted element of a container whose other elements changed,
+/// given the result of [`Substitute::subst_changed_from`] for the element.
+/// The target of an unchanged element may be a different type, so the
+/// element is substituted again to build it.
+fn unchanged_or<T, U, S>(
+    changed: Option<T::Target>,
+    inner: &T,
+    subst: &Subst<S>,
+    depth: usize,
+) -> Result<T::Target, T::Error>
+where
+    T: Substitute<U>,
+    S: Borrow<U>,
+{
+    match changed {
+        Some(changed) => Ok(changed),
+        None => inner.subst_all_from(subst, depth),
+    }
 }
 
 impl<T, U> Substitute<U> for Box<T>
@@ -67,8 +490,40 @@ where
     ) -> Result<Self::Target, Self::Error> {
         Ok(Box::new((**self).substitute(expr, var)?))
     }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        Ok(Box::new((**self).subst_all_from(subst, depth)?))
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        Ok((**self).subst_changed_from(subst, depth)?.map(Box::new))
+    }
+}
+
+impl<T, U> SubstituteInPlace<U> for Box<T>
+where
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        (**self).subst_all_in_place_from(subst, depth)
+    }
 }
 
+/// Substitution into a shared node returns the same pointer if the
+/// substitution doesn't change it, for types whose implementations of
+/// [`Substitute::subst_all_rc_from`] detect this, such as the derived ones.
 impl<T, U> Substitute<U> for std::rc::Rc<T>
 where
     T: Substitute<U>,
@@ -81,6 +536,426 @@ where
         expr: &U,
         var: usize,
     ) -> Result<Self::Target, Self::Error> {
-        Ok(std::rc::Rc::new((**self).substitute(expr, var)?))
+        T::subst_all_rc_from(self, &Subst::single(var, expr), 0)
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        T::subst_all_rc_from(self, subst, depth)
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        Ok((**self)
+            .subst_changed_from(subst, depth)?
+            .map(std::rc::Rc::new))
+    }
+}
+
+/// Substitution modifies a shared node in place only if there are no other
+/// references to it.
+impl<T, U> SubstituteInPlace<U> for std::rc::Rc<T>
+where
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        match std::rc::Rc::get_mut(self) {
+            Some(inner) => inner.subst_all_in_place_from(subst, depth),
+            None => {
+                *self = self.subst_all_from(subst, depth)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Substitution into a shared node returns the same pointer if the
+/// substitution doesn't change it, for types whose implementations of
+/// [`Substitute::subst_all_arc_from`] detect this, such as the derived ones.
+impl<T, U> Substitute<U> for std::sync::Arc<T>
+where
+    T: Substitute<U>,
+{
+    type Target = std::sync::Arc<T::Target>;
+    type Error = T::Error;
+
+    fn substitute(
+        &self,
+        expr: &U,
+        var: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        T::subst_all_arc_from(self, &Subst::single(var, expr), 0)
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        T::subst_all_arc_from(self, subst, depth)
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        Ok((**self)
+            .subst_changed_from(subst, depth)?
+            .map(std::sync::Arc::new))
+    }
+}
+
+/// Substitution modifies a shared node in place only if there are no other
+/// references to it.
+impl<T, U> SubstituteInPlace<U> for std::sync::Arc<T>
+where
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        match std::sync::Arc::get_mut(self) {
+            Some(inner) => inner.subst_all_in_place_from(subst, depth),
+            None => {
+                *self = self.subst_all_from(subst, depth)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T, U> Substitute<U> for Option<T>
+where
+    T: Substitute<U>,
+{
+    type Target = Option<T::Target>;
+    type Error = T::Error;
+
+    fn substitute(
+        &self,
+        expr: &U,
+        var: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        self.as_ref()
+            .map(|inner| inner.substitute(expr, var))
+            .transpose()
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        self.as_ref()
+            .map(|inner| inner.subst_all_from(subst, depth))
+            .transpose()
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        match self {
+            Some(inner) => {
+                Ok(inner.subst_changed_from(subst, depth)?.map(Some))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T, U> SubstituteInPlace<U> for Option<T>
+where
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        match self {
+            Some(inner) => inner.subst_all_in_place_from(subst, depth),
+            None => Ok(()),
+        }
     }
 }
+
+impl<T, U> Substitute<U> for Vec<T>
+where
+    T: Substitute<U>,
+{
+    type Target = Vec<T::Target>;
+    type Error = T::Error;
+
+    fn substitute(
+        &self,
+        expr: &U,
+        var: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        self.iter()
+            .map(|inner| inner.substitute(expr, var))
+            .collect()
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        self.iter()
+            .map(|inner| inner.subst_all_from(subst, depth))
+            .collect()
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        let changed = self
+            .iter()
+            .map(|inner| inner.subst_changed_from(subst, depth))
+            .collect::<Result<Vec<_>, _>>()?;
+        if changed.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        changed
+            .into_iter()
+            .zip(self)
+            .map(|(changed, inner)| unchanged_or(changed, inner, subst, depth))
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+impl<T, U> SubstituteInPlace<U> for Vec<T>
+where
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        self.iter_mut()
+            .try_for_each(|inner| inner.subst_all_in_place_from(subst, depth))
+    }
+}
+
+impl<T, U, const N: usize> Substitute<U> for [T; N]
+where
+    T: Substitute<U>,
+{
+    type Target = [T::Target; N];
+    type Error = T::Error;
+
+    fn substitute(
+        &self,
+        expr: &U,
+        var: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        let substituted = self
+            .iter()
+            .map(|inner| inner.substitute(expr, var))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(substituted
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("Array length is preserved")))
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        let substituted = self
+            .iter()
+            .map(|inner| inner.subst_all_from(subst, depth))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(substituted
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("Array length is preserved")))
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        let changed = self
+            .iter()
+            .map(|inner| inner.subst_changed_from(subst, depth))
+            .collect::<Result<Vec<_>, _>>()?;
+        if changed.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        let substituted = changed
+            .into_iter()
+            .zip(self)
+            .map(|(changed, inner)| unchanged_or(changed, inner, subst, depth))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(substituted.try_into().unwrap_or_else(|_| {
+            unreachable!("Array length is preserved")
+        })))
+    }
+}
+
+impl<T, U, const N: usize> SubstituteInPlace<U> for [T; N]
+where
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        self.iter_mut()
+            .try_for_each(|inner| inner.subst_all_in_place_from(subst, depth))
+    }
+}
+
+impl<K, T, U> Substitute<U> for BTreeMap<K, T>
+where
+    K: Ord + Clone,
+    T: Substitute<U>,
+{
+    type Target = BTreeMap<K, T::Target>;
+    type Error = T::Error;
+
+    fn substitute(
+        &self,
+        expr: &U,
+        var: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        self.iter()
+            .map(|(key, inner)| Ok((key.clone(), inner.substitute(expr, var)?)))
+            .collect()
+    }
+
+    fn subst_all_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Self::Target, Self::Error> {
+        self.iter()
+            .map(|(key, inner)| {
+                Ok((key.clone(), inner.subst_all_from(subst, depth)?))
+            })
+            .collect()
+    }
+
+    fn subst_changed_from<S: Borrow<U>>(
+        &self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<Option<Self::Target>, Self::Error> {
+        let changed = self
+            .values()
+            .map(|inner| inner.subst_changed_from(subst, depth))
+            .collect::<Result<Vec<_>, _>>()?;
+        if changed.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        changed
+            .into_iter()
+            .zip(self)
+            .map(|(changed, (key, inner))| {
+                Ok((key.clone(), unchanged_or(changed, inner, subst, depth)?))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+impl<K, T, U> SubstituteInPlace<U> for BTreeMap<K, T>
+where
+    K: Ord + Clone,
+    T: SubstituteInPlace<U>,
+{
+    fn subst_all_in_place_from<S: Borrow<U>>(
+        &mut self,
+        subst: &Subst<S>,
+        depth: usize,
+    ) -> Result<(), Self::Error> {
+        self.values_mut()
+            .try_for_each(|inner| inner.subst_all_in_place_from(subst, depth))
+    }
+}
+
+macro_rules! tuple_impl {
+    ($first:ident $(, $param:ident)*) => {
+        impl<U, $first, $($param),*> Substitute<U> for ($first, $($param,)*)
+        where
+            $first: Substitute<U>,
+            $($param: Substitute<U, Error = $first::Error>,)*
+        {
+            type Target = ($first::Target, $($param::Target,)*);
+            type Error = $first::Error;
+
+            fn substitute(
+                &self,
+                expr: &U,
+                var: usize,
+            ) -> Result<Self::Target, Self::Error> {
+                #[allow(non_snake_case)]
+                let ($first, $($param,)*) = self;
+                Ok((
+                    $first.substitute(expr, var)?,
+                    $($param.substitute(expr, var)?,)*
+                ))
+            }
+
+            fn subst_all_from<S: Borrow<U>>(
+                &self,
+                subst: &Subst<S>,
+                depth: usize,
+            ) -> Result<Self::Target, Self::Error> {
+                #[allow(non_snake_case)]
+                let ($first, $($param,)*) = self;
+                Ok((
+                    $first.subst_all_from(subst, depth)?,
+                    $($param.subst_all_from(subst, depth)?,)*
+                ))
+            }
+        }
+
+        impl<U, $first, $($param),*> SubstituteInPlace<U> for ($first, $($param,)*)
+        where
+            $first: SubstituteInPlace<U>,
+            $($param: SubstituteInPlace<U, Error = $first::Error>,)*
+        {
+            fn subst_all_in_place_from<S: Borrow<U>>(
+                &mut self,
+                subst: &Subst<S>,
+                depth: usize,
+            ) -> Result<(), Self::Error> {
+                #[allow(non_snake_case)]
+                let ($first, $($param,)*) = self;
+                $first.subst_all_in_place_from(subst, depth)?;
+                $($param.subst_all_in_place_from(subst, depth)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple_impl!(T0);
+tuple_impl!(T0, T1);
+tuple_impl!(T0, T1, T2);
+tuple_impl!(T0, T1, T2, T3);
+tuple_impl!(T0, T1, T2, T3, T4);
+tuple_impl!(T0, T1, T2, T3, T4, T5);
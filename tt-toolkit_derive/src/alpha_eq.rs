@@ -0,0 +1,81 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use synstructure::{AddBounds, BindingInfo, Structure};
+
+use crate::attributes::*;
+use crate::stack_safe::guard_stack;
+use crate::utils::attributes::HasAttributes;
+
+/// Whether a field is ignored when comparing terms. Global references are
+/// never shifted, like metadata, but name distinct declarations so are still
+/// compared.
+fn field_is_ignored(binding: &BindingInfo) -> bool {
+    binding.is_metadata() && !binding.has_attribute(GLOBAL_REF_ATTR)
+}
+
+/// Compares a pair of fields with `AlphaEquivalent` if their type implements
+/// it, and with `PartialEq` otherwise.
+fn field_eq_impl(lhs: &BindingInfo, rhs: &BindingInfo) -> TokenStream {
+    quote_spanned! { lhs.ast().ty.span() =>
+        ::ttt::spez::spez! {
+            for __ttt_fields = (#lhs, #rhs);
+            match<T: ::ttt::AlphaEquivalent> (&T, &T) -> bool {
+                ::ttt::AlphaEquivalent::alpha_eq(__ttt_fields.0, __ttt_fields.1)
+            }
+            match<T: ::core::cmp::PartialEq> (&T, &T) -> bool {
+                __ttt_fields.0 == __ttt_fields.1
+            }
+        }
+    }
+}
+
+fn alpha_eq_impl(ast: &Structure) -> TokenStream {
+    let mut lhs = ast.clone();
+    lhs.binding_name(|_, i| format_ident!("__ttt_lhs_{}", i));
+    let mut rhs = ast.clone();
+    rhs.binding_name(|_, i| format_ident!("__ttt_rhs_{}", i));
+
+    let arms = lhs.variants().iter().zip(rhs.variants()).map(|(lhs, rhs)| {
+        let lhs_pat = lhs.pat();
+        let rhs_pat = rhs.pat();
+        let field_eqs: Vec<_> = lhs
+            .bindings()
+            .iter()
+            .zip(rhs.bindings())
+            .filter(|(field, _)| !field_is_ignored(field))
+            .map(|(lhs, rhs)| field_eq_impl(lhs, rhs))
+            .collect();
+        let body = if field_eqs.is_empty() {
+            quote!(true)
+        } else {
+            quote!(#(#field_eqs)&&*)
+        };
+        quote! {
+            (&#lhs_pat, &#rhs_pat) => #body,
+        }
+    });
+
+    // A single variant always matches itself.
+    let fallback = (ast.variants().len() != 1).then(|| quote!(_ => false,));
+
+    quote! {
+        match (self, __ttt_other) {
+            #(#arms)*
+            #fallback
+        }
+    }
+}
+
+pub fn derive(mut ast: Structure) -> TokenStream {
+    ast.add_bounds(AddBounds::Generics);
+    let body = guard_stack(&ast, alpha_eq_impl(&ast));
+
+    ast.gen_impl(quote! {
+        gen impl ::ttt::AlphaEquivalent for @Self {
+            fn alpha_eq(&self, __ttt_other: &Self) -> bool {
+                #body
+            }
+        }
+    })
+}
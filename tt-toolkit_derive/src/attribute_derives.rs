@@ -6,9 +6,12 @@ use structmeta::StructMeta;
 use syn::{Arm, Attribute, Expr, Pat, Token, Type, parse_quote};
 use synstructure::{AddBounds, BindingInfo, Structure, VariantInfo};
 
-use crate::attribute_dsl::instantiate_dsl;
+use crate::attribute_dsl::{instantiate_dsl, signature_name};
+use crate::attributes::BINDING_NAME_ATTR;
+use crate::binders::let_binding;
+use crate::stack_safe::guard_stack;
 use crate::utils::attributes::HasAttributes;
-use crate::utils::auto_deref;
+use crate::utils::{VariantInfoExt, auto_deref};
 
 const CHECK_TYPES_ATTR: &str = "check_type";
 const SYNTH_TYPES_ATTR: &str = "synth_type";
@@ -71,15 +74,21 @@ struct CheckArm {
 }
 
 trait DesugarsToMatchArm {
-    fn desugar(&self, context_type: &Type, entry_type: &Type) -> Arm;
+    fn desugar(
+        &self,
+        variant: &VariantInfo,
+        context_type: &Type,
+        entry_type: &Type,
+    ) -> Arm;
 
     fn generate_match(
         &self,
         match_on: impl ToTokens,
+        variant: &VariantInfo,
         context_type: &Type,
         entry_type: &Type,
     ) -> TokenStream {
-        let arm = self.desugar(context_type, entry_type);
+        let arm = self.desugar(variant, context_type, entry_type);
         // TODO: Erorr handling
         let err = quote! {
             panic!()
@@ -96,13 +105,19 @@ trait DesugarsToMatchArm {
 }
 
 impl DesugarsToMatchArm for SynthBlock {
-    fn desugar(&self, context_type: &Type, entry_type: &Type) -> Arm {
+    fn desugar(
+        &self,
+        variant: &VariantInfo,
+        context_type: &Type,
+        entry_type: &Type,
+    ) -> Arm {
         let pattern = &self.arm.pattern;
         let body = instantiate_dsl(
             context_type,
             &ctx_name(),
             &self.attr_type,
             entry_type,
+            binding_name(variant),
             &self.arm.body,
         );
         parse_quote! {
@@ -112,7 +127,12 @@ impl DesugarsToMatchArm for SynthBlock {
 }
 
 impl DesugarsToMatchArm for CheckBlock {
-    fn desugar(&self, context_type: &Type, entry_type: &Type) -> Arm {
+    fn desugar(
+        &self,
+        variant: &VariantInfo,
+        context_type: &Type,
+        entry_type: &Type,
+    ) -> Arm {
         let expr_pat = &self.arm.expr_pat;
         let type_pat = &self.arm.type_pat;
         let guard = &self.arm.guard;
@@ -121,6 +141,7 @@ impl DesugarsToMatchArm for CheckBlock {
             &ctx_name(),
             &self.attr_type,
             entry_type,
+            binding_name(variant),
             &self.arm.body,
         );
         parse_quote! {
@@ -130,7 +151,12 @@ impl DesugarsToMatchArm for CheckBlock {
 }
 
 impl DesugarsToMatchArm for BidirSynthBlock {
-    fn desugar(&self, context_type: &Type, entry_type: &Type) -> Arm {
+    fn desugar(
+        &self,
+        variant: &VariantInfo,
+        context_type: &Type,
+        entry_type: &Type,
+    ) -> Arm {
         let pattern = &self.0.arm.pattern;
         let attr_type = &self.0.attr_type;
         let optional_attr_type: Type =
@@ -140,6 +166,7 @@ impl DesugarsToMatchArm for BidirSynthBlock {
             &ctx_name(),
             &attr_type,
             entry_type,
+            binding_name(variant),
             &self.0.arm.body,
         );
         parse_quote! {
@@ -161,12 +188,14 @@ struct AttrType {
     attr: Type,
     context_entry: Option<Type>,
     context: Option<Type>,
+    signature: Option<Type>,
 }
 
 struct AttrSpec {
     attr_type: Type,
     context_entry: Type,
     context: Type,
+    signature: Type,
 }
 
 impl From<AttrType> for AttrSpec {
@@ -177,11 +206,13 @@ impl From<AttrType> for AttrSpec {
         let context = attr_type.context.unwrap_or_else(
             || parse_quote!(::ttt::ListContext<#context_entry>),
         );
+        let signature = attr_type.signature.unwrap_or_else(|| parse_quote!(()));
         let attr_type = attr_type.attr;
         AttrSpec {
             attr_type,
             context_entry,
             context,
+            signature,
         }
     }
 }
@@ -212,6 +243,33 @@ fn attr_types(structure: &Structure, attr_name: &str) -> Vec<AttrSpec> {
     }
 }
 
+/// The `#[binding_name]` field of a variant, used to name the variables
+/// bound in its attribute DSL.
+fn binding_name(variant: &VariantInfo) -> Option<TokenStream> {
+    variant
+        .find_binding_with_attribute(BINDING_NAME_ATTR)
+        .map(|binding| binding.to_token_stream())
+}
+
+/// Adds the variable bound by a `#[let_binding]` field to the context, with
+/// its synthesized type `__ttt_definition_ty` and the definition
+/// `__ttt_definition`, shadowing the context for the rest of the block.
+fn extend_ctx_with_definition(instance: &AttrSpec) -> TokenStream {
+    let ctx_name = ctx_name();
+    let attr_type = &instance.attr_type;
+    let context_entry = &instance.context_entry;
+    let context_type = &instance.context;
+    quote! {
+        let #ctx_name = &<#context_type as ::ttt::Context<#context_entry>>::append(
+            #ctx_name,
+            <#context_entry as ::ttt::LetEntry<#attr_type, _>>::let_entry(
+                __ttt_definition_ty,
+                __ttt_definition,
+            ),
+        );
+    }
+}
+
 fn opt_single_binding<'a>(
     variant: &'a VariantInfo,
 ) -> Option<&'a BindingInfo<'a>> {
@@ -271,6 +329,8 @@ fn derive_check_one(input: &Structure, instance: AttrSpec) -> TokenStream {
     let attr_type = &instance.attr_type;
     let context_entry = &instance.context_entry;
     let context_type = &instance.context;
+    let signature_type = &instance.signature;
+    let sig_name = signature_name();
 
     let check_impl = input.each_variant(|variant| {
         let ctx_name = ctx_name();
@@ -285,12 +345,13 @@ fn derive_check_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             };
             check.generate_match(
                 bindings,
+                variant,
                 &instance.context,
                 &instance.context_entry,
             )
         } else if let Some(node) = opt_single_binding(variant) {
             quote! {
-                #node.check(#ctx_name, __ttt_check_value)
+                #node.check(__ttt_check_value, #ctx_name, #sig_name)
             }
         } else {
             abort! {
@@ -299,10 +360,19 @@ fn derive_check_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             }
         }
     });
+    let check_impl = guard_stack(
+        input,
+        quote! {
+            match self {
+                #check_impl
+            }
+        },
+    );
 
     input.gen_impl(quote! {
         gen impl ::ttt::CheckAttribute<#attr_type> for @Self {
             type Ctx = #context_type;
+            type Sig = #signature_type;
             type Entry = #context_entry;
             type Error = ::ttt::DefaultError;
             type Check = bool;
@@ -310,10 +380,9 @@ fn derive_check_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             fn check(&self,
                 __ttt_check_value: &#attr_type,
                 __ttt_context: &#context_type,
+                #sig_name: &#signature_type,
             ) -> ::core::result::Result<Self::Check, Self::Error> {
-                match self {
-                    #check_impl
-                }
+                #check_impl
             }
         }
     })
@@ -323,6 +392,8 @@ fn derive_synth_one(input: &Structure, instance: AttrSpec) -> TokenStream {
     let attr_type = &instance.attr_type;
     let context_entry = &instance.context_entry;
     let context_type = &instance.context;
+    let signature_type = &instance.signature;
+    let sig_name = signature_name();
 
     let synth_impl = input.each_variant(|variant| {
         let ctx_name = ctx_name();
@@ -335,12 +406,24 @@ fn derive_synth_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             };
             synth.generate_match(
                 bindings,
+                variant,
                 &instance.context,
                 &instance.context_entry,
             )
+        } else if let Some((body, value)) = let_binding(variant) {
+            let definition = auto_deref(value);
+            let body = auto_deref(body);
+            let extend_ctx = extend_ctx_with_definition(&instance);
+            quote! {{
+                let __ttt_definition = #definition;
+                let __ttt_definition_ty =
+                    ::ttt::SynthAttribute::<#attr_type>::synth(__ttt_definition, #ctx_name, #sig_name)?;
+                #extend_ctx
+                ::ttt::SynthAttribute::<#attr_type>::synth(#body, #ctx_name, #sig_name)
+            }}
         } else if let Some(node) = opt_single_binding(variant) {
             quote! {
-                #node.try_synth(#ctx_name)
+                #node.try_synth(#ctx_name, #sig_name)
             }
         } else {
             abort! {
@@ -349,19 +432,27 @@ fn derive_synth_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             }
         }
     });
+    let synth_impl = guard_stack(
+        input,
+        quote! {
+            match self {
+                #synth_impl
+            }
+        },
+    );
 
     input.gen_impl(quote! {
         gen impl ::ttt::SynthAttribute<#attr_type> for @Self {
             type Ctx = #context_type;
+            type Sig = #signature_type;
             type Entry = #context_entry;
             type Error = ::ttt::DefaultError;
 
             fn synth(&self,
                 __ttt_context: &#context_type,
+                #sig_name: &#signature_type,
             ) -> ::core::result::Result<#attr_type, Self::Error> {
-                match self {
-                    #synth_impl
-                }
+                #synth_impl
             }
         }
     })
@@ -371,6 +462,8 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
     let attr_type = &instance.attr_type;
     let context_entry = &instance.context_entry;
     let context_type = &instance.context;
+    let signature_type = &instance.signature;
+    let sig_name = signature_name();
 
     let synth_impl = input.each_variant(|variant| {
         let ctx_name = ctx_name();
@@ -383,6 +476,7 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             };
             synth.generate_match(
                 bindings,
+                variant,
                 &instance.context,
                 &instance.context_entry,
             )
@@ -390,9 +484,25 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             quote! {
                 ::core::result::Result::Ok(::core::option::Option::None)
             }
+        } else if let Some((body, value)) = let_binding(variant) {
+            let definition = auto_deref(value);
+            let body = auto_deref(body);
+            let extend_ctx = extend_ctx_with_definition(&instance);
+            quote! {{
+                let __ttt_definition = #definition;
+                match ::ttt::PartialSynthAttribute::<#attr_type>::try_synth(__ttt_definition, #ctx_name, #sig_name)? {
+                    ::core::option::Option::Some(__ttt_definition_ty) => {
+                        #extend_ctx
+                        ::ttt::PartialSynthAttribute::<#attr_type>::try_synth(#body, #ctx_name, #sig_name)
+                    }
+                    ::core::option::Option::None => {
+                        ::core::result::Result::Ok(::core::option::Option::None)
+                    }
+                }
+            }}
         } else if let Some(node) = opt_single_binding(variant) {
             quote! {
-                #node.try_synth(#ctx_name)
+                #node.try_synth(#ctx_name, #sig_name)
             }
         } else {
             quote! {
@@ -400,6 +510,14 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             }
         }
     });
+    let synth_impl = guard_stack(
+        input,
+        quote! {
+            match self {
+                #synth_impl
+            }
+        },
+    );
 
     let check_impl = input.each_variant(|variant| {
         let ctx_name = ctx_name();
@@ -415,7 +533,7 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
                     #attr_val
                 )
             };
-            check.generate_match(bindings, &instance.context, &instance.context_entry)
+            check.generate_match(bindings, variant, &instance.context, &instance.context_entry)
         } else if let Some(synth) = opt_bidir_synth_clause(variant, attr_type) {
             let bindings = variant
                 .bindings()
@@ -424,7 +542,7 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             let bindings = quote! {
                 ( #(#bindings),* )
             };
-            let synth_expr = synth.generate_match(bindings, &instance.context, &instance.context_entry);
+            let synth_expr = synth.generate_match(bindings, variant, &instance.context, &instance.context_entry);
             let synth_expr = quote! {
                 match {#synth_expr}? {
                     ::core::option::Option::Some(__ttt_param) => __ttt_param,
@@ -433,12 +551,28 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             };
             quote! {
                 ::core::result::Result::Ok(
-                    ::ttt::ContextualEq::<#context_entry, #context_type>::equiv(#ctx_name, #attr_val, &#synth_expr)?
+                    ::ttt::ContextualEq::<#context_entry, #context_type, #signature_type>::equiv(#ctx_name, #sig_name, #attr_val, &#synth_expr)?
                 )
             }
+        } else if let Some((body, value)) = let_binding(variant) {
+            let definition = auto_deref(value);
+            let body = auto_deref(body);
+            let extend_ctx = extend_ctx_with_definition(&instance);
+            quote! {{
+                let __ttt_definition = #definition;
+                match ::ttt::PartialSynthAttribute::<#attr_type>::try_synth(__ttt_definition, #ctx_name, #sig_name)? {
+                    ::core::option::Option::Some(__ttt_definition_ty) => {
+                        #extend_ctx
+                        ::ttt::CheckAttribute::<#attr_type>::check(#body, #attr_val, #ctx_name, #sig_name)
+                    }
+                    ::core::option::Option::None => ::core::result::Result::Err(
+                        ::ttt::DefaultError::UnsynthesizedDefinition,
+                    ),
+                }
+            }}
         } else if let Some(node) = opt_single_binding(variant) {
             quote! {
-                #node.check(__ttt_check_value, #ctx_name)
+                #node.check(__ttt_check_value, #ctx_name, #sig_name)
             }
         } else {
             abort! {
@@ -447,24 +581,34 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             }
         }
     });
+    let check_impl = guard_stack(
+        input,
+        quote! {
+            match self {
+                #check_impl
+            }
+        },
+    );
 
     input.gen_impl(quote! {
         gen impl ::ttt::PartialSynthAttribute<#attr_type> for @Self {
             type Ctx = #context_type;
+            type Sig = #signature_type;
             type Entry = #context_entry;
             type Error = ::ttt::DefaultError;
 
-            fn try_synth(&self, __ttt_context: &#context_type)
-                -> ::core::result::Result<::core::option::Option<#attr_type>, Self::Error>
+            fn try_synth(&self,
+                __ttt_context: &#context_type,
+                #sig_name: &#signature_type,
+            ) -> ::core::result::Result<::core::option::Option<#attr_type>, Self::Error>
             {
-                match self {
-                    #synth_impl
-                }
+                #synth_impl
             }
         }
 
         gen impl ::ttt::CheckAttribute<#attr_type> for @Self {
             type Ctx = #context_type;
+            type Sig = #signature_type;
             type Entry = #context_entry;
             type Error = ::ttt::DefaultError;
             type Check = bool;
@@ -472,15 +616,15 @@ fn derive_bidir_one(input: &Structure, instance: AttrSpec) -> TokenStream {
             fn check(&self,
                 __ttt_check_value: &#attr_type,
                 __ttt_context: &#context_type,
+                #sig_name: &#signature_type,
             ) -> ::core::result::Result<Self::Check, Self::Error> {
-                match self {
-                    #check_impl
-                }
+                #check_impl
             }
         }
 
         gen impl ::ttt::BidirAttribute<#attr_type> for @Self {
             type Ctx = #context_type;
+            type Sig = #signature_type;
             type Entry = #context_entry;
             type Error = ::ttt::DefaultError;
         }
@@ -18,6 +18,14 @@ fn context_name() -> Ident {
     }
 }
 
+/// The parameter holding the signature of global declarations in derived
+/// attribute implementations, which the DSL passes on unchanged.
+pub fn signature_name() -> Ident {
+    parse_quote! {
+        __ttt_signature
+    }
+}
+
 // HACK
 // `bind` expressions should be parsed the same as while expressions.
 //
@@ -49,6 +57,9 @@ struct DslParams {
     entry_type: Type,
     context: Expr,
     attr_type: Type,
+    /// The `#[binding_name]` field of the variant, which names the
+    /// variables added to the context by `bind` expressions.
+    binding_name: Option<TokenStream>,
 }
 
 impl DslParams {
@@ -59,7 +70,7 @@ impl DslParams {
             brace_token: token::Brace(span),
             stmts: body,
         };
-        let body = self.fold_block(body);
+        let body = self.fold_block(body).stmts;
 
         let input_ctx = &self.context;
         let ctx_name = context_name();
@@ -67,13 +78,14 @@ impl DslParams {
         quote! {
             {
                 let #ctx_name = #input_ctx;
-                #body
+                #(#body)*
             }
         }
     }
 
     fn expand_try_synth_expr(&self, synth_call: syn::ExprCall) -> Expr {
         let ctx_name = context_name();
+        let sig_name = signature_name();
         let span = synth_call.span();
         match synth_call.args.len() {
             1 => {
@@ -84,7 +96,7 @@ impl DslParams {
                     quote!(::ttt::PartialSynthAttribute::<#attr_type>);
                 let arg = auto_deref(&synth_call.args);
                 parse_quote_spanned! { span =>
-                    #synth_trait_expr::try_synth(#arg, #ctx_name)?
+                    #synth_trait_expr::try_synth(#arg, #ctx_name, #sig_name)?
                 }
             }
             // Allow specifying attribute type as parameter in synth call
@@ -95,7 +107,7 @@ impl DslParams {
                 let expr = auto_deref(synth_call.args.last());
 
                 parse_quote_spanned! { span =>
-                    #synth_trait_expr::try_synth(#expr, #ctx_name)?
+                    #synth_trait_expr::try_synth(#expr, #ctx_name, #sig_name)?
                 }
             }
             _ => abort!(
@@ -107,6 +119,7 @@ impl DslParams {
 
     fn expand_synth_expr(&self, synth_call: syn::ExprCall) -> Expr {
         let ctx_name = context_name();
+        let sig_name = signature_name();
         let span = synth_call.span();
         // let synth_trait = &quote!(::ttt::SynthAttribute<#attr_type>);
         match synth_call.args.len() {
@@ -118,7 +131,7 @@ impl DslParams {
                     quote!(::ttt::SynthAttribute::<#attr_type>);
                 let arg = auto_deref(&synth_call.args);
                 parse_quote_spanned! { span =>
-                    #synth_trait_expr::synth(#arg, #ctx_name)?
+                    #synth_trait_expr::synth(#arg, #ctx_name, #sig_name)?
                 }
             }
             // Allow specifying attribute type as parameter in synth call
@@ -128,7 +141,7 @@ impl DslParams {
                     quote!(::ttt::SynthAttribute::<#attr_type>);
                 let expr = auto_deref(synth_call.args.last());
                 parse_quote_spanned! { span =>
-                    #synth_trait_expr::synth(#expr, #ctx_name)?
+                    #synth_trait_expr::synth(#expr, #ctx_name, #sig_name)?
                 }
             }
             _ => abort!(
@@ -140,6 +153,7 @@ impl DslParams {
 
     fn expand_check_expr(&self, check_call: syn::ExprCall) -> Expr {
         let ctx_name = context_name();
+        let sig_name = signature_name();
         let attr_ty = &self.attr_type;
         let span = check_call.span();
         // let check_trait = quote!(::ttt::CheckAttribute<#attr_ty>);
@@ -156,7 +170,7 @@ impl DslParams {
                 // the compiler fails to infer this, I think due to
                 // https://github.com/rust-lang/rust/issues/136856
                 parse_quote_spanned! { span =>
-                    ::ttt::CheckAttribute::<#attr_ty>::check(#arg1, #arg2, #ctx_name)?
+                    ::ttt::CheckAttribute::<#attr_ty>::check(#arg1, #arg2, #ctx_name, #sig_name)?
                 }
             }
             3 => {
@@ -172,7 +186,7 @@ impl DslParams {
                 // the compiler fails to infer this, I think due to
                 // https://github.com/rust-lang/rust/issues/136856
                 parse_quote_spanned! { span =>
-                    ::ttt::CheckAttribute::<#attr_ty>::check(#arg1, #arg2, #ctx_name)?
+                    ::ttt::CheckAttribute::<#attr_ty>::check(#arg1, #arg2, #ctx_name, #sig_name)?
                 }
             }
 
@@ -191,18 +205,15 @@ impl DslParams {
                 let mut args = lookup_call.args.into_iter();
                 let arg = args.next().unwrap();
 
-                // TODO: Error handling
                 parse_quote_spanned! { span => {
                     let arg = #arg;
-                    let entry = <#ctx_ty as ::ttt::Context<#entry_ty>>::get(#ctx_name, arg);
+                    let entry = <#ctx_ty as ::ttt::Context<#entry_ty>>::lookup(#ctx_name, arg)?;
                     ::ttt::spez::spez! {
                         for __ttt_context = (entry, arg);
-                        match<Entry: DeBruijnIndexed> (Option<Entry>, usize) -> Option<Entry> {
-                            __ttt_context.0.map(|entry| {
-                                ::ttt::DeBruijnIndexed::increment_indices_by(&entry, __ttt_context.1)
-                            })
+                        match<Entry: DeBruijnIndexed> (Entry, usize) -> Entry {
+                            ::ttt::DeBruijnIndexed::increment_indices_by(&__ttt_context.0, __ttt_context.1)
                         }
-                        match<Entry> (Option<Entry>, usize) -> Option<Entry> {
+                        match<Entry> (Entry, usize) -> Entry {
                             __ttt_context.0
                         }
                     }
@@ -212,6 +223,22 @@ impl DslParams {
         }
     }
 
+    fn expand_global_expr(&self, global_call: syn::ExprCall) -> Expr {
+        let sig_name = signature_name();
+        let span = global_call.span();
+        match global_call.args.len() {
+            1 => {
+                let name = global_call.args.first().unwrap();
+                parse_quote_spanned! { span =>
+                    ::core::clone::Clone::clone(
+                        ::ttt::Signature::lookup(#sig_name, #name)?
+                    )
+                }
+            }
+            _ => abort!(span, "`global` call should have exactly 1 parameter"),
+        }
+    }
+
     fn expand_bind_expr(&self, bind_expr: syn::ExprWhile) -> syn::Expr {
         let span = bind_expr.span();
 
@@ -226,12 +253,39 @@ impl DslParams {
         let context_ty = &self.context_type;
         // let attr_ty = self
         let context = context_name();
+        let Some(binding_name) = &self.binding_name else {
+            return parse_quote_spanned! { span =>
+                #(#attrs)*
+                #label
+                {
+                    let #context =
+                        &<#context_ty as ::ttt::Context<_>>::append(&#context, ::core::clone::Clone::clone(&#bindee));
+                    #body
+                }
+            };
+        };
+
+        // Only fields holding a single name are recorded, as fields under
+        // several binders hold a collection of names.
         parse_quote_spanned! { span =>
             #(#attrs)*
             #label
             {
-                let #context =
-                    &<#context_ty as ::ttt::Context<_>>::append(&#context, ::core::clone::Clone::clone(&#bindee));
+                let __ttt_bound_name = ::ttt::spez::spez! {
+                    for __ttt_name = #binding_name;
+                    match<T: ::core::convert::AsRef<str>> &T -> ::core::option::Option<::std::string::String> {
+                        ::core::option::Option::Some(::std::string::ToString::to_string(::core::convert::AsRef::<str>::as_ref(__ttt_name)))
+                    }
+                    match<T> T -> ::core::option::Option<::std::string::String> {
+                        ::core::option::Option::None
+                    }
+                };
+                let #context = &match __ttt_bound_name {
+                    ::core::option::Option::Some(__ttt_name) =>
+                        <#context_ty as ::ttt::Context<_>>::append_named(&#context, &__ttt_name, ::core::clone::Clone::clone(&#bindee)),
+                    ::core::option::Option::None =>
+                        <#context_ty as ::ttt::Context<_>>::append(&#context, ::core::clone::Clone::clone(&#bindee)),
+                };
                 #body
             }
         }
@@ -264,6 +318,7 @@ impl syn::fold::Fold for DslParams {
                 try_synth => self.expand_try_synth_expr(call_expr),
                 check => self.expand_check_expr(call_expr),
                 lookup => self.expand_lookup_expr(call_expr),
+                global => self.expand_global_expr(call_expr),
                 _ => Expr::Call(call_expr),
             },
             expr => expr,
@@ -340,6 +395,7 @@ pub fn instantiate_dsl(
     context: &Expr,
     attr_type: &Type,
     entry_type: &Type,
+    binding_name: Option<TokenStream>,
     body: impl ToTokens,
 ) -> TokenStream {
     let body = unwrap_parsed!(
@@ -350,6 +406,7 @@ pub fn instantiate_dsl(
         context: context.clone(),
         attr_type: attr_type.clone(),
         entry_type: entry_type.clone(),
+        binding_name,
     }
     .expand_body(body)
 }
@@ -1,15 +1,33 @@
+use proc_macro_error2::abort;
+use syn::{Ident, punctuated::Punctuated, token::Comma};
+use synstructure::Structure;
+
 use crate::utils::attributes::HasAttributes;
 
 pub const METADATA_ATTR: &str = "metadata";
 pub const VAR_NAME_ATTR: &str = "var_name";
 pub const BINDING_ATTR: &str = "binding";
 pub const BINDING_NAME_ATTR: &str = "binding_name";
+pub const LET_BINDING_ATTR: &str = "let_binding";
+pub const GLOBAL_REF_ATTR: &str = "global_ref";
+pub const TELESCOPE_ATTR: &str = "telescope";
 pub const DEBRUIJN_VAR_ATTR: &str = "var_index";
+pub const DEBRUIJN_LEVEL_ATTR: &str = "var_level";
 pub const VAR_WRAPPER_ATTR: &str = "variable";
 pub const SUBST_TYPES_ATTR: &str = "subst_types";
+pub const SUBST_INFALLIBLE_ATTR: &str = "subst_infallible";
+pub const SUBST_ERROR_ATTR: &str = "subst_error_type";
+pub const SUBST_FUNC_ATTR: &str = "subst_with";
+
+// Options shared by every derive, given as `#[ttt(option, ...)]`
+pub const TTT_ATTR: &str = "ttt";
+pub const STACK_SAFE_OPTION: &str = "stack_safe";
+pub const IN_PLACE_OPTION: &str = "in_place";
+const TTT_OPTIONS: &[&str] = &[STACK_SAFE_OPTION, IN_PLACE_OPTION];
 
 // Evaluate
 pub const CONTEXT_TYPE_ATTR: &str = "context_type";
+pub const SIGNATURE_TYPE_ATTR: &str = "signature_type";
 pub const EVAL_TARGET_ATTR: &str = "eval_target";
 pub const EVAL_ERROR_ATTR: &str = "eval_error_type";
 pub const EVAL_FUNC_ATTR: &str = "evaluate_with";
@@ -25,5 +43,37 @@ impl<T: HasAttributes> IsMetadata for T {
         self.has_attribute(METADATA_ATTR)
             || self.has_attribute(VAR_NAME_ATTR)
             || self.has_attribute(BINDING_NAME_ATTR)
+            || self.has_attribute(GLOBAL_REF_ATTR)
+    }
+}
+
+/// The options given in `#[ttt(...)]` attributes on the deriving type.
+fn ttt_options(ast: &Structure) -> Vec<Ident> {
+    let mut options = Vec::new();
+    for attr in ast.find_all_attributes(TTT_ATTR) {
+        let parsed =
+            attr.parse_args_with(Punctuated::<Ident, Comma>::parse_terminated);
+        match parsed {
+            Ok(parsed) => options.extend(parsed),
+            Err(e) => abort!(e.span(),
+                "Error while parsing `#[{}(...)]` attribute", TTT_ATTR;
+                note = "{}", e;
+                help = "Expected a list of options, such as `#[{}({})]`", TTT_ATTR, STACK_SAFE_OPTION;
+            ),
+        }
+    }
+    for option in &options {
+        if !TTT_OPTIONS.iter().any(|known| option == known) {
+            abort!(option.span(),
+                "Unknown option `{}` in `#[{}(...)]` attribute", option, TTT_ATTR;
+                help = "The supported options are: {}", TTT_OPTIONS.join(", ");
+            )
+        }
     }
+    options
+}
+
+/// Whether the deriving type is annotated with `#[ttt(option)]`.
+pub fn has_ttt_option(ast: &Structure, option: &str) -> bool {
+    ttt_options(ast).iter().any(|given| given == option)
 }
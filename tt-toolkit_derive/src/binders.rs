@@ -0,0 +1,344 @@
+use proc_macro_error2::abort;
+use proc_macro2::TokenStream;
+use quote::{ToTokens, quote};
+use structmeta::StructMeta;
+use syn::{
+    Expr, ExprField, ExprLit, Lit, Member, Type, fold::Fold, parse_quote,
+    spanned::Spanned,
+};
+use synstructure::{BindingInfo, VariantInfo};
+
+use crate::attributes::{BINDING_ATTR, LET_BINDING_ATTR, TELESCOPE_ATTR};
+use crate::sorts::sort_value;
+use crate::utils::attributes::HasAttributes;
+
+/// Arguments accepted by the `#[binding(...)]` attribute.
+///
+/// The number of variables bound by a field can be given either as a bare
+/// expression, as in `#[binding(2)]`, or with the `count` key, as in
+/// `#[binding(count = self.arity)]`.
+/// The sort of the bound variables can be given with the `sort` key, as in
+/// `#[binding(sort = Ty)]`.
+#[derive(StructMeta)]
+struct BindingArgs {
+    #[struct_meta(unnamed)]
+    count_expr: Option<Expr>,
+    count: Option<Expr>,
+    sort: Option<Type>,
+}
+
+fn binding_args(binding: &BindingInfo) -> Option<BindingArgs> {
+    let attr = binding.find_attribute(BINDING_ATTR)?;
+    if attr.meta.require_path_only().is_ok() {
+        return None;
+    }
+
+    match attr.parse_args::<BindingArgs>() {
+        Ok(args) => Some(args),
+        Err(e) => abort!(e.span(),
+            "Error while parsing `#[{}(...)]` attribute", BINDING_ATTR;
+            note = "{}", e;
+            help = "Expected a number of bound variables, such as `#[{0}(2)]` or `#[{0}(count = self.field)]`, or a sort, such as `#[{0}(sort = Ty)]`", BINDING_ATTR;
+        ),
+    }
+}
+
+/// Arguments accepted by the `#[let_binding(...)]` attribute, which names the
+/// field holding the definition of the bound variable, as in
+/// `#[let_binding(value = definition)]`.
+#[derive(StructMeta)]
+struct LetBindingArgs {
+    value: Member,
+}
+
+/// For a `#[let_binding(value = field)]` field, which binds one variable
+/// defined by another field of the variant, returns that other field.
+pub fn let_binding_value<'a>(
+    variant: &'a VariantInfo,
+    binding: &BindingInfo,
+) -> Option<&'a BindingInfo<'a>> {
+    let attr = binding.find_attribute(LET_BINDING_ATTR)?;
+    let args = match attr.parse_args::<LetBindingArgs>() {
+        Ok(args) => args,
+        Err(e) => abort!(e.span(),
+            "Error while parsing `#[{}(...)]` attribute", LET_BINDING_ATTR;
+            note = "{}", e;
+            help = "Expected the field holding the definition, such as `#[{}(value = field)]`", LET_BINDING_ATTR;
+        ),
+    };
+    if binding.has_attribute(BINDING_ATTR)
+        || binding.has_attribute(TELESCOPE_ATTR)
+    {
+        abort!(
+            attr.span(),
+            "A #[{}] field cannot also be a #[{}] or #[{}] field",
+            LET_BINDING_ATTR,
+            BINDING_ATTR,
+            TELESCOPE_ATTR
+        )
+    }
+    if !preceding_telescopes(variant, binding).is_empty() {
+        abort!(
+            attr.span(),
+            "A #[{}] field cannot follow a #[{}] field",
+            LET_BINDING_ATTR,
+            TELESCOPE_ATTR
+        )
+    }
+
+    match sibling(variant, &args.value) {
+        Some(value) if value != binding => Some(value),
+        Some(_) => abort!(
+            args.value.span(),
+            "A #[{}] field cannot hold its own definition",
+            LET_BINDING_ATTR
+        ),
+        None => abort!(
+            args.value.span(),
+            "No field `{}` in variant `{}`",
+            args.value.to_token_stream(),
+            variant.ast().ident
+        ),
+    }
+}
+
+/// The `#[let_binding]` field of a variant, along with the field holding
+/// the definition of the variable it binds.
+pub fn let_binding<'a>(
+    variant: &'a VariantInfo,
+) -> Option<(&'a BindingInfo<'a>, &'a BindingInfo<'a>)> {
+    variant.bindings().iter().find_map(|binding| {
+        let_binding_value(variant, binding).map(|value| (binding, value))
+    })
+}
+
+/// Rewrites references to `self.<field>` in a binder count expression into
+/// references to the matching binding in the current variant.
+struct SiblingFields<'a> {
+    variant: &'a VariantInfo<'a>,
+}
+
+/// The field of `variant` named by `member`.
+fn sibling<'a>(
+    variant: &'a VariantInfo,
+    member: &Member,
+) -> Option<&'a BindingInfo<'a>> {
+    variant
+        .bindings()
+        .iter()
+        .enumerate()
+        .find_map(|(i, binding)| {
+            let matches = match (member, &binding.ast().ident) {
+                (Member::Named(name), Some(ident)) => name == ident,
+                (Member::Unnamed(index), None) => index.index as usize == i,
+                _ => false,
+            };
+            matches.then_some(binding)
+        })
+}
+
+impl Fold for SiblingFields<'_> {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Field(ExprField { base, member, .. }) if matches!(base.as_ref(), Expr::Path(path) if path.path.is_ident("self")) =>
+            {
+                let Some(binding) = sibling(self.variant, &member) else {
+                    abort!(
+                        member.span(),
+                        "No field `{}` in variant `{}`",
+                        member.to_token_stream(),
+                        self.variant.ast().ident;
+                        note = "Binder counts may only refer to fields of the variant containing the #[{}] field", BINDING_ATTR;
+                    )
+                };
+                parse_quote!((*#binding))
+            }
+            expr => syn::fold::fold_expr(self, expr),
+        }
+    }
+}
+
+/// Whether the `#[binding]` attribute on this field specifies how many
+/// variables it binds, rather than using the default of one. A count written
+/// as the literal `1` is treated like the default.
+pub fn has_explicit_binder_count(binding: &BindingInfo) -> bool {
+    let Some(args) = binding_args(binding) else {
+        return false;
+    };
+    args.count_expr
+        .or(args.count)
+        .is_some_and(|count| !is_literal_one(&count))
+}
+
+fn is_literal_one(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(int), ..
+        }) => int.base10_digits() == "1",
+        _ => false,
+    }
+}
+
+/// The sort of the variables bound by the `#[binding]` attribute on this
+/// field, if it specifies one.
+pub fn binder_sort(binding: &BindingInfo) -> Option<Type> {
+    binding_args(binding)?.sort
+}
+
+/// The number of variables bound by the `#[binding]` attribute on this field,
+/// if it specifies one.
+fn explicit_binder_count(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> Option<TokenStream> {
+    let args = binding_args(binding)?;
+    let count = match (args.count_expr, args.count) {
+        (Some(count), None) | (None, Some(count)) => count,
+        (None, None) => return None,
+        (Some(_), Some(count)) => abort!(
+            count.span(),
+            "The number of bound variables was specified twice"
+        ),
+    };
+    let count = SiblingFields { variant }.fold_expr(count);
+
+    Some(quote! {{
+        let __ttt_count: usize = #count;
+        __ttt_count
+    }})
+}
+
+/// Returns an expression of type `usize` giving the number of variables bound
+/// by a `#[telescope]` field.
+/// The binding is reborrowed, so it may still be used afterwards if it is a
+/// mutable reference.
+pub fn telescope_len(binding: &BindingInfo) -> TokenStream {
+    quote! {
+        ::std::iter::Iterator::count(
+            ::std::iter::IntoIterator::into_iter(&*#binding)
+        )
+    }
+}
+
+pub fn is_telescope(binding: &BindingInfo) -> bool {
+    if binding.has_attribute(TELESCOPE_ATTR)
+        && binding.has_attribute(BINDING_ATTR)
+    {
+        abort!(
+            binding.ast().span(),
+            "A field cannot be annotated with both #[{}] and #[{}]",
+            TELESCOPE_ATTR,
+            BINDING_ATTR
+        )
+    }
+    binding.has_attribute(TELESCOPE_ATTR)
+}
+
+pub fn variant_has_telescope(variant: &VariantInfo) -> bool {
+    variant.bindings().iter().any(is_telescope)
+}
+
+/// The telescopes which appear in `variant` before the given field.
+fn preceding_telescopes<'a>(
+    variant: &'a VariantInfo,
+    binding: &BindingInfo,
+) -> Vec<&'a BindingInfo<'a>> {
+    variant
+        .bindings()
+        .iter()
+        .take_while(|other| *other != binding)
+        .filter(|other| is_telescope(other))
+        .collect()
+}
+
+/// Returns an expression of type `usize` giving the number of variables bound
+/// over the field, or `None` if the field is not annotated with `#[binding]`
+/// or `#[let_binding]`.
+///
+/// A `#[binding]` field which follows a `#[telescope]` is under all of the
+/// variables bound by the telescope, in addition to any count given explicitly
+/// in its own attribute. A plain `#[binding]` there binds no variables of its
+/// own, so it counts only the telescope.
+///
+/// Any references to `self.<field>` in the count are resolved against the
+/// bindings of `variant`, so the expression is only valid inside the match
+/// arm for that variant.
+pub fn binder_count(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> Option<TokenStream> {
+    sorted_binder_count(variant, binding, None)
+}
+
+/// Like [`binder_count`], but only counts the variables bound by the field's
+/// own `#[binding]` attribute if they share an index space with the runtime
+/// `Option<::ttt::Sort>` value `sort`.
+/// Telescopes bind variables of every sort.
+pub fn sorted_binder_count(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+    sort: Option<&TokenStream>,
+) -> Option<TokenStream> {
+    if binding.has_attribute(LET_BINDING_ATTR) {
+        return Some(quote!(1usize));
+    }
+    if !binding.has_attribute(BINDING_ATTR) {
+        return None;
+    }
+
+    let telescopes = preceding_telescopes(variant, binding);
+    let mut own_count =
+        explicit_binder_count(variant, binding).unwrap_or_else(|| {
+            if telescopes.is_empty() {
+                quote!(1usize)
+            } else {
+                quote!(0usize)
+            }
+        });
+    if let (Some(sort), Some(binder_sort)) = (sort, binder_sort(binding)) {
+        if !telescopes.is_empty() {
+            abort!(
+                binder_sort.span(),
+                "A #[{}] field with a sort cannot follow a #[{}] field",
+                BINDING_ATTR,
+                TELESCOPE_ATTR;
+                note = "The variables bound by a telescope have no sort"
+            )
+        }
+        let binder_sort = sort_value(Some(&binder_sort));
+        own_count = quote! {
+            if ::ttt::Sort::overlap(#binder_sort, #sort) {
+                #own_count
+            } else {
+                0usize
+            }
+        };
+    }
+    let telescope_lens = telescopes.into_iter().map(telescope_len);
+
+    Some(quote! {
+        (#own_count #(+ #telescope_lens)*)
+    })
+}
+
+/// For a `#[telescope]` field, returns an expression of type `usize` giving
+/// the number of variables bound over the first element of the telescope.
+/// Each later element is under one more variable than the element before it.
+///
+/// Returns `None` if the field is not a telescope.
+pub fn telescope_offset(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> Option<TokenStream> {
+    if !is_telescope(binding) {
+        return None;
+    }
+
+    let telescope_lens = preceding_telescopes(variant, binding)
+        .into_iter()
+        .map(telescope_len);
+
+    Some(quote! {
+        (0usize #(+ #telescope_lens)*)
+    })
+}
@@ -1,80 +1,832 @@
 use crate::utils::*;
-use proc_macro_error2::abort;
 use proc_macro2::TokenStream;
-use quote::{ToTokens, quote, quote_spanned};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{Ident, parse_quote, spanned::Spanned};
 use synstructure::{BindingInfo, Structure, VariantInfo};
 
 use crate::attributes::*;
+use crate::binders::{binder_count, sorted_binder_count, telescope_offset};
+use crate::sorts::{sort_value, var_sort};
+use crate::stack_safe::guard_stack;
 use crate::utils::attributes::HasAttributes;
 
-fn ensure_can_be_debruijn_var(binding: &BindingInfo) {
-    let attr = binding.find_attribute(DEBRUIJN_VAR_ATTR).unwrap();
+/// The value of a `#[var_index]` or `#[var_level]` field as a `usize`.
+pub fn var_value(binding: &BindingInfo) -> TokenStream {
     let binding_ty = &binding.ast().ty;
-    if *binding_ty != parse_quote!(usize) {
-        abort!(
-            attr.span(),
-            "Type `{}` cannot be used as a variable index",
-            binding_ty.to_token_stream();
-            note = "The #[{}] annotation should only be used with fields of type usize.", DEBRUIJN_VAR_ATTR;
+    quote_spanned! { binding.ast().ty.span() =>
+        <#binding_ty as ::ttt::VarIndex>::to_index(*#binding)
+    }
+}
 
-            help = "Maybe you meant to use the #[{}] annotation instead", VAR_WRAPPER_ATTR;
-        );
+/// Converts a `usize` to the type of a `#[var_index]` or `#[var_level]` field.
+fn var_from_value(binding: &BindingInfo, value: TokenStream) -> TokenStream {
+    let binding_ty = &binding.ast().ty;
+    quote_spanned! { binding.ast().ty.span() =>
+        <#binding_ty as ::ttt::VarIndex>::from_index(#value)
     }
 }
 
+fn map_fn_type_param() -> Ident {
+    parse_quote!(__TTT_F)
+}
+
+fn map_fn_param() -> Ident {
+    parse_quote!(__ttt_map_fn)
+}
+
+fn start_param() -> Ident {
+    parse_quote!(__ttt_start)
+}
+
+fn sort_param() -> Ident {
+    parse_quote!(__ttt_sort)
+}
+
 fn map_indices_impl(mut ast: Structure) -> TokenStream {
     ast.bind_with(|_| synstructure::BindStyle::Move);
 
-    let func_type_name: Ident = parse_quote!(__TTT_F);
-    let func_var_name: Ident = parse_quote!(__ttt_map_fn);
-    let index_var_name: Ident = parse_quote!(__ttt_start);
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
 
-    let match_body = ast.each_construct(|binding| {
-        let binding_ty = &binding.ast().ty;
-        let recursive_call = |index_value| {
-            quote_spanned! { binding.span() =>
-                <#binding_ty as ::ttt::DeBruijnIndexed>::map_indices_from(
-                        #binding,
-                        #index_value,
-                        <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+    let match_body = ast.each_variant(|variant| {
+        variant.construct_from_bindings(|binding| {
+            map_indices_field_impl(variant, binding)
+        })
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match self {
+                #match_body
             }
-        };
+        },
+    );
 
-        if binding.is_metadata() {
-            // This field is metadata (such as the string representation of a variable)
-            // so we leave it untouched.
-            quote! {
-                ::std::clone::Clone::clone(#binding)
-            }
-        } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
-            ensure_can_be_debruijn_var(binding);
-            // This field is a raw debruijn variable, so we directly modify it with the map function
-            quote_spanned! { binding.span() =>
-                if *#binding >= #index_var_name {
-                    #func_var_name(*#binding)
+    quote! {
+        fn map_sort_indices_from<#func_type_name>(&self, #sort_var_name: ::std::option::Option<::ttt::Sort>, #index_var_name: usize, #func_var_name: #func_type_name) -> Self
+        where
+            #func_type_name: Fn(usize) -> usize + Clone
+        {
+            #body
+        }
+    }
+}
+
+fn map_indices_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> TokenStream {
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let binding_ty = &binding.ast().ty;
+    let recursive_call = |index_value| {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::map_sort_indices_from(
+                    #binding,
+                    #sort_var_name,
+                    #index_value,
+                    <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+        }
+    };
+
+    if binding.is_metadata() {
+        // This field is metadata (such as the string representation of a variable)
+        // so we leave it untouched.
+        quote! {
+            ::std::clone::Clone::clone(#binding)
+        }
+    } else if binding.has_attribute(DEBRUIJN_LEVEL_ATTR) {
+        // Levels count from the root of the context, so are unaffected by
+        // changes to the indices of variables.
+        var_from_value(binding, var_value(binding))
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
+        // This field is a raw debruijn variable, so we directly modify it with the map function
+        // if it is of the sort being mapped.
+        let sort_check = var_sort(binding).map(|var_sort| {
+            let var_sort = sort_value(Some(&var_sort));
+            quote!(::ttt::Sort::overlap(#var_sort, #sort_var_name) &&)
+        });
+        let index = var_value(binding);
+        let mapped =
+            var_from_value(binding, quote!(#func_var_name(__ttt_index)));
+        quote_spanned! { binding.span() =>
+            {
+                let __ttt_index = #index;
+                if #sort_check __ttt_index >= #index_var_name {
+                    #mapped
                 } else {
                     *#binding
                 }
             }
-        } else if binding.has_attribute(BINDING_ATTR) {
-            // This field represents a node under a binder, so we bump the start index to account for the new variable,
-            // and make a recursive call.
-            recursive_call(quote!(#index_var_name + 1))
-        } else {
-            // This field is a regular node, so just proceed recursively
-            recursive_call(index_var_name.to_token_stream())
+        }
+    } else if let Some(offset) = telescope_offset(variant, binding) {
+        // This field is a telescope, so each element is under the binders of
+        // all of the elements before it.
+        quote_spanned! { binding.span() =>
+            ::std::iter::Iterator::collect(
+                ::std::iter::Iterator::map(
+                    ::std::iter::Iterator::enumerate(
+                        ::std::iter::IntoIterator::into_iter(#binding)
+                    ),
+                    |(__ttt_position, __ttt_elem)| {
+                        ::ttt::DeBruijnIndexed::map_sort_indices_from(
+                            __ttt_elem,
+                            #sort_var_name,
+                            #index_var_name + #offset + __ttt_position,
+                            <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+                    }
+                )
+            )
+        }
+    } else if let Some(count) = sorted_binder_count(
+        variant,
+        binding,
+        Some(&sort_var_name.to_token_stream()),
+    ) {
+        // This field represents a node under a binder, so we bump the start index to account for the new variables,
+        // and make a recursive call.
+        recursive_call(quote!(#index_var_name + #count))
+    } else {
+        // This field is a regular node, so just proceed recursively
+        recursive_call(index_var_name.to_token_stream())
+    }
+}
+
+fn map_changed_indices_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::Move);
+
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let match_body = ast.each_variant(|variant| {
+        let changed_fields = variant.bindings().iter().map(|binding| {
+            let changed = changed_ident(binding);
+            let binding_ty = &binding.ast().ty;
+            let changed_value = map_changed_field_impl(variant, binding);
+            quote! {
+                let #changed: ::std::option::Option<#binding_ty> = #changed_value;
+            }
+        });
+        if variant.bindings().is_empty() {
+            return quote!(::std::option::Option::None);
+        }
+        let any_changed = variant.bindings().iter().map(|binding| {
+            let changed = changed_ident(binding);
+            quote!(::std::option::Option::is_some(&#changed))
+        });
+        let ctor = variant.construct_from_bindings(|binding| {
+            let changed = changed_ident(binding);
+            let unchanged = clone_unchanged_field_impl(variant, binding);
+            quote! {
+                match #changed {
+                    ::std::option::Option::Some(__ttt_changed) => __ttt_changed,
+                    ::std::option::Option::None => #unchanged,
+                }
+            }
+        });
+        quote! {
+            #(#changed_fields)*
+            if #(#any_changed)||* {
+                ::std::option::Option::Some(#ctor)
+            } else {
+                ::std::option::Option::None
+            }
         }
     });
 
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match self {
+                #match_body
+            }
+        },
+    );
+
     quote! {
-        fn map_indices_from<#func_type_name>(&self, #index_var_name: usize, #func_var_name: #func_type_name) -> Self
+        fn map_changed_sort_indices_from<#func_type_name>(&self, #sort_var_name: ::std::option::Option<::ttt::Sort>, #index_var_name: usize, #func_var_name: #func_type_name) -> ::std::option::Option<Self>
         where
             #func_type_name: Fn(usize) -> usize + Clone
         {
+            #body
+        }
+    }
+}
+
+/// The name of the variable holding the mapped value of a field, if it
+/// changed.
+fn changed_ident(binding: &BindingInfo) -> Ident {
+    format_ident!("__ttt_changed_{}", binding.binding)
+}
+
+/// An expression of type `Option<T>` for a field of type `T`, which is the
+/// mapped field if any of its indices are changed by the map function.
+fn map_changed_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> TokenStream {
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let binding_ty = &binding.ast().ty;
+    let recursive_call = |index_value| {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::map_changed_sort_indices_from(
+                    #binding,
+                    #sort_var_name,
+                    #index_value,
+                    <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+        }
+    };
+
+    if binding.is_metadata() || binding.has_attribute(DEBRUIJN_LEVEL_ATTR) {
+        quote!(::std::option::Option::None)
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
+        let sort_check = var_sort(binding).map(|var_sort| {
+            let var_sort = sort_value(Some(&var_sort));
+            quote!(::ttt::Sort::overlap(#var_sort, #sort_var_name) &&)
+        });
+        let index = var_value(binding);
+        let mapped = var_from_value(binding, quote!(__ttt_mapped));
+        quote_spanned! { binding.span() =>
+            {
+                let __ttt_index = #index;
+                let __ttt_mapped = if #sort_check __ttt_index >= #index_var_name {
+                    #func_var_name(__ttt_index)
+                } else {
+                    __ttt_index
+                };
+                if __ttt_mapped != __ttt_index {
+                    ::std::option::Option::Some(#mapped)
+                } else {
+                    ::std::option::Option::None
+                }
+            }
+        }
+    } else if let Some(offset) = telescope_offset(variant, binding) {
+        // The telescope is rebuilt from the elements which changed, and
+        // copies of those which didn't.
+        quote_spanned! { binding.span() =>
+            {
+                let __ttt_changed_elems: ::std::vec::Vec<::std::option::Option<_>> =
+                    ::std::iter::Iterator::collect(
+                        ::std::iter::Iterator::map(
+                            ::std::iter::Iterator::enumerate(
+                                ::std::iter::IntoIterator::into_iter(#binding)
+                            ),
+                            |(__ttt_position, __ttt_elem)| {
+                                ::ttt::DeBruijnIndexed::map_changed_sort_indices_from(
+                                    __ttt_elem,
+                                    #sort_var_name,
+                                    #index_var_name + #offset + __ttt_position,
+                                    <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+                            }
+                        )
+                    );
+                if ::std::iter::Iterator::all(
+                    &mut ::std::iter::IntoIterator::into_iter(&__ttt_changed_elems),
+                    ::std::option::Option::is_none,
+                ) {
+                    ::std::option::Option::None
+                } else {
+                    ::std::option::Option::Some(::std::iter::Iterator::collect(
+                        ::std::iter::Iterator::map(
+                            ::std::iter::Iterator::zip(
+                                ::std::iter::IntoIterator::into_iter(__ttt_changed_elems),
+                                ::std::iter::IntoIterator::into_iter(#binding),
+                            ),
+                            |(__ttt_changed, __ttt_elem)| {
+                                ::std::option::Option::unwrap_or_else(
+                                    __ttt_changed,
+                                    || ::ttt::DeBruijnIndexed::clone_unchanged(__ttt_elem),
+                                )
+                            }
+                        )
+                    ))
+                }
+            }
+        }
+    } else if let Some(count) = sorted_binder_count(
+        variant,
+        binding,
+        Some(&sort_var_name.to_token_stream()),
+    ) {
+        recursive_call(quote!(#index_var_name + #count))
+    } else {
+        recursive_call(index_var_name.to_token_stream())
+    }
+}
+
+fn clone_unchanged_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::Move);
+
+    let match_body = ast.each_variant(|variant| {
+        variant.construct_from_bindings(|binding| {
+            clone_unchanged_field_impl(variant, binding)
+        })
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match self {
+                #match_body
+            }
+        },
+    );
+
+    quote! {
+        fn clone_unchanged(&self) -> Self {
+            #body
+        }
+    }
+}
+
+/// A copy of a field which the map function didn't change.
+fn clone_unchanged_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> TokenStream {
+    let binding_ty = &binding.ast().ty;
+    if binding.is_metadata() {
+        quote! {
+            ::std::clone::Clone::clone(#binding)
+        }
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR)
+        || binding.has_attribute(DEBRUIJN_LEVEL_ATTR)
+    {
+        quote!(*#binding)
+    } else if telescope_offset(variant, binding).is_some() {
+        quote_spanned! { binding.span() =>
+            ::std::iter::Iterator::collect(
+                ::std::iter::Iterator::map(
+                    ::std::iter::IntoIterator::into_iter(#binding),
+                    ::ttt::DeBruijnIndexed::clone_unchanged,
+                )
+            )
+        }
+    } else {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::clone_unchanged(#binding)
+        }
+    }
+}
+
+fn map_indices_in_place_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::RefMut);
+
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let match_body = ast.each_variant(|variant| {
+        let field_maps = variant
+            .bindings()
+            .iter()
+            .map(|binding| map_indices_in_place_field_impl(variant, binding));
+        quote!(#(#field_maps)*)
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match *self {
+                #match_body
+            }
+        },
+    );
+
+    quote! {
+        fn map_sort_indices_in_place_from<#func_type_name>(&mut self, #sort_var_name: ::std::option::Option<::ttt::Sort>, #index_var_name: usize, #func_var_name: #func_type_name)
+        where
+            #func_type_name: Fn(usize) -> usize + Clone
+        {
+            #body
+        }
+    }
+}
+
+fn map_indices_in_place_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> TokenStream {
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let binding_ty = &binding.ast().ty;
+    let recursive_call = |index_value| {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::map_sort_indices_in_place_from(
+                    #binding,
+                    #sort_var_name,
+                    #index_value,
+                    <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name));
+        }
+    };
+
+    if binding.is_metadata() || binding.has_attribute(DEBRUIJN_LEVEL_ATTR) {
+        quote!()
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
+        let sort_check = var_sort(binding).map(|var_sort| {
+            let var_sort = sort_value(Some(&var_sort));
+            quote!(::ttt::Sort::overlap(#var_sort, #sort_var_name) &&)
+        });
+        let index = var_value(binding);
+        let mapped =
+            var_from_value(binding, quote!(#func_var_name(__ttt_index)));
+        quote_spanned! { binding.span() =>
+            {
+                let __ttt_index = #index;
+                if #sort_check __ttt_index >= #index_var_name {
+                    *#binding = #mapped;
+                }
+            }
+        }
+    } else if let Some(offset) = telescope_offset(variant, binding) {
+        quote_spanned! { binding.span() =>
+            {
+                let __ttt_offset = #offset;
+                for (__ttt_position, __ttt_elem) in ::std::iter::Iterator::enumerate(
+                    ::std::iter::IntoIterator::into_iter(&mut *#binding)
+                ) {
+                    ::ttt::DeBruijnIndexed::map_sort_indices_in_place_from(
+                        __ttt_elem,
+                        #sort_var_name,
+                        #index_var_name + __ttt_offset + __ttt_position,
+                        <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name));
+                }
+            }
+        }
+    } else if let Some(count) = sorted_binder_count(
+        variant,
+        binding,
+        Some(&sort_var_name.to_token_stream()),
+    ) {
+        recursive_call(quote!(#index_var_name + #count))
+    } else {
+        recursive_call(index_var_name.to_token_stream())
+    }
+}
+
+fn depth_param() -> Ident {
+    parse_quote!(__ttt_depth)
+}
+
+fn map_vars_at_depth_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::Move);
+
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let depth_var_name = depth_param();
+
+    let match_body = ast.each_variant(|variant| {
+        variant.construct_from_bindings(|binding| {
+            map_vars_at_depth_field_impl(variant, binding)
+        })
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match self {
+                #match_body
+            }
+        },
+    );
+
+    quote! {
+        fn map_vars_at_depth<#func_type_name>(&self, #depth_var_name: usize, #func_var_name: #func_type_name) -> Self
+        where
+            #func_type_name: Fn(::ttt::VarKind, usize, usize) -> usize + Clone
+        {
+            #body
+        }
+    }
+}
+
+fn map_vars_at_depth_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> TokenStream {
+    let func_type_name = map_fn_type_param();
+    let func_var_name = map_fn_param();
+    let depth_var_name = depth_param();
+
+    let binding_ty = &binding.ast().ty;
+    let recursive_call = |depth_value| {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::map_vars_at_depth(
+                    #binding,
+                    #depth_value,
+                    <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+        }
+    };
+
+    if binding.is_metadata() {
+        quote! {
+            ::std::clone::Clone::clone(#binding)
+        }
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
+        let index = var_value(binding);
+        var_from_value(
+            binding,
+            quote!(#func_var_name(::ttt::VarKind::Index, #index, #depth_var_name)),
+        )
+    } else if binding.has_attribute(DEBRUIJN_LEVEL_ATTR) {
+        let level = var_value(binding);
+        var_from_value(
+            binding,
+            quote!(#func_var_name(::ttt::VarKind::Level, #level, #depth_var_name)),
+        )
+    } else if let Some(offset) = telescope_offset(variant, binding) {
+        quote_spanned! { binding.span() =>
+            ::std::iter::Iterator::collect(
+                ::std::iter::Iterator::map(
+                    ::std::iter::Iterator::enumerate(
+                        ::std::iter::IntoIterator::into_iter(#binding)
+                    ),
+                    |(__ttt_position, __ttt_elem)| {
+                        ::ttt::DeBruijnIndexed::map_vars_at_depth(
+                            __ttt_elem,
+                            #depth_var_name + #offset + __ttt_position,
+                            <#func_type_name as ::std::clone::Clone>::clone(&#func_var_name))
+                    }
+                )
+            )
+        }
+    } else if let Some(count) = binder_count(variant, binding) {
+        recursive_call(quote!(#depth_var_name + #count))
+    } else {
+        recursive_call(depth_var_name.to_token_stream())
+    }
+}
+
+fn visit_fn_param() -> Ident {
+    parse_quote!(__ttt_visit_fn)
+}
+
+fn visit_free_vars_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::Move);
+
+    let func_type_name = map_fn_type_param();
+    let func_var_name = visit_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let match_body = ast.each_variant(|variant| {
+        let field_visits = variant
+            .bindings()
+            .iter()
+            .map(|binding| visit_free_vars_field_impl(variant, binding));
+        quote! {
+            #(#field_visits)*
+            ::std::ops::ControlFlow::Continue(())
+        }
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
             match self {
                 #match_body
             }
+        },
+    );
+
+    quote! {
+        fn visit_sort_free_vars_from<#func_type_name>(&self, #sort_var_name: ::std::option::Option<::ttt::Sort>, #index_var_name: usize, #func_var_name: &mut #func_type_name) -> ::std::ops::ControlFlow<()>
+        where
+            #func_type_name: FnMut(usize) -> ::std::ops::ControlFlow<()>
+        {
+            #body
+        }
+    }
+}
+
+fn visit_free_vars_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> TokenStream {
+    let func_var_name = visit_fn_param();
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let binding_ty = &binding.ast().ty;
+    let recursive_call = |index_value| {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::visit_sort_free_vars_from(
+                #binding,
+                #sort_var_name,
+                #index_value,
+                #func_var_name,
+            )?;
+        }
+    };
+
+    if binding.is_metadata() {
+        // Metadata can't contain variables
+        quote!()
+    } else if binding.has_attribute(DEBRUIJN_LEVEL_ATTR) {
+        // Levels are not indices, so are not reported as free variables
+        quote!()
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
+        // Only indices pointing outside of the binders we have passed under are free
+        let sort_check = var_sort(binding).map(|var_sort| {
+            let var_sort = sort_value(Some(&var_sort));
+            quote!(::ttt::Sort::overlap(#var_sort, #sort_var_name) &&)
+        });
+        let index = var_value(binding);
+        quote_spanned! { binding.span() =>
+            {
+                let __ttt_index = #index;
+                if #sort_check __ttt_index >= #index_var_name {
+                    #func_var_name(__ttt_index - #index_var_name)?;
+                }
+            }
+        }
+    } else if let Some(offset) = telescope_offset(variant, binding) {
+        quote_spanned! { binding.span() =>
+            for (__ttt_position, __ttt_elem) in ::std::iter::Iterator::enumerate(
+                ::std::iter::IntoIterator::into_iter(#binding)
+            ) {
+                ::ttt::DeBruijnIndexed::visit_sort_free_vars_from(
+                    __ttt_elem,
+                    #sort_var_name,
+                    #index_var_name + #offset + __ttt_position,
+                    #func_var_name,
+                )?;
+            }
+        }
+    } else if let Some(count) = sorted_binder_count(
+        variant,
+        binding,
+        Some(&sort_var_name.to_token_stream()),
+    ) {
+        recursive_call(quote!(#index_var_name + #count))
+    } else {
+        recursive_call(index_var_name.to_token_stream())
+    }
+}
+
+fn max_free_index_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::Move);
+
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let match_body = ast.each_variant(|variant| {
+        let field_maxes = variant.bindings().iter().filter_map(|binding| {
+            let field_max = max_free_index_field_impl(variant, binding)?;
+            Some(quote! {
+                __ttt_max = ::std::cmp::max(__ttt_max, #field_max);
+            })
+        });
+        quote! {
+            let mut __ttt_max: ::std::option::Option<usize> =
+                ::std::option::Option::None;
+            #(#field_maxes)*
+            __ttt_max
+        }
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match self {
+                #match_body
+            }
+        },
+    );
+
+    quote! {
+        fn max_sort_free_index_from(&self, #sort_var_name: ::std::option::Option<::ttt::Sort>, #index_var_name: usize) -> ::std::option::Option<usize> {
+            #body
+        }
+    }
+}
+
+/// An expression of type `Option<usize>` giving the largest free index in
+/// a field, or `None` if the field can't contain free indices.
+fn max_free_index_field_impl(
+    variant: &VariantInfo,
+    binding: &BindingInfo,
+) -> Option<TokenStream> {
+    let index_var_name = start_param();
+    let sort_var_name = sort_param();
+
+    let binding_ty = &binding.ast().ty;
+    let recursive_call = |index_value| {
+        quote_spanned! { binding.span() =>
+            <#binding_ty as ::ttt::DeBruijnIndexed>::max_sort_free_index_from(
+                #binding,
+                #sort_var_name,
+                #index_value,
+            )
+        }
+    };
+
+    if binding.is_metadata() || binding.has_attribute(DEBRUIJN_LEVEL_ATTR) {
+        None
+    } else if binding.has_attribute(DEBRUIJN_VAR_ATTR) {
+        let sort_check = var_sort(binding).map(|var_sort| {
+            let var_sort = sort_value(Some(&var_sort));
+            quote!(::ttt::Sort::overlap(#var_sort, #sort_var_name) &&)
+        });
+        let index = var_value(binding);
+        Some(quote_spanned! { binding.span() =>
+            {
+                let __ttt_index = #index;
+                if #sort_check __ttt_index >= #index_var_name {
+                    ::std::option::Option::Some(__ttt_index - #index_var_name)
+                } else {
+                    ::std::option::Option::None
+                }
+            }
+        })
+    } else if let Some(offset) = telescope_offset(variant, binding) {
+        Some(quote_spanned! { binding.span() =>
+            ::std::iter::Iterator::fold(
+                ::std::iter::Iterator::enumerate(
+                    ::std::iter::IntoIterator::into_iter(#binding)
+                ),
+                ::std::option::Option::None,
+                |__ttt_max, (__ttt_position, __ttt_elem)| {
+                    ::std::cmp::max(
+                        __ttt_max,
+                        ::ttt::DeBruijnIndexed::max_sort_free_index_from(
+                            __ttt_elem,
+                            #sort_var_name,
+                            #index_var_name + #offset + __ttt_position,
+                        ),
+                    )
+                }
+            )
+        })
+    } else if let Some(count) = sorted_binder_count(
+        variant,
+        binding,
+        Some(&sort_var_name.to_token_stream()),
+    ) {
+        Some(recursive_call(quote!(#index_var_name + #count)))
+    } else {
+        Some(recursive_call(index_var_name.to_token_stream()))
+    }
+}
+
+fn interned_size_impl(mut ast: Structure) -> TokenStream {
+    ast.bind_with(|_| synstructure::BindStyle::Move);
+
+    let match_body = ast.each_variant(|variant| {
+        let field_sizes = variant.bindings().iter().filter_map(|binding| {
+            if binding.is_metadata()
+                || binding.has_attribute(DEBRUIJN_VAR_ATTR)
+                || binding.has_attribute(DEBRUIJN_LEVEL_ATTR)
+            {
+                None
+            } else if telescope_offset(variant, binding).is_some() {
+                Some(quote_spanned! { binding.span() =>
+                    ::std::iter::Iterator::sum::<usize>(
+                        ::std::iter::Iterator::map(
+                            ::std::iter::IntoIterator::into_iter(#binding),
+                            ::ttt::DeBruijnIndexed::interned_size,
+                        )
+                    )
+                })
+            } else {
+                let binding_ty = &binding.ast().ty;
+                Some(quote_spanned! { binding.span() =>
+                    <#binding_ty as ::ttt::DeBruijnIndexed>::interned_size(#binding)
+                })
+            }
+        });
+        quote!(0usize #(+ #field_sizes)*)
+    });
+
+    let body = guard_stack(
+        &ast,
+        quote! {
+            match self {
+                #match_body
+            }
+        },
+    );
+
+    quote! {
+        fn interned_size(&self) -> usize {
+            #body
         }
     }
 }
@@ -99,8 +851,9 @@ fn get_var_variant_impl(variant: &VariantInfo) -> TokenStream {
     } else if let Some(field) =
         variant.find_binding_with_attribute(DEBRUIJN_VAR_ATTR)
     {
+        let index = var_value(&field);
         quote! {
-            ::std::option::Option::Some(*#field)
+            ::std::option::Option::Some(#index)
         }
     } else {
         quote! {
@@ -112,12 +865,33 @@ fn get_var_variant_impl(variant: &VariantInfo) -> TokenStream {
 pub fn derive(mut ast: synstructure::Structure) -> proc_macro2::TokenStream {
     ast.add_bounds(synstructure::AddBounds::Generics);
     let dbn_impl = map_indices_impl(ast.clone());
+    let changed_impl = map_changed_indices_impl(ast.clone());
+    let clone_unchanged_impl = clone_unchanged_impl(ast.clone());
     let get_var_impl = get_var_impl(ast.clone());
+    let visit_impl = visit_free_vars_impl(ast.clone());
+    let max_free_index_impl = max_free_index_impl(ast.clone());
+    let interned_size_impl = interned_size_impl(ast.clone());
+    let map_vars_impl = map_vars_at_depth_impl(ast.clone());
+    let in_place_impl = map_indices_in_place_impl(ast.clone());
 
     ast.gen_impl(quote! {
         gen impl ::ttt::DeBruijnIndexed for @Self {
             #dbn_impl
 
+            #changed_impl
+
+            #clone_unchanged_impl
+
+            #in_place_impl
+
+            #visit_impl
+
+            #max_free_index_impl
+
+            #interned_size_impl
+
+            #map_vars_impl
+
             fn get_var(&self) -> Option<usize> {
                 #get_var_impl
             }
@@ -1,8 +1,11 @@
 use proc_macro2::TokenStream;
-use quote::{ToTokens, quote};
+use quote::{ToTokens, format_ident, quote};
 use syn::{Arm, Expr, Field, Ident, Type, parse_quote};
 use synstructure::{AddBounds, BindingInfo, Structure, VariantInfo};
 
+use crate::binders::{binder_count, let_binding_value, telescope_offset};
+use crate::debruijn_indexed::var_value;
+use crate::stack_safe::guard_stack;
 use crate::utils::attributes::HasAttributes;
 use crate::{attributes::*, utils::*};
 
@@ -14,28 +17,42 @@ fn context_param() -> Ident {
     parse_quote!(__ttt_ctx)
 }
 
+fn signature_param() -> Ident {
+    parse_quote!(__ttt_sig)
+}
+
 fn field_doesnt_eval(field: &Field) -> bool {
     field.has_attribute(METADATA_ATTR)
         || field.has_attribute(DEBRUIJN_VAR_ATTR) // todo: remove
+        || field.has_attribute(DEBRUIJN_LEVEL_ATTR)
         || field.has_attribute(VAR_NAME_ATTR)
         || field.has_attribute(BINDING_NAME_ATTR)
+        || field.has_attribute(GLOBAL_REF_ATTR)
     // || field_has_attribute(field, "variable")
 }
 
 fn evaluated(expr: impl ToTokens, ctx: impl ToTokens) -> TokenStream {
+    let sig = signature_param();
     let under_binders = under_binders_param();
     quote! {
-        ::ttt::Evaluate::evaluate(#expr, #ctx, #under_binders)?
+        ::ttt::Evaluate::evaluate(#expr, #ctx, #sig, #under_binders)?
     }
 }
 
 fn normalised(expr: impl ToTokens, ctx: impl ToTokens) -> TokenStream {
+    let sig = signature_param();
     let under_binders = under_binders_param();
     quote! {
-        ::ttt::Evaluate::normalise(#expr, #ctx, #under_binders)?
+        ::ttt::Evaluate::normalise(#expr, #ctx, #sig, #under_binders)?
     }
 }
 
+/// The variable holding the evaluated definition of the variable bound by a
+/// `#[let_binding]` field.
+fn definition_ident(let_binding: &BindingInfo) -> Ident {
+    format_ident!("{}_definition", let_binding.binding)
+}
+
 fn evaluator_func_opt(variant: &VariantInfo) -> Option<Expr> {
     variant.parse_attribute(EVAL_FUNC_ATTR)
 }
@@ -66,23 +83,40 @@ struct EvaluateDerive<'a> {
     ast: Structure<'a>,
     eval_type: Type,
     context_type: Type,
+    /// The signature type given by `#[signature_type(...)]`, if any. Global
+    /// references are only unfolded when one is given.
+    signature_type: Option<Type>,
     error_type: Type,
 }
 
 impl<'a> EvaluateDerive<'a> {
-    fn ctx_consed(
+    /// Binds `binding_ctx` to a reference to the context extended with
+    /// `count` unknown variables, while evaluating `body`.
+    fn with_ctx_under_binders(
         &self,
-        ctx: impl ToTokens,
-        binding_type_expr: impl ToTokens,
+        count: impl ToTokens,
+        binding_ctx: impl ToTokens,
+        body: impl ToTokens,
     ) -> TokenStream {
         let context_trait = self.context_trait();
         let context_type = &self.context_type;
-        quote! {
-            &<#context_type as #context_trait>::append(
-                #ctx,
-                #binding_type_expr
-            )
-        }
+        let ctx = context_param();
+        let none = option_none();
+        quote! {{
+            let __ttt_extended_ctx;
+            let #binding_ctx = match #count {
+                0 => #ctx,
+                __ttt_count => {
+                    __ttt_extended_ctx = ::std::iter::Iterator::fold(
+                        1..__ttt_count,
+                        <#context_type as #context_trait>::append(#ctx, #none),
+                        |__ttt_ctx_acc, _| <#context_type as #context_trait>::append(&__ttt_ctx_acc, #none),
+                    );
+                    &__ttt_extended_ctx
+                }
+            };
+            #body
+        }}
     }
 
     fn context_trait(&self) -> TokenStream {
@@ -99,12 +133,69 @@ impl<'a> EvaluateDerive<'a> {
         variant
             .bindings()
             .iter()
-            .map(|b| self.recursively_eval_field(b))
+            .map(|b| self.recursively_eval_field(variant, b))
+    }
+
+    /// Evaluates the definition of each variable bound by a `#[let_binding]`
+    /// field of the variant, once for both the field holding it and the
+    /// field it is bound over.
+    fn let_definitions(&self, variant: &VariantInfo) -> TokenStream {
+        let definitions = variant.bindings().iter().filter_map(|binding| {
+            let value = let_binding_value(variant, binding)?;
+            let definition = definition_ident(binding);
+            let evalled = evaluated(value, context_param());
+            Some(quote!(let #definition = #evalled;))
+        });
+        quote!(#(#definitions)*)
+    }
+
+    /// The definition which the field holds, if it is the value of a
+    /// `#[let_binding]` field.
+    fn definition_held(
+        &self,
+        variant: &VariantInfo,
+        binding: &BindingInfo,
+    ) -> Option<Ident> {
+        variant.bindings().iter().find_map(|let_binding| {
+            (let_binding_value(variant, let_binding)? == binding)
+                .then(|| definition_ident(let_binding))
+        })
+    }
+
+    /// Binds `binding_ctx` to a reference to the context extended with the
+    /// definition of a `#[let_binding]` field, while evaluating `body`.
+    /// Unlike other binders, the body of a definition is evaluated whether
+    /// or not evaluation goes under binders, as its variable has a value.
+    fn with_ctx_under_let(
+        &self,
+        let_binding: &BindingInfo,
+        binding_ctx: impl ToTokens,
+        body: impl ToTokens,
+    ) -> TokenStream {
+        let context_trait = self.context_trait();
+        let context_type = &self.context_type;
+        let ctx = context_param();
+        let definition = definition_ident(let_binding);
+        quote! {{
+            let #binding_ctx = &<#context_type as #context_trait>::append(
+                #ctx,
+                ::core::option::Option::Some(::core::clone::Clone::clone(&#definition)),
+            );
+            #body
+        }}
     }
 
-    fn recursively_eval_field(&self, binding: &BindingInfo) -> TokenStream {
-        if binding.has_attribute("binding") {
-            self.eval_under_binder(binding)
+    fn recursively_eval_field(
+        &self,
+        variant: &VariantInfo,
+        binding: &BindingInfo,
+    ) -> TokenStream {
+        if let Some(offset) = telescope_offset(variant, binding) {
+            self.eval_telescope(binding, offset, |elem, ctx| {
+                evaluated(elem, ctx)
+            })
+        } else if let Some(count) = binder_count(variant, binding) {
+            self.eval_under_binder(binding, count)
         } else if field_doesnt_eval(binding.ast()) {
             binding.cloned().to_token_stream()
         } else {
@@ -114,10 +205,23 @@ impl<'a> EvaluateDerive<'a> {
 
     fn recursively_normalise_field(
         &self,
+        variant: &VariantInfo,
         binding: &BindingInfo,
     ) -> TokenStream {
-        if binding.has_attribute(BINDING_ATTR) {
-            self.normalise_under_binder(binding)
+        let ctx: Ident = parse_quote!(__ttt_binder_ctx);
+        if let_binding_value(variant, binding).is_some() {
+            self.with_ctx_under_let(binding, &ctx, normalised(binding, &ctx))
+        } else if let Some(definition) = self.definition_held(variant, binding)
+        {
+            quote! {
+                ::core::convert::Into::into(::core::clone::Clone::clone(&#definition))
+            }
+        } else if let Some(offset) = telescope_offset(variant, binding) {
+            self.eval_telescope(binding, offset, |elem, ctx| {
+                normalised(elem, ctx)
+            })
+        } else if let Some(count) = binder_count(variant, binding) {
+            self.normalise_under_binder(binding, count)
         } else if field_doesnt_eval(binding.ast()) {
             binding.cloned().to_token_stream()
         } else {
@@ -125,11 +229,16 @@ impl<'a> EvaluateDerive<'a> {
         }
     }
 
-    fn eval_under_binder(&self, binding: &BindingInfo) -> TokenStream {
+    fn eval_under_binder(
+        &self,
+        binding: &BindingInfo,
+        count: TokenStream,
+    ) -> TokenStream {
         let under_binders = under_binders_param();
-        let ctx = self.ctx_consed(context_param(), option_none());
+        let ctx: Ident = parse_quote!(__ttt_binder_ctx);
 
-        let evalled = evaluated(binding, ctx);
+        let evalled =
+            self.with_ctx_under_binders(count, &ctx, evaluated(binding, &ctx));
         let not_evalled = binding.cloned();
 
         quote! {
@@ -141,11 +250,71 @@ impl<'a> EvaluateDerive<'a> {
         }
     }
 
-    fn normalise_under_binder(&self, binding: &BindingInfo) -> TokenStream {
+    /// Evaluates each element of a `#[telescope]` field with `eval`, in the
+    /// context extended with an unknown variable for each element before it.
+    /// Elements under binders are only evaluated when evaluation goes under
+    /// binders.
+    fn eval_telescope(
+        &self,
+        binding: &BindingInfo,
+        offset: TokenStream,
+        eval: impl Fn(&Ident, &Ident) -> TokenStream,
+    ) -> TokenStream {
+        let under_binders = under_binders_param();
+        let context_trait = self.context_trait();
+        let context_type = &self.context_type;
+        let ctx = context_param();
+        let none = option_none();
+        let elem: Ident = parse_quote!(__ttt_elem);
+        let elem_ctx: Ident = parse_quote!(__ttt_binder_ctx);
+        let evalled = eval(&elem, &elem_ctx);
+        quote! {{
+            let mut __ttt_depth: usize = #offset;
+            let mut __ttt_telescope_ctx: ::core::option::Option<#context_type> =
+                ::core::option::Option::None;
+            if #under_binders {
+                for _ in 0..__ttt_depth {
+                    let #elem_ctx = ::core::option::Option::unwrap_or(
+                        ::core::option::Option::as_ref(&__ttt_telescope_ctx),
+                        #ctx,
+                    );
+                    __ttt_telescope_ctx = ::core::option::Option::Some(
+                        <#context_type as #context_trait>::append(#elem_ctx, #none),
+                    );
+                }
+            }
+            let mut __ttt_elems = ::std::vec::Vec::new();
+            for #elem in ::std::iter::IntoIterator::into_iter(&*#binding) {
+                let #elem_ctx = ::core::option::Option::unwrap_or(
+                    ::core::option::Option::as_ref(&__ttt_telescope_ctx),
+                    #ctx,
+                );
+                if __ttt_depth == 0 || #under_binders {
+                    __ttt_elems.push(#evalled);
+                } else {
+                    __ttt_elems.push(::core::clone::Clone::clone(#elem));
+                }
+                if #under_binders {
+                    __ttt_telescope_ctx = ::core::option::Option::Some(
+                        <#context_type as #context_trait>::append(#elem_ctx, #none),
+                    );
+                }
+                __ttt_depth += 1;
+            }
+            ::std::iter::FromIterator::from_iter(__ttt_elems)
+        }}
+    }
+
+    fn normalise_under_binder(
+        &self,
+        binding: &BindingInfo,
+        count: TokenStream,
+    ) -> TokenStream {
         let under_binders = under_binders_param();
-        let ctx = self.ctx_consed(context_param(), option_none());
+        let ctx: Ident = parse_quote!(__ttt_binder_ctx);
 
-        let evalled = normalised(binding, ctx);
+        let evalled =
+            self.with_ctx_under_binders(count, &ctx, normalised(binding, &ctx));
         let not_evalled = binding.cloned();
 
         quote! {
@@ -170,6 +339,9 @@ impl<'a> EvaluateDerive<'a> {
                 default_context_type(&eval_type)
             });
 
+        let signature_type: Option<Type> =
+            ast.parse_attribute(SIGNATURE_TYPE_ATTR);
+
         let error_type: Type = ast
             .parse_attribute_with_default(EVAL_ERROR_ATTR, || {
                 parse_quote!(::ttt::EvalError)
@@ -179,6 +351,7 @@ impl<'a> EvaluateDerive<'a> {
             ast,
             eval_type,
             context_type,
+            signature_type,
             error_type,
         }
     }
@@ -187,13 +360,20 @@ impl<'a> EvaluateDerive<'a> {
         let eval_impl =
             self.ast.each_variant(|var| self.evaluate_variant_impl(var));
         let context_name = context_param();
+        let signature_name = signature_param();
         let under_binders_name = under_binders_param();
-
-        quote! {
-            fn evaluate(&self, #context_name: &Self::Context, #under_binders_name: bool) -> Result<Self::Target, Self::Error> {
+        let body = guard_stack(
+            &self.ast,
+            quote! {
                 match self {
                     #eval_impl
                 }
+            },
+        );
+
+        quote! {
+            fn evaluate(&self, #context_name: &Self::Context, #signature_name: &Self::Signature, #under_binders_name: bool) -> Result<Self::Target, Self::Error> {
+                #body
             }
         }
     }
@@ -203,12 +383,17 @@ impl<'a> EvaluateDerive<'a> {
         let eval_type = &self.eval_type;
         let eval_error_type = &self.error_type;
         let context_type = &self.context_type;
+        let signature_type = self
+            .signature_type
+            .clone()
+            .unwrap_or_else(|| parse_quote!(()));
 
         self.ast.gen_impl(quote! {
             gen impl ::ttt::Evaluate for @Self {
                 type Target = #eval_type;
                 type Error = #eval_error_type;
                 type Context = #context_type;
+                type Signature = #signature_type;
 
                 #evaluate_function
             }
@@ -221,7 +406,9 @@ impl<'a> EvaluateDerive<'a> {
         evaluate_fn: Expr,
     ) -> TokenStream {
         let field_exprs = self.recursively_evalled_fields(variant);
-        let field_exprs = std::iter::once(context_param().to_token_stream())
+        let field_exprs = [context_param(), signature_param()]
+            .into_iter()
+            .map(|param| param.to_token_stream())
             .chain(field_exprs);
 
         let custom_evalled = function_call(&evaluate_fn, field_exprs);
@@ -265,10 +452,76 @@ impl<'a> EvaluateDerive<'a> {
     }
 
     fn variant_impl_default(&self, variant: &VariantInfo) -> TokenStream {
-        variant
-            .construct_from_bindings(|b| self.recursively_normalise_field(b))
+        let definitions = self.let_definitions(variant);
+        let normalised: TokenStream = variant
+            .construct_from_bindings(|b| {
+                self.recursively_normalise_field(variant, b)
+            })
             .intoed_explicit(&self.eval_type)
-            .result_ok()
+            .result_ok();
+        quote! {{
+            #definitions
+            #normalised
+        }}
+    }
+
+    /// Looks up a variable in the context, evaluating to the value it is
+    /// bound to, or to the variable itself if it is bound without a value.
+    fn variant_impl_variable(
+        &self,
+        variant: &VariantInfo,
+        var: &BindingInfo,
+    ) -> TokenStream {
+        let context_trait = self.context_trait();
+        let context_type = &self.context_type;
+        let ctx = context_param();
+        let index = var_value(var);
+        let unbound = self.variant_impl_default(variant);
+
+        quote! {{
+            let __ttt_index = #index;
+            match <#context_type as #context_trait>::lookup(#ctx, __ttt_index)? {
+                ::core::option::Option::Some(__ttt_value) => Ok(::ttt::spez::spez! {
+                    for __ttt_bound = (__ttt_value, __ttt_index);
+                    match<V: ::ttt::DeBruijnIndexed> (V, usize) -> V {
+                        ::ttt::DeBruijnIndexed::increment_indices_by(&__ttt_bound.0, __ttt_bound.1 + 1)
+                    }
+                    match<V> (V, usize) -> V {
+                        __ttt_bound.0
+                    }
+                }),
+                ::core::option::Option::None => #unbound,
+            }
+        }}
+    }
+
+    /// Looks up a global in the signature, evaluating to its definition, or
+    /// to the reference itself if it is declared without one. Definitions are
+    /// closed, so are evaluated in an empty context.
+    fn variant_impl_global(
+        &self,
+        variant: &VariantInfo,
+        global: &BindingInfo,
+    ) -> TokenStream {
+        let context_trait = self.context_trait();
+        let context_type = &self.context_type;
+        let sig = signature_param();
+        let under_binders = under_binders_param();
+        let undefined = self.variant_impl_default(variant);
+
+        quote! {
+            match ::ttt::GlobalDef::definition(::ttt::Signature::lookup(#sig, #global)?) {
+                ::core::option::Option::Some(__ttt_value) => ::core::result::Result::Ok(
+                    ::ttt::Evaluate::evaluate(
+                        __ttt_value,
+                        &<#context_type as #context_trait>::empty(),
+                        #sig,
+                        #under_binders,
+                    )?
+                ),
+                ::core::option::Option::None => #undefined,
+            }
+        }
     }
 
     fn evaluate_variant_impl(&self, variant: &VariantInfo<'_>) -> TokenStream {
@@ -278,6 +531,15 @@ impl<'a> EvaluateDerive<'a> {
             self.variant_impl_from_function(variant, evaluator_fn)
         } else if let Some(evaluator_arm) = evaluator_pattern_opt(variant) {
             self.variant_impl_from_pattern(variant, evaluator_arm)
+        } else if let Some(var) =
+            variant.find_binding_with_attribute(DEBRUIJN_VAR_ATTR)
+        {
+            self.variant_impl_variable(variant, &var)
+        } else if let Some(global) = variant
+            .find_binding_with_attribute(GLOBAL_REF_ATTR)
+            .filter(|_| self.signature_type.is_some())
+        {
+            self.variant_impl_global(variant, &global)
         } else {
             self.variant_impl_default(variant)
         }
@@ -3,25 +3,28 @@
 //! Documentation for the macros defined here can be found in the docs for the main crate.
 
 mod attributes;
+mod binders;
+mod sorts;
+mod stack_safe;
 mod utils;
 
 use proc_macro_error2::proc_macro_error;
 use synstructure::decl_derive;
 
 mod debruijn_indexed;
-decl_derive! { [DeBruijnIndexed, attributes(var_index, variable, binding, metadata, var_name)] =>
+decl_derive! { [DeBruijnIndexed, attributes(var_index, var_level, variable, binding, let_binding, telescope, metadata, var_name, global_ref, ttt)] =>
     #[proc_macro_error]
     debruijn_indexed::derive
 }
 
 mod substitute;
-decl_derive! { [Substitute, attributes(var_index, subst_types, variable, binding, inherit_subst_types)] =>
+decl_derive! { [Substitute, attributes(var_index, var_level, subst_types, variable, binding, let_binding, telescope, inherit_subst_types, subst_infallible, subst_error_type, subst_with, global_ref, ttt)] =>
     #[proc_macro_error]
     substitute::derive
 }
 
 mod evaluate;
-decl_derive! { [Evaluate, attributes(eval_target, context_type, binding, evaluate_with, evaluate_pattern, evaluate_unwrap_variant, metadata, var_name, eval_error_type)] =>
+decl_derive! { [Evaluate, attributes(eval_target, context_type, signature_type, binding, let_binding, telescope, evaluate_with, evaluate_pattern, evaluate_unwrap_variant, metadata, var_name, var_level, eval_error_type, global_ref, ttt)] =>
     #[proc_macro_error]
     evaluate::derive
 }
@@ -29,10 +32,16 @@ decl_derive! { [Evaluate, attributes(eval_target, context_type, binding, evaluat
 mod attribute_dsl;
 
 mod attribute_derives;
-decl_derive! { [Attributed, attributes(check, check_type, synth, synth_type, bidir_type)] =>
+decl_derive! { [Attributed, attributes(check, check_type, synth, synth_type, bidir_type, binding_name, let_binding, global_ref, ttt)] =>
     #[proc_macro_error]
     attribute_derives::derive_attributed
 }
 
 mod resolve_vars;
-decl_derive! { [ResolveVars, attributes(var_index, binding, var_name, binding_name)] => resolve_vars::derive }
+decl_derive! { [ResolveVars, attributes(var_index, var_level, binding, let_binding, telescope, var_name, binding_name, global_ref, ttt)] => resolve_vars::derive }
+
+mod alpha_eq;
+decl_derive! { [AlphaEq, attributes(var_index, var_level, variable, binding, let_binding, telescope, metadata, var_name, binding_name, global_ref, ttt)] =>
+    #[proc_macro_error]
+    alpha_eq::derive
+}
@@ -1,3 +1,8 @@
+use crate::binders::{
+    binder_count, has_explicit_binder_count, telescope_offset,
+    variant_has_telescope,
+};
+use crate::stack_safe::guard_stack;
 use crate::utils::*;
 use attributes::HasAttributes;
 use proc_macro2::TokenStream;
@@ -8,6 +13,31 @@ fn field_is_metadata(binding: &BindingInfo) -> bool {
     binding.has_attribute("metadata")
         || binding.has_attribute("var_name")
         || binding.has_attribute("binding_name")
+        || binding.has_attribute("global_ref")
+}
+
+fn binding_name_field<'a>(variant: &'a VariantInfo) -> &'a BindingInfo<'a> {
+    let Some(name_field) = variant
+        .bindings()
+        .iter()
+        .find(|field| field.has_attribute("binding_name"))
+    else {
+        let variant = variant.ast().ident;
+        panic!("In variant {variant}, expected a #[binding_name] field");
+    };
+    name_field
+}
+
+fn var_name_field<'a>(variant: &'a VariantInfo) -> &'a BindingInfo<'a> {
+    let Some(name_field) = variant
+        .bindings()
+        .iter()
+        .find(|field| field.has_attribute("var_name"))
+    else {
+        let variant = variant.ast().ident;
+        panic!("In variant {variant}, expected a #[var_name] field");
+    };
+    name_field
 }
 
 fn resolve_vars_impl(ast: &mut Structure) -> TokenStream {
@@ -27,34 +57,93 @@ fn resolve_vars_variant_impl(variant: &VariantInfo) -> TokenStream {
             quote! {
                 #field.clone()
             }
-        } else if field.has_attribute("binding") {
-            let Some(name_field) = variant
-                .bindings()
-                .iter()
-                .find(|field| field.has_attribute("binding_name"))
-            else {
-                let variant = variant.ast().ident;
-                panic!("In variant {variant}, expected a #[binding_name] field");
-            };
-            quote! {
-                #field.resolve_vars(
-                    &__ttt_param_ctx.append(#name_field.clone())
-                )?.into()
+        } else if let Some(offset) = telescope_offset(variant, field) {
+            // Each element of the telescope is under the names of the
+            // elements before it.
+            let name_field = binding_name_field(variant);
+            quote! {{
+                let mut __ttt_param_names =
+                    ::std::iter::IntoIterator::into_iter(#name_field);
+                let mut __ttt_param_inner_ctx = ::std::iter::Iterator::fold(
+                    ::std::iter::Iterator::take(&mut __ttt_param_names, #offset),
+                    __ttt_param_ctx.clone(),
+                    |__ttt_param_ctx, __ttt_param_name| {
+                        __ttt_param_ctx.append(__ttt_param_name.clone())
+                    },
+                );
+                ::std::iter::Iterator::collect::<Result<_, _>>(
+                    ::std::iter::Iterator::map(
+                        ::std::iter::IntoIterator::into_iter(#field),
+                        |__ttt_param_elem| {
+                            let __ttt_param_resolved =
+                                __ttt_param_elem.resolve_vars(&__ttt_param_inner_ctx)?;
+                            if let Some(__ttt_param_name) =
+                                ::std::iter::Iterator::next(&mut __ttt_param_names)
+                            {
+                                __ttt_param_inner_ctx =
+                                    __ttt_param_inner_ctx.append(__ttt_param_name.clone());
+                            }
+                            Ok(__ttt_param_resolved)
+                        },
+                    )
+                )?
+            }}
+        } else if let Some(count) = binder_count(variant, field) {
+            let name_field = binding_name_field(variant);
+            if has_explicit_binder_count(field) || variant_has_telescope(variant)
+            {
+                // The field binds several variables, so the name field holds
+                // one name for each of them, outermost first.
+                quote! {{
+                    let __ttt_param_count: usize = #count;
+                    let __ttt_param_names = ::std::iter::Iterator::count(
+                        ::std::iter::IntoIterator::into_iter(#name_field),
+                    );
+                    if __ttt_param_names != __ttt_param_count {
+                        return Err(::ttt::ResolveVarsError::BinderCountMismatch {
+                            binders: __ttt_param_count,
+                            names: __ttt_param_names,
+                        });
+                    }
+                    #field.resolve_vars(
+                        &::std::iter::Iterator::fold(
+                            ::std::iter::IntoIterator::into_iter(#name_field),
+                            __ttt_param_ctx.clone(),
+                            |__ttt_param_ctx, __ttt_param_name| {
+                                __ttt_param_ctx.append(__ttt_param_name.clone())
+                            },
+                        )
+                    )?.into()
+                }}
+            } else {
+                quote! {
+                    #field.resolve_vars(
+                        &__ttt_param_ctx.append(#name_field.clone())
+                    )?.into()
+                }
             }
         } else if field.has_attribute("var_index") {
-            let Some(name_field) = variant
-                .bindings()
-                .iter()
-                .find(|field| field.has_attribute("var_name"))
-            else {
-                let variant = variant.ast().ident;
-                panic!("In variant {variant}, expected a #[var_name] field");
-            };
+            let name_field = var_name_field(variant);
+            let var_ty = &field.ast().ty;
+            quote! {
+                <#var_ty as ::ttt::VarIndex>::from_index(
+                    ::std::iter::Iterator::position(
+                        &mut ::ttt::NameContext::iter(__ttt_param_ctx),
+                        |__ttt_param_name| __ttt_param_name == #name_field
+                    ).ok_or_else(|| ::ttt::ResolveVarsError::UnboundVariable(#name_field.to_string()))?
+                )
+            }
+        } else if field.has_attribute("var_level") {
+            let name_field = var_name_field(variant);
+            let var_ty = &field.ast().ty;
             quote! {
-                ::std::iter::Iterator::position(
-                    &mut ::ttt::NameContext::iter(__ttt_param_ctx),
-                    |__ttt_param_name| __ttt_param_name == #name_field
-                ).ok_or_else(|| ::ttt::ResolveVarsError::UnboundVariable(#name_field.to_string()))?
+                <#var_ty as ::ttt::VarIndex>::from_index(::ttt::debruijn_indexed::index_to_level(
+                    ::std::iter::Iterator::position(
+                        &mut ::ttt::NameContext::iter(__ttt_param_ctx),
+                        |__ttt_param_name| __ttt_param_name == #name_field
+                    ).ok_or_else(|| ::ttt::ResolveVarsError::UnboundVariable(#name_field.to_string()))?,
+                    ::ttt::NameContext::len(__ttt_param_ctx),
+                ).expect("A variable found in the context is in scope"))
             }
         } else {
             quote! {
@@ -68,12 +157,18 @@ fn resolve_vars_variant_impl(variant: &VariantInfo) -> TokenStream {
 pub fn derive(mut ast: synstructure::Structure) -> proc_macro2::TokenStream {
     ast.add_bounds(AddBounds::Generics);
     let resolve_vars_impl = resolve_vars_impl(&mut ast);
+    let body = guard_stack(
+        &ast,
+        quote! {
+            use std::borrow::Borrow;
+            Ok(#resolve_vars_impl)
+        },
+    );
 
     ast.gen_impl(quote! {
         gen impl ttt::ResolveVars for @Self {
             fn resolve_vars(&self, __ttt_param_ctx: &ttt::NameContext) -> Result<Self, ::ttt::ResolveVarsError> {
-                use std::borrow::Borrow;
-                Ok(#resolve_vars_impl)
+                #body
             }
         }
     })
@@ -0,0 +1,43 @@
+use proc_macro_error2::abort;
+use proc_macro2::TokenStream;
+use quote::quote;
+use structmeta::StructMeta;
+use syn::Type;
+use synstructure::BindingInfo;
+
+use crate::attributes::DEBRUIJN_VAR_ATTR;
+use crate::utils::attributes::HasAttributes;
+
+/// Arguments accepted by the `#[var_index(...)]` attribute.
+#[derive(StructMeta)]
+struct VarIndexArgs {
+    sort: Option<Type>,
+}
+
+/// The sort of the variable held in a `#[var_index]` field, if it specifies
+/// one as `#[var_index(sort = T)]`.
+pub fn var_sort(binding: &BindingInfo) -> Option<Type> {
+    let attr = binding.find_attribute(DEBRUIJN_VAR_ATTR)?;
+    if attr.meta.require_path_only().is_ok() {
+        return None;
+    }
+
+    match attr.parse_args::<VarIndexArgs>() {
+        Ok(args) => args.sort,
+        Err(e) => abort!(e.span(),
+            "Error while parsing `#[{}(...)]` attribute", DEBRUIJN_VAR_ATTR;
+            note = "{}", e;
+            help = "Expected the sort of the variable, such as `#[{}(sort = Ty)]`", DEBRUIJN_VAR_ATTR;
+        ),
+    }
+}
+
+/// An expression of type `Option<::ttt::Sort>` for the given sort.
+pub fn sort_value(sort: Option<&Type>) -> TokenStream {
+    match sort {
+        Some(sort) => quote! {
+            ::std::option::Option::Some(::ttt::Sort::of::<#sort>())
+        },
+        None => quote!(::std::option::Option::None),
+    }
+}
@@ -0,0 +1,27 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use synstructure::Structure;
+
+use crate::attributes::{STACK_SAFE_OPTION, has_ttt_option};
+
+/// Whether the deriving type is annotated with `#[ttt(stack_safe)]`.
+pub fn is_stack_safe(ast: &Structure) -> bool {
+    has_ttt_option(ast, STACK_SAFE_OPTION)
+}
+
+/// Wraps the body of a derived method which recurses into the fields of a
+/// term, so that for stack safe types it checks for enough stack first.
+///
+/// The body is moved into a closure returning the same type as the method,
+/// so any `return` or `?` in it still returns from the method.
+pub fn guard_stack(ast: &Structure, body: TokenStream) -> TokenStream {
+    if is_stack_safe(ast) {
+        quote! {
+            ::ttt::stack::ensure_sufficient_stack(|| {
+                #body
+            })
+        }
+    } else {
+        body
+    }
+}
@@ -1,17 +1,21 @@
 use proc_macro_error2::abort;
 use proc_macro2::TokenStream;
-use quote::{ToTokens, quote, quote_spanned};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{
-    GenericParam, Type, parse_quote, punctuated::Punctuated, spanned::Spanned,
-    token::Comma,
+    Expr, GenericParam, Ident, Type, TypePath, parse_quote,
+    punctuated::Punctuated, spanned::Spanned, token::Comma,
 };
 use synstructure::{BindingInfo, Structure, VariantInfo};
 
 use crate::{
     attributes::{
-        BINDING_ATTR, DEBRUIJN_VAR_ATTR, IsMetadata, SUBST_TYPES_ATTR,
-        VAR_WRAPPER_ATTR,
+        DEBRUIJN_LEVEL_ATTR, DEBRUIJN_VAR_ATTR, IN_PLACE_OPTION, IsMetadata,
+        SUBST_ERROR_ATTR, SUBST_FUNC_ATTR, SUBST_INFALLIBLE_ATTR,
+        SUBST_TYPES_ATTR, VAR_WRAPPER_ATTR, has_ttt_option,
     },
+    binders::{binder_count, binder_sort, telescope_offset},
+    sorts::{sort_value, var_sort},
+    stack_safe::guard_stack,
     utils::{
         StructureExt, VariantInfoExt, attributes::HasAttributes, type_ident,
     },
@@ -21,14 +25,42 @@ struct SubstDerive<'a> {
     ast: &'a Structure<'a>,
     ty: Type,
     impl_generics: Vec<GenericParam>,
+    infallible: bool,
+    error_type: Type,
+}
+
+/// The function given by `#[subst_with(...)]` on a variant, which replaces
+/// the default conversion of a substituted expression into the target type.
+fn subst_func_opt(variant: &VariantInfo) -> Option<Expr> {
+    variant.parse_attribute(SUBST_FUNC_ATTR)
 }
 
 impl SubstDerive<'_> {
-    // fn is_self_subst(&self) -> bool {
-    //     // todo: Better way to compare types?
-    //     self.ty == parse_quote!(Self)
-    //         || self.ty == type_ident(self.ast.ast().ident.clone())
-    // }
+    /// An expression for the sort of variable replaced by this substitution.
+    fn subst_sort(&self) -> TokenStream {
+        sort_value(Some(&self.ty))
+    }
+
+    /// Whether a variable of sort `var_sort` may be replaced by this
+    /// substitution, judging by the names of the types.
+    fn substitutes_sort(&self, var_sort: Option<&Type>) -> bool {
+        var_sort.is_none_or(|var_sort| {
+            sort_name(self.ast, var_sort) == sort_name(self.ast, &self.ty)
+        })
+    }
+
+    /// Whether a variable of sort `var_sort` in `variant` can never be
+    /// replaced, so [`Self::substituted_expr`] is unreachable. Infallible
+    /// substitutions never replace variables of another sort.
+    fn never_replaced(
+        &self,
+        variant: &VariantInfo<'_>,
+        var_sort: Option<&Type>,
+    ) -> bool {
+        self.infallible
+            && subst_func_opt(variant).is_none()
+            && !self.substitutes_sort(var_sort)
+    }
 
     fn ast_is_var_wrapper(&self) -> bool {
         self.ast.variants().iter().all(|variant| {
@@ -47,22 +79,43 @@ impl SubstDerive<'_> {
         }
     }
 
-    fn debruijn_index_variant_impl(
+    /// Converts the expression `_ast_param_other` into the target type, for
+    /// a variable of sort `var_sort` in `variant`, or returns an error if it
+    /// can't be converted.
+    ///
+    /// Infallible substitutions instead require the conversion to exist at
+    /// compile time, except for variables of another sort, which are never
+    /// replaced.
+    fn substituted_expr(
         &self,
         variant: &VariantInfo<'_>,
-        var_field: BindingInfo<'_>,
+        var_sort: Option<&Type>,
     ) -> TokenStream {
-        let greater_than_case = variant.construct_from_bindings(|binding| {
-            if *binding == var_field {
-                quote! { *#binding - 1 }
-            } else {
-                quote! { ::std::clone::Clone::clone(#binding) }
-            }
-        });
-
         let subst_ty = &self.ty;
         let target_ty = self.subst_target_type();
-        let equal_case = quote! {{
+        if let Some(subst_func) = subst_func_opt(variant) {
+            return quote_spanned! { subst_func.span() =>
+                #subst_func(_ast_param_other)?
+            };
+        }
+        if self.infallible {
+            return if self.substitutes_sort(var_sort) {
+                quote! {
+                    <#subst_ty as ::std::convert::Into<#target_ty>>::into(
+                        ::std::clone::Clone::clone(_ast_param_other)
+                    )
+                }
+            } else {
+                quote! {
+                    ::std::unreachable!(
+                        "Variables of sort `{}` are never replaced by `{}`",
+                        ::std::stringify!(#var_sort),
+                        ::std::stringify!(#subst_ty),
+                    )
+                }
+            };
+        }
+        quote! {{
             let __tmp = ::ttt::spez::spez! {
                 for _ast_param_other;
                 match<T: ::std::convert::Into<#target_ty> + Clone> &T -> ::std::option::Option<#target_ty> {
@@ -81,147 +134,690 @@ impl SubstDerive<'_> {
                 ::std::option::Option::Some(__tmp) => __tmp,
                 ::std::option::Option::None =>
                     return ::std::result::Result::Err(
-                        ::ttt::SubstError::new::<#subst_ty, #target_ty>()
+                        ::std::convert::From::from(
+                            ::ttt::SubstError::new::<#subst_ty, #target_ty>()
+                        )
                     )
             }
-        }};
+        }}
+    }
+
+    /// Binds `_ast_param_other` to the term `__ttt_term` from a substitution,
+    /// shifted in sort `sort` past the `_ast_param_depth` binders it is being
+    /// substituted under.
+    /// Closed terms are never shifted, and substituting types which don't
+    /// implement `DeBruijnIndexed` is still allowed at a depth of zero, but
+    /// is an error under binders. Infallible substitutions instead require
+    /// `DeBruijnIndexed` at compile time.
+    fn shifted_term(&self, sort: TokenStream) -> TokenStream {
+        let subst_ty = &self.ty;
+        let shift = |term: TokenStream,
+                     sort: TokenStream,
+                     depth: TokenStream,
+                     is_closed: TokenStream| {
+            quote! {
+                if #depth == 0 || #is_closed {
+                    ::std::option::Option::None
+                } else {
+                    ::std::option::Option::Some(
+                        ::ttt::DeBruijnIndexed::increment_sort_indices_by(
+                            #term, #sort, #depth))
+                }
+            }
+        };
+        let shifted = if self.infallible {
+            shift(
+                quote!(__ttt_term),
+                sort,
+                quote!(_ast_param_depth),
+                quote! {
+                    ::ttt::Subst::is_closed_term::<#subst_ty>(_ast_param_subst, __ttt_var)
+                },
+            )
+        } else {
+            let shift = shift(
+                quote!(__ttt_term),
+                quote!(__ttt_sort),
+                quote!(__ttt_depth),
+                quote! {
+                    ::ttt::Subst::is_closed_term::<T>(__ttt_subst, __ttt_var)
+                },
+            );
+            quote! {{
+                let __ttt_shift_args =
+                    (__ttt_term, #sort, _ast_param_depth, _ast_param_subst, __ttt_var);
+                let __ttt_shifted = ::ttt::spez::spez! {
+                    for __ttt_shift_args;
+                    match<'__ttt, T: ::ttt::DeBruijnIndexed, S: ::std::borrow::Borrow<T>>
+                        (&'__ttt T, ::std::option::Option<::ttt::Sort>, usize, &'__ttt ::ttt::Subst<S>, usize)
+                        -> ::std::result::Result<::std::option::Option<T>, ::ttt::SubstError>
+                    {
+                        let (__ttt_term, __ttt_sort, __ttt_depth, __ttt_subst, __ttt_var) =
+                            __ttt_shift_args;
+                        ::std::result::Result::Ok(#shift)
+                    }
+                    match<'__ttt, T: 'static, S>
+                        (&'__ttt T, ::std::option::Option<::ttt::Sort>, usize, &'__ttt ::ttt::Subst<S>, usize)
+                        -> ::std::result::Result<::std::option::Option<T>, ::ttt::SubstError>
+                    {
+                        if __ttt_shift_args.2 == 0 {
+                            ::std::result::Result::Ok(::std::option::Option::None)
+                        } else {
+                            ::std::result::Result::Err(::ttt::SubstError::not_shiftable::<T>())
+                        }
+                    }
+                };
+                match __ttt_shifted {
+                    ::std::result::Result::Ok(__ttt_shifted) => __ttt_shifted,
+                    ::std::result::Result::Err(__ttt_error) =>
+                        return ::std::result::Result::Err(
+                            ::std::convert::From::from(__ttt_error)
+                        ),
+                }
+            }}
+        };
+        quote! {
+            let __ttt_term: &#subst_ty = ::std::borrow::Borrow::borrow(__ttt_term);
+            let __ttt_shifted = #shifted;
+            let _ast_param_other: &#subst_ty = match &__ttt_shifted {
+                ::std::option::Option::Some(__ttt_shifted) => __ttt_shifted,
+                ::std::option::Option::None => __ttt_term,
+            };
+        }
+    }
+
+    /// The substitution of a variant holding a `#[var_index]` field. If
+    /// `changed` is set, this is for [`Self::changed_variant_impl`], and is
+    /// `None` when the variable is left unchanged.
+    fn debruijn_index_variant_impl(
+        &self,
+        variant: &VariantInfo<'_>,
+        var_field: BindingInfo<'_>,
+        changed: bool,
+    ) -> TokenStream {
+        let var_ty = &var_field.ast().ty;
+        let var_index = quote! {
+            <#var_ty as ::ttt::VarIndex>::to_index(*#var_field)
+        };
+
+        let unchanged_case = variant.construct_from_bindings(|binding| {
+            quote! { ::std::clone::Clone::clone(#binding) }
+        });
+
+        let renamed_case = variant.construct_from_bindings(|binding| {
+            if *binding == var_field {
+                quote! {
+                    <#var_ty as ::ttt::VarIndex>::from_index(
+                        __ttt_new_index + _ast_param_depth
+                    )
+                }
+            } else {
+                quote! { ::std::clone::Clone::clone(#binding) }
+            }
+        });
 
-        let less_than_case = variant.construct_from_bindings(|x| {
+        // Substituted terms only need to be shifted in their own sort.
+        let var_sort = var_sort(&var_field);
+        let term_sort = if var_sort.is_some() {
+            self.subst_sort()
+        } else {
+            sort_value(None)
+        };
+        let equal_case = self.substituted_expr(variant, var_sort.as_ref());
+        let never_replaced = self.never_replaced(variant, var_sort.as_ref());
+        let sort_check = var_sort.map(|var_sort| sort_value(Some(&var_sort)));
+
+        let shifted_term = self.shifted_term(term_sort);
+        let (unchanged_case, equal_case, renamed_case) = if changed {
+            changed_cases(equal_case, renamed_case, never_replaced)
+        } else {
+            (
+                quote!(#unchanged_case.into()),
+                equal_case,
+                quote!(#renamed_case.into()),
+            )
+        };
+        let sort_check = sort_check.map(|var_sort| {
+            let subst_sort = self.subst_sort();
             quote! {
-                ::std::clone::Clone::clone(#x)
+                if !::ttt::Sort::overlap(#var_sort, #subst_sort) {
+                    return ::std::result::Result::Ok(#unchanged_case);
+                }
             }
         });
 
         quote! {{
+            #sort_check
+            let __ttt_index = #var_index;
+            if __ttt_index < _ast_param_depth {
+                return ::std::result::Result::Ok(#unchanged_case);
+            }
+            let __ttt_var = __ttt_index - _ast_param_depth;
             ::std::result::Result::Ok(
-                match std::cmp::Ord::cmp(#var_field, &_ast_param_var) {
-                    std::cmp::Ordering::Less => #less_than_case.into(),
-                    std::cmp::Ordering::Equal => #equal_case,
-                    std::cmp::Ordering::Greater => #greater_than_case.into(),
+                match ::ttt::Subst::lookup(_ast_param_subst, __ttt_var) {
+                    ::std::result::Result::Ok(__ttt_term) => {
+                        #shifted_term
+                        #equal_case
+                    }
+                    ::std::result::Result::Err(__ttt_new_index) => #renamed_case,
                 }
             )
         }}
     }
 
+    /// Like [`Self::debruijn_index_variant_impl`], for a variant holding a
+    /// `#[variable]` field.
     fn variable_variant_impl(
         &self,
         variant: &VariantInfo<'_>,
         var_field: BindingInfo<'_>,
+        changed: bool,
     ) -> TokenStream {
-        let greater_than_case = variant.construct_from_bindings(|binding| {
+        let unchanged_case = variant.construct_from_bindings(|binding| {
+            quote! { ::std::clone::Clone::clone(#binding) }
+        });
+
+        let renamed_case = variant.construct_from_bindings(|binding| {
             if *binding == var_field {
                 quote! {
-                    ::ttt::DeBruijnIndexed::map_indices(#var_field, |index| { index - 1 })
+                    ::ttt::DeBruijnIndexed::map_indices(
+                        #var_field,
+                        move |_| __ttt_new_index + _ast_param_depth,
+                    )
                 }
             } else {
-                quote! {
-                    ::std::clone::Clone::clone(#binding)
-                }
+                quote! { ::std::clone::Clone::clone(#binding) }
             }
         });
 
+        let shifted_term = self.shifted_term(sort_value(None));
+        let equal_case = self.substituted_expr(variant, None);
+        let (unchanged_case, equal_case, renamed_case) = if changed {
+            changed_cases(equal_case, renamed_case, false)
+        } else {
+            (unchanged_case, equal_case, renamed_case)
+        };
+
+        quote! {{
+            let __ttt_index = ::ttt::DeBruijnIndexed::get_var(#var_field).unwrap();
+            if __ttt_index < _ast_param_depth {
+                return ::std::result::Result::Ok(#unchanged_case);
+            }
+            let __ttt_var = __ttt_index - _ast_param_depth;
+            ::std::result::Result::Ok(
+                match ::ttt::Subst::lookup(_ast_param_subst, __ttt_var) {
+                    ::std::result::Result::Ok(__ttt_term) => {
+                        #shifted_term
+                        #equal_case
+                    }
+                    ::std::result::Result::Err(__ttt_new_index) => #renamed_case,
+                }
+            )
+        }}
+    }
+
+    /// The substitution of a variant without a variable, as an
+    /// `Option` which is `None` if none of the fields changed. The fields
+    /// which didn't change are cloned.
+    fn generic_variant_impl(&self, variant: &VariantInfo<'_>) -> TokenStream {
         let subst_ty = &self.ty;
-        let target_ty = self.subst_target_type();
-        let equal_case = quote! {{
-            let __tmp = ::ttt::spez::spez! {
-                for _ast_param_other;
-                match<T: ::std::convert::Into<#target_ty> + Clone> &T -> ::std::option::Option<#target_ty> {
-                    ::std::option::Option::Some(
-                    ::std::convert::Into::into(
-                    ::std::clone::Clone::clone(
-                        _ast_param_other
-                    )))
+        let changed_fields = variant.bindings().iter().map(|binding| {
+            let changed = changed_ident(binding);
+            let binding_ty = &binding.ast().ty;
+            let changed_value = if binding.is_metadata()
+                || binding.has_attribute(DEBRUIJN_LEVEL_ATTR)
+            {
+                quote!(::std::option::Option::None)
+            } else if let Some(offset) = telescope_offset(variant, binding) {
+                // The telescope is rebuilt from the elements which changed,
+                // and clones of those which didn't.
+                quote_spanned! { binding.ast().span() =>
+                    {
+                        let __ttt_changed_elems: ::std::vec::Vec<::std::option::Option<_>> =
+                            ::std::iter::Iterator::collect::<::std::result::Result<_, _>>(
+                                ::std::iter::Iterator::map(
+                                    ::std::iter::Iterator::enumerate(
+                                        ::std::iter::IntoIterator::into_iter(#binding)
+                                    ),
+                                    |(__ttt_position, __ttt_elem)| {
+                                        ::ttt::Substitute::<#subst_ty>::subst_changed_from(
+                                            __ttt_elem,
+                                            _ast_param_subst,
+                                            _ast_param_depth + #offset + __ttt_position)
+                                    }
+                                )
+                            )?;
+                        if ::std::iter::Iterator::all(
+                            &mut ::std::iter::IntoIterator::into_iter(&__ttt_changed_elems),
+                            ::std::option::Option::is_none,
+                        ) {
+                            ::std::option::Option::None
+                        } else {
+                            ::std::option::Option::Some(::std::iter::Iterator::collect(
+                                ::std::iter::Iterator::map(
+                                    ::std::iter::Iterator::zip(
+                                        ::std::iter::IntoIterator::into_iter(__ttt_changed_elems),
+                                        ::std::iter::IntoIterator::into_iter(#binding),
+                                    ),
+                                    |(__ttt_changed, __ttt_elem)| {
+                                        ::std::option::Option::unwrap_or_else(
+                                            __ttt_changed,
+                                            || ::std::clone::Clone::clone(__ttt_elem),
+                                        )
+                                    }
+                                )
+                            ))
+                        }
+                    }
                 }
-                match<T> &T -> ::std::option::Option<#target_ty> {
-                    ::std::option::Option::None
+            } else if let Some(count) = binder_count(variant, binding) {
+                match binder_sort(binding) {
+                    // Binders of a different sort don't bind any of the
+                    // variables being substituted, but the free variables of
+                    // the substituted terms need to be shifted past them.
+                    Some(binder_sort) => {
+                        let binder_sort = sort_value(Some(&binder_sort));
+                        let subst_sort = self.subst_sort();
+                        quote_spanned! { binding.ast().span() =>
+                            {
+                                let __ttt_count = #count;
+                                if ::ttt::Sort::overlap(#binder_sort, #subst_sort) {
+                                    ::ttt::Substitute::<#subst_ty>::subst_changed_from(
+                                        #binding,
+                                        _ast_param_subst,
+                                        _ast_param_depth + __ttt_count)?
+                                } else {
+                                    ::ttt::Substitute::<#subst_ty>::subst_changed_from(
+                                        #binding,
+                                        &::ttt::Subst::shift_terms::<#subst_ty>(
+                                            _ast_param_subst, #binder_sort, __ttt_count),
+                                        _ast_param_depth)?
+                                }
+                            }
+                        }
+                    }
+                    None => quote_spanned! { binding.ast().span() =>
+                        ::ttt::Substitute::<#subst_ty>::subst_changed_from(
+                            #binding,
+                            _ast_param_subst,
+                            _ast_param_depth + #count)?
+                    },
+                }
+            } else {
+                quote_spanned! { binding.ast().span() =>
+                    ::ttt::Substitute::<#subst_ty>::subst_changed_from(
+                        #binding,
+                        _ast_param_subst,
+                        _ast_param_depth)?
                 }
             };
-            match __tmp {
-                ::std::option::Option::Some(__tmp) => __tmp,
-                ::std::option::Option::None => {
-                    return ::std::result::Result::Err(
-                        ::ttt::SubstError::new::<#subst_ty, #target_ty>()
-                    )
+            quote! {
+                let #changed: ::std::option::Option<#binding_ty> = #changed_value;
+            }
+        });
+        if variant.bindings().is_empty() {
+            return quote!(::std::result::Result::Ok(
+                ::std::option::Option::None
+            ));
+        }
+        let any_changed = variant.bindings().iter().map(|binding| {
+            let changed = changed_ident(binding);
+            quote!(::std::option::Option::is_some(&#changed))
+        });
+        let ctor = variant.construct_from_bindings(|binding| {
+            let changed = changed_ident(binding);
+            quote! {
+                match #changed {
+                    ::std::option::Option::Some(__ttt_changed) => __ttt_changed,
+                    ::std::option::Option::None => ::std::clone::Clone::clone(#binding),
                 }
             }
-        }};
+        });
+        quote! {
+            #(#changed_fields)*
+            if #(#any_changed)||* {
+                ::std::result::Result::Ok(::std::option::Option::Some(#ctor))
+            } else {
+                ::std::result::Result::Ok(::std::option::Option::None)
+            }
+        }
+    }
+
+    /// Like [`Self::debruijn_index_variant_impl`], but modifies the term in
+    /// place, for a match arm binding the fields by mutable reference.
+    fn debruijn_index_variant_in_place(
+        &self,
+        variant: &VariantInfo<'_>,
+        var_field: BindingInfo<'_>,
+    ) -> TokenStream {
+        let var_ty = &var_field.ast().ty;
+        let var_index = quote! {
+            <#var_ty as ::ttt::VarIndex>::to_index(*#var_field)
+        };
 
-        let less_than_case = variant.construct_from_bindings(|binding| {
+        let var_sort = var_sort(&var_field);
+        let term_sort = if var_sort.is_some() {
+            self.subst_sort()
+        } else {
+            sort_value(None)
+        };
+        let equal_case = self.substituted_expr(variant, var_sort.as_ref());
+        let never_replaced = self.never_replaced(variant, var_sort.as_ref());
+        let sort_check = var_sort.map(|var_sort| {
+            let var_sort = sort_value(Some(&var_sort));
+            let subst_sort = self.subst_sort();
             quote! {
-                ::std::clone::Clone::clone(#binding)
+                if !::ttt::Sort::overlap(#var_sort, #subst_sort) {
+                    return ::std::result::Result::Ok(());
+                }
             }
         });
 
-        quote! {
-            {
-                let index = ::ttt::DeBruijnIndexed::get_var(#var_field).unwrap();
-                ::std::result::Result::Ok(
-                    match ::std::cmp::Ord::cmp(&index, &_ast_param_var) {
-                        ::std::cmp::Ordering::Less => #less_than_case,
-                        ::std::cmp::Ordering::Equal => #equal_case,
-                        ::std::cmp::Ordering::Greater => #greater_than_case,
-                    }
-                )
+        let shifted_term = self.shifted_term(term_sort);
+
+        let replace_case = if never_replaced {
+            quote! {{
+                let _ = __ttt_term;
+                #equal_case
+            }}
+        } else {
+            quote! {{
+                #shifted_term
+                *self = #equal_case;
+            }}
+        };
+
+        quote! {{
+            #sort_check
+            let __ttt_index = #var_index;
+            if __ttt_index < _ast_param_depth {
+                return ::std::result::Result::Ok(());
             }
-        }
+            let __ttt_var = __ttt_index - _ast_param_depth;
+            match ::ttt::Subst::lookup(_ast_param_subst, __ttt_var) {
+                ::std::result::Result::Ok(__ttt_term) => #replace_case
+                ::std::result::Result::Err(__ttt_new_index) => {
+                    *#var_field = <#var_ty as ::ttt::VarIndex>::from_index(
+                        __ttt_new_index + _ast_param_depth
+                    );
+                }
+            }
+            ::std::result::Result::Ok(())
+        }}
     }
 
-    fn generic_variant_impl(&self, variant: &VariantInfo<'_>) -> TokenStream {
-        let ctor = variant.construct_from_bindings(|binding| {
-            let subst_ty = &self.ty;
-            if binding.is_metadata() {
-                quote! {
-                    ::std::clone::Clone::clone(#binding)
+    /// Like [`Self::variable_variant_impl`], but modifies the term in place.
+    fn variable_variant_in_place(
+        &self,
+        variant: &VariantInfo<'_>,
+        var_field: BindingInfo<'_>,
+    ) -> TokenStream {
+        let shifted_term = self.shifted_term(sort_value(None));
+        let equal_case = self.substituted_expr(variant, None);
+
+        quote! {{
+            let __ttt_index = ::ttt::DeBruijnIndexed::get_var(&*#var_field).unwrap();
+            if __ttt_index < _ast_param_depth {
+                return ::std::result::Result::Ok(());
+            }
+            let __ttt_var = __ttt_index - _ast_param_depth;
+            match ::ttt::Subst::lookup(_ast_param_subst, __ttt_var) {
+                ::std::result::Result::Ok(__ttt_term) => {
+                    #shifted_term
+                    *self = #equal_case;
                 }
-            } else if binding.has_attribute(BINDING_ATTR) {
+                ::std::result::Result::Err(__ttt_new_index) => {
+                    ::ttt::DeBruijnIndexed::map_indices_in_place_from(
+                        #var_field,
+                        0,
+                        move |_| __ttt_new_index + _ast_param_depth,
+                    );
+                }
+            }
+            ::std::result::Result::Ok(())
+        }}
+    }
+
+    /// Like [`Self::generic_variant_impl`], but modifies each field in place.
+    fn generic_variant_in_place(
+        &self,
+        variant: &VariantInfo<'_>,
+    ) -> TokenStream {
+        let subst_ty = &self.ty;
+        let field_substs = variant.bindings().iter().map(|binding| {
+            if binding.is_metadata() || binding.has_attribute(DEBRUIJN_LEVEL_ATTR)
+            {
+                quote!()
+            } else if let Some(offset) = telescope_offset(variant, binding) {
                 quote_spanned! { binding.ast().span() =>
-                    ::ttt::Substitute::<#subst_ty>::substitute(
-                        #binding,
-                        &::ttt::DeBruijnIndexed::increment_indices(
-                            _ast_param_other),
-                        _ast_param_var + 1)?
+                    {
+                        let __ttt_offset = #offset;
+                        for (__ttt_position, __ttt_elem) in ::std::iter::Iterator::enumerate(
+                            ::std::iter::IntoIterator::into_iter(&mut *#binding)
+                        ) {
+                            ::ttt::SubstituteInPlace::<#subst_ty>::subst_all_in_place_from(
+                                __ttt_elem,
+                                _ast_param_subst,
+                                _ast_param_depth + __ttt_offset + __ttt_position)?;
+                        }
+                    }
+                }
+            } else if let Some(count) = binder_count(variant, binding) {
+                match binder_sort(binding) {
+                    Some(binder_sort) => {
+                        let binder_sort = sort_value(Some(&binder_sort));
+                        let subst_sort = self.subst_sort();
+                        quote_spanned! { binding.ast().span() =>
+                            {
+                                let __ttt_count = #count;
+                                if ::ttt::Sort::overlap(#binder_sort, #subst_sort) {
+                                    ::ttt::SubstituteInPlace::<#subst_ty>::subst_all_in_place_from(
+                                        #binding,
+                                        _ast_param_subst,
+                                        _ast_param_depth + __ttt_count)?;
+                                } else {
+                                    ::ttt::SubstituteInPlace::<#subst_ty>::subst_all_in_place_from(
+                                        #binding,
+                                        &::ttt::Subst::shift_terms::<#subst_ty>(
+                                            _ast_param_subst, #binder_sort, __ttt_count),
+                                        _ast_param_depth)?;
+                                }
+                            }
+                        }
+                    }
+                    None => quote_spanned! { binding.ast().span() =>
+                        ::ttt::SubstituteInPlace::<#subst_ty>::subst_all_in_place_from(
+                            #binding,
+                            _ast_param_subst,
+                            _ast_param_depth + #count)?;
+                    },
                 }
             } else {
                 quote_spanned! { binding.ast().span() =>
-                    ::ttt::Substitute::<#subst_ty>::substitute(
+                    ::ttt::SubstituteInPlace::<#subst_ty>::subst_all_in_place_from(
                         #binding,
-                        _ast_param_other,
-                        _ast_param_var)?
+                        _ast_param_subst,
+                        _ast_param_depth)?;
                 }
             }
         });
         quote! {
-            ::std::result::Result::Ok(#ctor)
+            #(#field_substs)*
+            ::std::result::Result::Ok(())
+        }
+    }
+
+    fn variant_in_place(&self, variant: &VariantInfo<'_>) -> TokenStream {
+        if let Some(var_field) =
+            variant.find_binding_with_attribute(DEBRUIJN_VAR_ATTR)
+        {
+            self.debruijn_index_variant_in_place(variant, var_field)
+        } else if let Some(var_field) =
+            variant.find_binding_with_attribute(VAR_WRAPPER_ATTR)
+        {
+            self.variable_variant_in_place(variant, var_field)
+        } else {
+            self.generic_variant_in_place(variant)
         }
     }
 
+    /// The substitution of a variant of a variable wrapper, whose target is
+    /// the substituted type.
     fn variant_impl(&self, variant: &VariantInfo<'_>) -> TokenStream {
         if let Some(var_field) =
             variant.find_binding_with_attribute(DEBRUIJN_VAR_ATTR)
         {
-            self.debruijn_index_variant_impl(variant, var_field)
+            self.debruijn_index_variant_impl(variant, var_field, false)
+        } else {
+            let var_field = variant
+                .find_binding_with_attribute(VAR_WRAPPER_ATTR)
+                .expect("Every variant of a variable wrapper has a variable");
+            self.variable_variant_impl(variant, var_field, false)
+        }
+    }
+
+    /// The substitution of a variant of a type whose target is itself, as
+    /// an `Option` which is `None` if the substitution doesn't change it.
+    fn changed_variant_impl(&self, variant: &VariantInfo<'_>) -> TokenStream {
+        if let Some(var_field) =
+            variant.find_binding_with_attribute(DEBRUIJN_VAR_ATTR)
+        {
+            self.debruijn_index_variant_impl(variant, var_field, true)
         } else if let Some(var_field) =
-            variant.find_binding_with_attribute("variable")
+            variant.find_binding_with_attribute(VAR_WRAPPER_ATTR)
         {
-            self.variable_variant_impl(variant, var_field)
+            self.variable_variant_impl(variant, var_field, true)
         } else {
             self.generic_variant_impl(variant)
         }
     }
 }
 
+/// The name of the variable holding the substituted value of a field, if it
+/// changed.
+fn changed_ident(binding: &BindingInfo) -> Ident {
+    format_ident!("__ttt_changed_{}", binding.binding)
+}
+
+/// The cases of a variable variant for
+/// [`SubstDerive::changed_variant_impl`], given the expressions for a
+/// replaced and a renumbered variable. A variable renumbered to its own index
+/// is unchanged. If the variable is `never_replaced`, the replaced case is
+/// unreachable, so is left as it is.
+fn changed_cases(
+    equal_case: TokenStream,
+    renamed_case: TokenStream,
+    never_replaced: bool,
+) -> (TokenStream, TokenStream, TokenStream) {
+    let equal_case = if never_replaced {
+        equal_case
+    } else {
+        quote!(::std::option::Option::Some(#equal_case))
+    };
+    (
+        quote!(::std::option::Option::None),
+        equal_case,
+        quote! {
+            if __ttt_new_index + _ast_param_depth == __ttt_index {
+                ::std::option::Option::None
+            } else {
+                ::std::option::Option::Some(#renamed_case)
+            }
+        },
+    )
+}
+
 impl ToTokens for SubstDerive<'_> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let subst_expr_impl =
-            self.ast.each_variant(|variant| self.variant_impl(variant));
-        let subst_expr_impl = quote! {
-            match self {
-                #subst_expr_impl
-            }
+        let subst_type = &self.ty;
+        // Unchanged terms can only be kept when the target is the type
+        // itself, so a variable wrapper is always rebuilt.
+        let (subst_all_body, changed_impls) = if self.ast_is_var_wrapper() {
+            let subst_all_impl =
+                self.ast.each_variant(|variant| self.variant_impl(variant));
+            let subst_all_body = guard_stack(
+                self.ast,
+                quote! {
+                    match self {
+                        #subst_all_impl
+                    }
+                },
+            );
+            (subst_all_body, quote!())
+        } else {
+            let changed_impl = self
+                .ast
+                .each_variant(|variant| self.changed_variant_impl(variant));
+            let changed_body = guard_stack(
+                self.ast,
+                quote! {
+                    match self {
+                        #changed_impl
+                    }
+                },
+            );
+            let unchanged = self.ast.each_variant(|variant| {
+                variant.construct_from_bindings(
+                    |binding| quote!(::std::clone::Clone::clone(#binding)),
+                )
+            });
+            let subst_all_body = quote! {
+                ::std::result::Result::Ok(
+                    match ::ttt::Substitute::<#subst_type>::subst_changed_from(
+                        self,
+                        _ast_param_subst,
+                        _ast_param_depth,
+                    )? {
+                        ::std::option::Option::Some(__ttt_changed) => __ttt_changed,
+                        ::std::option::Option::None => match self {
+                            #unchanged
+                        },
+                    }
+                )
+            };
+            // Shared terms are kept if the substitution doesn't change them.
+            let shared_impls = [
+                (quote!(subst_all_rc_from), quote!(::std::rc::Rc)),
+                (quote!(subst_all_arc_from), quote!(::std::sync::Arc)),
+            ]
+            .into_iter()
+            .map(|(method, ptr)| {
+                quote! {
+                    fn #method<__TTTSubstStorage: ::std::borrow::Borrow<#subst_type>>(
+                        __ttt_this: &#ptr<Self>,
+                        _ast_param_subst: &::ttt::Subst<__TTTSubstStorage>,
+                        _ast_param_depth: usize,
+                    ) -> Result<#ptr<Self::Target>, Self::Error> {
+                        ::std::result::Result::Ok(
+                            match ::ttt::Substitute::<#subst_type>::subst_changed_from(
+                                &**__ttt_this,
+                                _ast_param_subst,
+                                _ast_param_depth,
+                            )? {
+                                ::std::option::Option::Some(__ttt_changed) =>
+                                    #ptr::new(__ttt_changed),
+                                ::std::option::Option::None => #ptr::clone(__ttt_this),
+                            }
+                        )
+                    }
+                }
+            });
+            let changed_impls = quote! {
+                fn subst_changed_from<__TTTSubstStorage: ::std::borrow::Borrow<#subst_type>>(
+                    &self,
+                    _ast_param_subst: &::ttt::Subst<__TTTSubstStorage>,
+                    _ast_param_depth: usize,
+                ) -> Result<::std::option::Option<Self::Target>, Self::Error> {
+                    #changed_body
+                }
+
+                #(#shared_impls)*
+            };
+            (subst_all_body, changed_impls)
         };
 
-        let subst_type = &self.ty;
         let subst_target_type = self.subst_target_type();
+        let error_type = &self.error_type;
 
         let extra_generics = &self.impl_generics;
         let extra_generics = if !extra_generics.is_empty() {
@@ -230,21 +826,158 @@ impl ToTokens for SubstDerive<'_> {
             quote!()
         };
 
-        // TODO: #[subst_infallible] attribute to check that all possible substitutions will be valid,
-        // and set Error to ::std::convert::Infallible.
+        // Infallible substitutions are instantiated to the target directly.
+        let (instantiate_output, unwrap_result) = if self.infallible {
+            (
+                quote!(#subst_target_type),
+                quote! {
+                    match __ttt_result {
+                        ::std::result::Result::Ok(__ttt_target) => __ttt_target,
+                        ::std::result::Result::Err(__ttt_never) => match __ttt_never {},
+                    }
+                },
+            )
+        } else {
+            (
+                quote!(::std::result::Result<#subst_target_type, #error_type>),
+                quote!(__ttt_result),
+            )
+        };
+
+        self.ast.gen_impl(quote! {
+            gen impl #extra_generics ::ttt::Instantiate<#subst_type> for @Self {
+                type Output = #instantiate_output;
+
+                fn instantiate(&self, __ttt_arg: &#subst_type) -> Self::Output {
+                    let __ttt_result =
+                        ::ttt::Substitute::<#subst_type>::substitute(self, __ttt_arg, 0);
+                    #unwrap_result
+                }
+
+                fn instantiate_many(&self, __ttt_args: &[#subst_type]) -> Self::Output {
+                    let __ttt_result = ::ttt::Substitute::<#subst_type>::subst_all_from(
+                        self,
+                        &::ttt::Subst::instantiate_slice(__ttt_args),
+                        0,
+                    );
+                    #unwrap_result
+                }
+            }
+        }).to_tokens(tokens);
+
         self.ast.gen_impl(quote! {
             gen impl #extra_generics ::ttt::Substitute<#subst_type> for @Self {
                 type Target = #subst_target_type;
-                type Error = ::ttt::SubstError;
+                type Error = #error_type;
 
                 fn substitute(&self, _ast_param_other: &#subst_type, _ast_param_var: usize) -> Result<Self::Target, Self::Error> {
-                    #subst_expr_impl
+                    ::ttt::Substitute::<#subst_type>::subst_all_from(
+                        self,
+                        &::ttt::Subst::single(_ast_param_var, _ast_param_other),
+                        0,
+                    )
                 }
+
+                fn subst_all_from<__TTTSubstStorage: ::std::borrow::Borrow<#subst_type>>(
+                    &self,
+                    _ast_param_subst: &::ttt::Subst<__TTTSubstStorage>,
+                    _ast_param_depth: usize,
+                ) -> Result<Self::Target, Self::Error> {
+                    #subst_all_body
+                }
+
+                #changed_impls
+            }
+        }).to_tokens(tokens);
+
+        // `SubstituteInPlace` is only implemented on request, as every field
+        // must implement it too. Substitution into a variable wrapper changes
+        // the type of the term, so can't be done in place.
+        if !has_ttt_option(self.ast, IN_PLACE_OPTION)
+            || self.ast_is_var_wrapper()
+        {
+            return;
+        }
+
+        // The fields of types which inherit their substituted types are only
+        // known to implement `Substitute`, so are rebuilt by the default
+        // implementation.
+        let in_place_impl = if self.impl_generics.is_empty() {
+            let mut ast = self.ast.clone();
+            ast.bind_with(|_| synstructure::BindStyle::RefMut);
+            let in_place_body =
+                ast.each_variant(|variant| self.variant_in_place(variant));
+            let in_place_body = guard_stack(
+                self.ast,
+                quote! {
+                    match *self {
+                        #in_place_body
+                    }
+                },
+            );
+            quote! {
+                fn subst_all_in_place_from<__TTTSubstStorage: ::std::borrow::Borrow<#subst_type>>(
+                    &mut self,
+                    _ast_param_subst: &::ttt::Subst<__TTTSubstStorage>,
+                    _ast_param_depth: usize,
+                ) -> Result<(), Self::Error> {
+                    #in_place_body
+                }
+            }
+        } else {
+            quote!()
+        };
+
+        self.ast.gen_impl(quote! {
+            gen impl #extra_generics ::ttt::SubstituteInPlace<#subst_type> for @Self {
+                #in_place_impl
             }
         }).to_tokens(tokens);
     }
 }
 
+/// The name of a sort or substituted type, with `Self` replaced by the name of
+/// the deriving type and a leading `crate::` or `self::` removed, so that the
+/// usual ways of writing a type which is in scope compare equal.
+fn sort_name(ast: &Structure, ty: &Type) -> String {
+    let mut ty = ty.clone();
+    if let Type::Path(TypePath { qself: None, path }) = &mut ty {
+        if path.is_ident("Self") {
+            ty = type_ident(ast.ast().ident.clone());
+        } else if path.leading_colon.is_none()
+            && path.segments.len() > 1
+            && (path.segments[0].ident == "crate"
+                || path.segments[0].ident == "self")
+        {
+            path.segments = path.segments.iter().skip(1).cloned().collect();
+        }
+    }
+    ty.to_token_stream().to_string()
+}
+
+/// Infallible substitutions decide which variables are replaced by comparing
+/// their sorts with the substituted types by name, so each sort must be
+/// written as one of the substituted types for the comparison to be sound.
+fn check_infallible_sorts(ast: &Structure, types: &[Type]) {
+    let names: Vec<String> =
+        types.iter().map(|ty| sort_name(ast, ty)).collect();
+    for variant in ast.variants() {
+        for binding in variant.bindings() {
+            let Some(sort) = var_sort(binding) else {
+                continue;
+            };
+            if !names.contains(&sort_name(ast, &sort)) {
+                abort!(sort.span(),
+                    "The sort of a variable in an infallible substitution must be one of the substituted types";
+                    note = "Sorts are compared with the types in `#[{}(...)]` by name, so `{}` could be an alias of a substituted type.",
+                        SUBST_TYPES_ATTR, sort.to_token_stream();
+                    help = "Write the sort in the same way as in `#[{}(...)]`, or add it to the substituted types.", SUBST_TYPES_ATTR;
+                );
+            }
+        }
+    }
+}
+
 /// Extracts the list of types which can be substituted into the deriving type.
 /// These should be specified as an attribute `#[SubstTypes(Type1, Type2, ...)]`
 /// on the type for which substitution is being derived.
@@ -270,9 +1003,28 @@ fn substitutee_types(ast: &Structure) -> Vec<Type> {
 pub fn derive(mut ast: Structure) -> TokenStream {
     ast.bind_with(|_| synstructure::BindStyle::Move);
     ast.add_bounds(synstructure::AddBounds::Generics);
+    let infallible = ast.find_attribute(SUBST_INFALLIBLE_ATTR);
+    let error_type = ast.parse_attribute::<Type>(SUBST_ERROR_ATTR);
+    if let (Some(attr), Some(error_type)) = (&infallible, &error_type) {
+        abort!(error_type.span(),
+            "`#[{}(...)]` cannot be used with `#[{}]`", SUBST_ERROR_ATTR, SUBST_INFALLIBLE_ATTR;
+            note = attr.span() => "Infallible substitutions use `std::convert::Infallible` as the error type.";
+        );
+    }
+    let error_type = match (&infallible, error_type) {
+        (Some(_), _) => parse_quote!(::std::convert::Infallible),
+        (None, Some(error_type)) => error_type,
+        (None, None) => parse_quote!(::ttt::SubstError),
+    };
     if let Some(passthrough_ty) =
         ast.parse_attribute::<Type>("inherit_subst_types")
     {
+        if let Some(attr) = infallible {
+            abort!(attr.span(),
+                "`#[{}]` cannot be used with `#[inherit_subst_types(...)]`", SUBST_INFALLIBLE_ATTR;
+                note = "The substituted types are only known when the impl is used, so the substitutions can't be checked.";
+            );
+        }
         // ast.add_impl_generic(parse_quote!(__TTTSubstType));
         ast.add_where_predicate(parse_quote! {
             #passthrough_ty : ::ttt::Substitute<__TTTSubstType, Error = ::ttt::SubstError, Target = #passthrough_ty>
@@ -283,16 +1035,24 @@ pub fn derive(mut ast: Structure) -> TokenStream {
             impl_generics: vec![
                 parse_quote!(__TTTSubstType : ::ttt::DeBruijnIndexed),
             ],
+            infallible: false,
+            error_type,
         }
         .to_token_stream()
     } else {
-        substitutee_types(&ast)
+        let types = substitutee_types(&ast);
+        if infallible.is_some() {
+            check_infallible_sorts(&ast, &types);
+        }
+        types
             .into_iter()
             .map(|ty| {
                 SubstDerive {
                     ast: &ast,
                     ty,
                     impl_generics: Vec::new(),
+                    infallible: infallible.is_some(),
+                    error_type: error_type.clone(),
                 }
                 .to_token_stream()
             })
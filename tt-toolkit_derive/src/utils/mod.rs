@@ -77,11 +77,6 @@ pub trait VariantInfoExt {
 }
 
 pub trait StructureExt {
-    fn each_construct(
-        &self,
-        f: impl Fn(&BindingInfo) -> TokenStream,
-    ) -> TokenStream;
-
     fn type_name(&self) -> Type;
 }
 
@@ -109,13 +104,6 @@ impl VariantInfoExt for VariantInfo<'_> {
 }
 
 impl StructureExt for Structure<'_> {
-    fn each_construct(
-        &self,
-        f: impl Fn(&BindingInfo) -> TokenStream,
-    ) -> TokenStream {
-        self.each_variant(|variant| variant.construct_from_bindings(&f))
-    }
-
     fn type_name(&self) -> Type {
         let ident = &self.ast().ident;
         let (_, ty_generics, _) = self.ast().generics.split_for_impl();
@@ -0,0 +1,129 @@
+use ttt::{
+    AlphaEq, AlphaEquivalent, Attributed, AutoContextualEq, CheckAttribute,
+    Context, DeBruijnIndexed, ListContext,
+};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, AlphaEq)]
+enum Ty {
+    Unit,
+    Var(#[var_index] usize, #[var_name] String),
+    Forall {
+        #[binding_name]
+        name: String,
+        #[binding]
+        body: Box<Ty>,
+    },
+    Func(Box<Ty>, Box<Ty>),
+    Named(#[global_ref] String),
+    Spanned(#[metadata] (usize, usize), Box<Ty>),
+}
+
+impl<E, C: Context<E>> AutoContextualEq<E, C> for Ty {
+    type Impl = AlphaEq<Ty>;
+}
+
+fn var(var: usize, name: &str) -> Ty {
+    Ty::Var(var, name.to_string())
+}
+
+fn forall(name: &str, body: Ty) -> Ty {
+    Ty::Forall {
+        name: name.to_string(),
+        body: Box::new(body),
+    }
+}
+
+fn func(src: Ty, tgt: Ty) -> Ty {
+    Ty::Func(Box::new(src), Box::new(tgt))
+}
+
+#[test]
+fn binder_names_are_ignored() {
+    let lhs = forall("a", func(var(0, "a"), var(0, "a")));
+    let rhs = forall("b", func(var(0, "b"), var(0, "b")));
+
+    assert_ne!(lhs, rhs);
+    assert!(lhs.alpha_eq(&rhs));
+}
+
+#[test]
+fn variables_are_compared_by_index() {
+    let lhs = forall("a", forall("b", var(0, "b")));
+    let rhs = forall("a", forall("b", var(1, "a")));
+
+    assert!(!lhs.alpha_eq(&rhs));
+    assert!(!Ty::Unit.alpha_eq(&lhs));
+}
+
+#[test]
+fn metadata_is_ignored() {
+    let lhs = Ty::Spanned((0, 4), Box::new(Ty::Unit));
+    let rhs = Ty::Spanned((7, 11), Box::new(Ty::Unit));
+
+    assert!(lhs.alpha_eq(&rhs));
+    assert!(!lhs.alpha_eq(&Ty::Spanned((0, 4), Box::new(var(0, "a")))));
+}
+
+#[test]
+fn global_refs_are_compared() {
+    let nat = Ty::Named("Nat".to_string());
+
+    assert!(nat.alpha_eq(&Ty::Named("Nat".to_string())));
+    assert!(!nat.alpha_eq(&Ty::Named("Bool".to_string())));
+}
+
+#[test]
+fn containers_are_compared_elementwise() {
+    let lhs = vec![Some(forall("a", var(0, "a"))), None];
+    let rhs = vec![Some(forall("b", var(0, "b"))), None];
+
+    assert!(lhs.alpha_eq(&rhs));
+    assert!(!lhs.alpha_eq(&rhs[..1].to_vec()));
+    assert!((Ty::Unit, lhs.clone()).alpha_eq(&(Ty::Unit, rhs)));
+}
+
+#[derive(AlphaEq)]
+struct Pair<T> {
+    #[metadata]
+    label: &'static str,
+    first: T,
+    second: T,
+}
+
+#[test]
+fn generic_structs() {
+    let lhs = Pair {
+        label: "lhs",
+        first: forall("a", var(0, "a")),
+        second: Ty::Unit,
+    };
+    let rhs = Pair {
+        label: "rhs",
+        first: forall("b", var(0, "b")),
+        second: Ty::Unit,
+    };
+
+    assert!(lhs.alpha_eq(&rhs));
+    assert_ne!(lhs.label, rhs.label);
+}
+
+#[derive(Clone, Attributed, DeBruijnIndexed)]
+#[bidir_type(Ty)]
+enum Expr {
+    #[synth(Ty; var => lookup(*var))]
+    Var(#[var_index] usize),
+
+    #[synth(Ty; (ty, expr) =>
+        let true = check(expr, ty);
+        ty.clone()
+    )]
+    Annot(Ty, Box<Expr>),
+}
+
+#[test]
+fn attributes_are_compared_up_to_alpha_equivalence() {
+    let ctx = ListContext::empty().append(forall("a", var(0, "a")));
+    let expr = Expr::Annot(forall("b", var(0, "b")), Box::new(Expr::Var(0)));
+
+    assert!(expr.check(&forall("c", var(0, "c")), &ctx, &()).unwrap());
+}
@@ -45,7 +45,10 @@ fn check_lambda() {
     let expr = Lam(Unit.into());
     let ty = Ty::Func(Ty::Unit.into(), Ty::Unit.into());
 
-    assert!(CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty()).unwrap())
+    assert!(
+        CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty(), &())
+            .unwrap()
+    )
 }
 
 #[test]
@@ -37,7 +37,10 @@ fn check_lambda() {
     let expr = Lam(Ty::Unit.into(), Unit.into());
     let ty = Ty::Func(Ty::Unit.into(), Ty::Unit.into());
 
-    assert!(CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty()).unwrap())
+    assert!(
+        CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty(), &())
+            .unwrap()
+    )
 }
 
 #[test]
@@ -50,12 +53,15 @@ fn check_pair() {
         use Ty::*;
         Prod(Unit.into(), Unit.into())
     };
-    assert!(CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty()).unwrap())
+    assert!(
+        CheckAttribute::<Ty>::check(&expr, &ty, &Context::empty(), &())
+            .unwrap()
+    )
 }
 
 #[test]
 fn check_lambda_app() {
     use Expr::*;
     let expr = App(Lam(Ty::Unit.into(), Unit.into()).into(), Unit.into());
-    assert!(CheckAttribute::<Ty>::check(&expr, &Ty::Unit, &Context::empty()).unwrap())
+    assert!(CheckAttribute::<Ty>::check(&expr, &Ty::Unit, &Context::empty(), &()).unwrap())
 }
\ No newline at end of file
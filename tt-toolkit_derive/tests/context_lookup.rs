@@ -0,0 +1,103 @@
+use ttt::{
+    Attributed, CheckAttribute, Context, DeBruijnIndexed, DefaultError,
+    EvalError, Evaluate, ListContext, SynthAttribute, UnboundVariable,
+    contextual_eq::{AutoContextualEq, SyntacticEq},
+};
+
+#[test]
+fn lookup_reports_context_length() {
+    let ctx = ListContext::empty().append(1).append(2);
+    assert_eq!(ctx.lookup(0), Ok(2));
+    assert_eq!(ctx.lookup(1), Ok(1));
+    assert_eq!(ctx.lookup(2), Err(UnboundVariable { var: 2, len: 2 }));
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Ty {
+    Func(Box<Ty>, Box<Ty>),
+    Unit,
+}
+
+impl<E, C: Context<E>> AutoContextualEq<E, C> for Ty {
+    type Impl = SyntacticEq<Ty>;
+}
+
+#[derive(Clone, Attributed, DeBruijnIndexed)]
+#[synth_type(Ty)]
+enum Expr {
+    #[synth(Ty; var => lookup(*var))]
+    Var(#[var_index] usize),
+}
+
+#[test]
+fn synth_unbound_variable() {
+    let ctx = ListContext::empty().append(Ty::Unit);
+    assert_eq!(Expr::Var(0).synth(&ctx, &()), Ok(Ty::Unit));
+    assert_eq!(
+        Expr::Var(3).synth(&ctx, &()),
+        Err(DefaultError::UnboundVariable(UnboundVariable {
+            var: 3,
+            len: 1
+        }))
+    );
+}
+
+#[derive(Clone, Attributed, DeBruijnIndexed)]
+#[bidir_type(Ty)]
+enum BidirExpr {
+    #[synth(Ty; var => lookup(*var))]
+    Var(#[var_index] usize),
+
+    #[check(Ty; body : Ty::Func(src, tgt) =>
+        bind src { check(body, tgt) }
+    )]
+    Lam(Box<BidirExpr>),
+}
+
+#[test]
+fn check_unbound_variable() {
+    let ty = Ty::Func(Ty::Unit.into(), Ty::Unit.into());
+    let expr = BidirExpr::Lam(BidirExpr::Var(1).into());
+    assert_eq!(
+        expr.check(&ty, &Context::empty(), &()),
+        Err(DefaultError::UnboundVariable(UnboundVariable {
+            var: 1,
+            len: 1
+        }))
+    );
+
+    let expr = BidirExpr::Lam(BidirExpr::Var(0).into());
+    assert_eq!(expr.check(&ty, &Context::empty(), &()), Ok(true));
+}
+
+#[derive(Clone, DeBruijnIndexed, Evaluate, PartialEq, Debug)]
+enum Value {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<Value>),
+    Pair(Box<Value>, Box<Value>),
+}
+
+#[test]
+fn evaluate_looks_up_variables() {
+    use Value::*;
+    let ctx = ListContext::empty()
+        .append(Some(Lambda(Var(1).into())))
+        .append(None);
+    let expr = Pair(Var(0).into(), Var(1).into());
+
+    assert_eq!(
+        expr.evaluate(&ctx, &(), false),
+        Ok(Pair(Var(0).into(), Lambda(Var(3).into()).into()))
+    );
+    assert_eq!(
+        Var(2).evaluate(&ctx, &(), false),
+        Err(EvalError::UnboundVariable(UnboundVariable {
+            var: 2,
+            len: 2
+        }))
+    );
+    assert_eq!(
+        Lambda(Var(1).into()).evaluate(&ctx, &(), true),
+        Ok(Lambda(Var(1).into()))
+    );
+}
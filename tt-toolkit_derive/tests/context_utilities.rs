@@ -0,0 +1,96 @@
+use std::fmt;
+
+use ttt::{
+    Context, DeBruijnIndexed, ListContext, NamedContext, TreeContext,
+    VecContext,
+};
+
+fn assert_drops<C: Context<usize> + Clone>() {
+    let ctx = C::empty().extend(0..6);
+
+    assert_eq!(ctx.len(), 6);
+    assert_eq!(ctx.get(0), Some(5));
+    assert_eq!(ctx.drop_n(2).iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+    assert_eq!(ctx.truncate(2).iter().collect::<Vec<_>>(), vec![1, 0]);
+    assert!(ctx.drop_n(10).is_empty());
+    assert_eq!(ctx.truncate(10).len(), 6);
+    assert_eq!(ctx.drop_n(3).append(7).get(0), Some(7));
+}
+
+#[test]
+fn contexts_extend_and_drop() {
+    assert_drops::<ListContext<usize>>();
+    assert_drops::<VecContext<usize>>();
+    assert_drops::<TreeContext<usize>>();
+}
+
+#[test]
+fn named_context_drops_names() {
+    let ctx = NamedContext::empty()
+        .append_named("x", 0)
+        .append_named("y", 1)
+        .append_named("x", 2);
+
+    let dropped = ctx.drop_n(1);
+    assert_eq!(dropped.name_of(0), Some("y"));
+    assert_eq!(dropped.append_named("x", 3).name_of(0), Some("x1"));
+}
+
+#[test]
+fn named_context_freshens_past_used_suffixes() {
+    let ctx = NamedContext::empty()
+        .append_named("x", 0)
+        .append_named("x1", 1)
+        .append_named("x", 2);
+    let snapshot = ctx.clone();
+
+    assert_eq!(ctx.name_of(0), Some("x2"));
+    assert_eq!(ctx.fresh_name("x"), "x3");
+    assert_eq!(snapshot.append_named("y", 3).name_of(1), Some("x2"));
+    assert!(format!("{snapshot:?}").contains("x2"));
+}
+
+#[test]
+fn list_context_from_iter() {
+    let ctx: ListContext<usize> = (0..3).collect();
+
+    assert_eq!(ctx, ListContext::empty().append(0).append(1).append(2));
+    assert_ne!(ctx, ListContext::empty().extend([0, 1]));
+    assert_eq!(ctx.len(), 3);
+    assert_eq!(ctx.clone().get(0), Some(2));
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed)]
+enum Ty {
+    Base,
+    Var(#[var_index] usize),
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Base => write!(f, "B"),
+            Ty::Var(var) => write!(f, "#{var}"),
+        }
+    }
+}
+
+#[test]
+fn list_context_display_shifts_entries() {
+    let ctx: ListContext<Ty> =
+        [Ty::Base, Ty::Var(0), Ty::Var(0)].into_iter().collect();
+
+    assert_eq!(ctx.to_string(), "x0 : B, x1 : #0, x2 : #0 ⊢");
+    assert_eq!(
+        ctx.display_shifted().to_string(),
+        "x0 : B, x1 : #2, x2 : #1 ⊢"
+    );
+    assert_eq!(ListContext::<Ty>::empty().to_string(), "⊢");
+}
+
+#[test]
+fn list_context_displays_entries_without_indices() {
+    let ctx: ListContext<&str> = ["A", "B"].into_iter().collect();
+
+    assert_eq!(ctx.to_string(), "x0 : A, x1 : B ⊢");
+}
@@ -0,0 +1,157 @@
+use ttt::{
+    Attributed, CheckAttribute, Context, DeBruijnIndexed, ListContext,
+    PartialSynthAttribute, TreeContext, VecContext,
+    contextual_eq::{AutoContextualEq, SyntacticEq},
+};
+
+fn assert_agrees<C: Context<usize>>(len: usize) {
+    let list = (0..len).fold(ListContext::empty(), |ctx, i| ctx.append(i));
+    let ctx = (0..len).fold(C::empty(), |ctx, i| ctx.append(i));
+
+    assert_eq!(ctx.len(), len);
+    assert_eq!(
+        ctx.iter().collect::<Vec<_>>(),
+        list.iter().collect::<Vec<_>>()
+    );
+    for var in 0..=len {
+        assert_eq!(ctx.get(var), list.get(var));
+        assert_eq!(ctx.get_level(var), list.get_level(var));
+    }
+}
+
+#[test]
+fn contexts_agree_with_list_context() {
+    for len in [0, 1, 2, 3, 6, 7, 15, 100] {
+        assert_agrees::<VecContext<usize>>(len);
+        assert_agrees::<TreeContext<usize>>(len);
+    }
+}
+
+#[test]
+fn tree_context_extensions_are_persistent() {
+    let ctx = (0..10).fold(TreeContext::empty(), |ctx, i| ctx.append(i));
+    let left = ctx.append(10);
+    let right = ctx.append(20).append(21);
+
+    assert_eq!(ctx.get(0), Some(9));
+    assert_eq!(left.get(0), Some(10));
+    assert_eq!(right.get(1), Some(20));
+    assert_eq!(left.get(10), Some(0));
+    assert_eq!(right.get(11), Some(0));
+    assert_eq!(right.len(), 12);
+}
+
+#[test]
+fn vec_context_extensions_are_persistent() {
+    let ctx = (0..10).fold(VecContext::empty(), |ctx, i| ctx.append(i));
+    let left = ctx.append(10);
+    let right = ctx.append(20).append(21);
+    let shorter = left.drop_n(5).append(30);
+
+    assert_eq!(ctx.get(0), Some(9));
+    assert_eq!(left.get(0), Some(10));
+    assert_eq!(right.get(1), Some(20));
+    assert_eq!(left.get(10), Some(0));
+    assert_eq!(right.get(11), Some(0));
+    assert_eq!(right.len(), 12);
+    assert_eq!(
+        shorter.iter().collect::<Vec<_>>(),
+        vec![30, 5, 4, 3, 2, 1, 0]
+    );
+    assert_eq!(left.get(5), Some(5));
+}
+
+#[test]
+fn vec_context_reuses_dropped_extensions() {
+    let ctx: VecContext<usize> = (0..5).collect();
+    {
+        let inner = ctx.append(5).append(6);
+        assert_eq!(inner.get(1), Some(5));
+    }
+    let sibling = ctx.append(7);
+    assert_eq!(sibling.iter().collect::<Vec<_>>(), vec![7, 4, 3, 2, 1, 0]);
+    assert_eq!(ctx.len(), 5);
+    assert_eq!(ctx.extend(ctx.iter()).get(9), Some(0));
+}
+
+#[test]
+fn vec_context_snapshots() {
+    let mut ctx: VecContext<usize> = (0..5).collect();
+    let snapshot = ctx.clone();
+    ctx.push(5);
+
+    assert_eq!(ctx.get(0), Some(5));
+    assert_eq!(snapshot.get(0), Some(4));
+    assert_eq!(snapshot.len(), 5);
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Ty {
+    Func(Box<Ty>, Box<Ty>),
+    Unit,
+}
+
+impl<E, C: Context<E>> AutoContextualEq<E, C> for Ty {
+    type Impl = SyntacticEq<Ty>;
+}
+
+macro_rules! lambda_calculus {
+    ($name:ident, $context:ty) => {
+        #[derive(Clone, Attributed, DeBruijnIndexed)]
+        #[bidir_type(Ty, context = $context)]
+        enum $name {
+            #[synth(Ty; _ => Ty::Unit)]
+            Unit,
+
+            #[synth(Ty; var => lookup(*var))]
+            Var(#[var_index] usize),
+
+            #[check(Ty; body : Ty::Func(src, tgt) =>
+                                bind src { check(body, tgt) }
+                            )]
+            Lam(Box<$name>),
+
+            #[synth(Ty; (Self::Lam(body), arg) =>
+                                let Some(src): Option<Ty> = try_synth(arg);
+                                bind src { try_synth(body) }
+                            )]
+            App(Box<$name>, Box<$name>),
+        }
+    };
+}
+
+lambda_calculus!(VecExpr, ttt::VecContext<Ty>);
+lambda_calculus!(TreeExpr, ttt::TreeContext<Ty>);
+
+fn unit_to_unit() -> Ty {
+    Ty::Func(Ty::Unit.into(), Ty::Unit.into())
+}
+
+#[test]
+fn vec_context_type_checking() {
+    use VecExpr::*;
+    let expr = Lam(Var(0).into());
+    let app = App(Lam(Var(0).into()).into(), Unit.into());
+
+    assert!(
+        expr.check(&unit_to_unit(), &VecContext::empty(), &())
+            .unwrap()
+    );
+    assert_eq!(
+        app.try_synth(&VecContext::empty(), &()).unwrap(),
+        Some(Ty::Unit)
+    );
+}
+
+#[test]
+fn tree_context_type_checking() {
+    use TreeExpr::*;
+    let ctx = TreeContext::empty().append(unit_to_unit());
+    let expr = Lam(Var(1).into());
+    let ty = Ty::Func(Ty::Unit.into(), unit_to_unit().into());
+
+    assert!(expr.check(&ty, &ctx, &()).unwrap());
+    assert!(!Var(0).check(&Ty::Unit, &ctx, &()).unwrap());
+    let app = App(Lam(Var(1).into()).into(), Unit.into());
+    assert_eq!(app.try_synth(&ctx, &()).unwrap(), Some(unit_to_unit()));
+}
@@ -0,0 +1,106 @@
+use ttt::{DeBruijnIndexed, ResolveVars, Substitute, VarIndex};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ix(u32);
+
+impl VarIndex for Ix {
+    fn to_index(self) -> usize {
+        self.0.to_index()
+    }
+
+    fn from_index(index: usize) -> Self {
+        Ix(u32::from_index(index))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute, ResolveVars)]
+enum Expr {
+    Var {
+        #[var_name]
+        name: String,
+        #[var_index]
+        index: Ix,
+    },
+    Lam {
+        #[binding_name]
+        name: String,
+        #[binding]
+        body: Box<Expr>,
+    },
+    App(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+enum CompactExpr {
+    Var(#[var_index] u16),
+    Lam(#[binding] Box<CompactExpr>),
+}
+
+fn var(name: &str, index: u32) -> Expr {
+    Expr::Var {
+        name: name.to_string(),
+        index: Ix(index),
+    }
+}
+
+fn lam(name: &str, body: Expr) -> Expr {
+    Expr::Lam {
+        name: name.to_string(),
+        body: Box::new(body),
+    }
+}
+
+#[test]
+fn shifts_newtype_indices() {
+    let x = lam("x", Expr::App(Box::new(var("x", 0)), Box::new(var("y", 1))));
+    assert_eq!(
+        x.increment_indices(),
+        lam("x", Expr::App(Box::new(var("x", 0)), Box::new(var("y", 2))))
+    );
+    assert_eq!(x.get_var(), None);
+    assert_eq!(var("y", 3).get_var(), Some(3));
+    assert!(x.occurs(0));
+}
+
+#[test]
+fn substitutes_newtype_indices() {
+    let x = lam("x", Expr::App(Box::new(var("y", 1)), Box::new(var("z", 2))));
+    assert_eq!(
+        x.substitute(&var("w", 4), 0),
+        Ok(lam(
+            "x",
+            Expr::App(Box::new(var("w", 5)), Box::new(var("z", 1)))
+        ))
+    );
+}
+
+#[test]
+fn resolves_newtype_indices() {
+    let x = lam("x", var("x", u32::MAX));
+    assert_eq!(
+        x.resolve_vars(&ttt::NameContext::new()).unwrap(),
+        lam("x", var("x", 0))
+    );
+}
+
+#[test]
+fn shifts_compact_indices() {
+    let x = CompactExpr::Lam(Box::new(CompactExpr::Var(3)));
+    assert_eq!(
+        x.increment_indices_by(2),
+        CompactExpr::Lam(Box::new(CompactExpr::Var(5)))
+    );
+}
+
+#[test]
+fn shifts_var_index() {
+    assert_eq!(Ix(3).shift(2), Ix(5));
+    assert_eq!(4u16.shift(0), 4);
+}
+
+#[test]
+#[should_panic(expected = "too large for type `u16`")]
+fn shifting_past_max_index_panics() {
+    let x = CompactExpr::Var(u16::MAX);
+    x.increment_indices();
+}
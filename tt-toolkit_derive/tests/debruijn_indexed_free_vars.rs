@@ -0,0 +1,56 @@
+use std::collections::BTreeSet;
+
+use ttt::DeBruijnIndexed;
+
+#[derive(DeBruijnIndexed, Debug, PartialEq, Clone)]
+enum LambdaExpr {
+    Var(#[metadata] usize, #[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    Let(Box<LambdaExpr>, #[binding(2)] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+fn var(index: usize) -> LambdaExpr {
+    LambdaExpr::Var(100, index)
+}
+
+fn lam(body: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn finds_free_variables() {
+    let x = app(var(0), app(var(3), var(0)));
+    assert_eq!(x.free_vars(), BTreeSet::from([0, 3]));
+    assert!(x.occurs(3));
+    assert!(!x.occurs(1));
+    assert!(!x.is_closed());
+    assert_eq!(x.max_free_index(), Some(3));
+}
+
+#[test]
+fn respects_binders() {
+    let x = lam(app(var(0), lam(var(2))));
+    assert_eq!(x.free_vars(), BTreeSet::from([0]));
+    assert!(x.occurs(0));
+    assert!(!x.occurs(2));
+
+    let x = lam(lam(app(var(0), var(1))));
+    assert!(x.is_closed());
+    assert_eq!(x.max_free_index(), None);
+
+    let x = LambdaExpr::Let(Box::new(var(1)), Box::new(app(var(1), var(4))));
+    assert_eq!(x.free_vars(), BTreeSet::from([1, 2]));
+}
+
+#[test]
+fn skips_metadata() {
+    // The metadata field holds a large number which is not an index
+    let x = lam(var(0));
+    assert!(x.is_closed());
+    assert!(!x.occurs(100));
+}
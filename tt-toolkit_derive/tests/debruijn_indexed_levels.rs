@@ -0,0 +1,117 @@
+use ttt::{Context, DeBruijnIndexed, ListContext, ResolveVars, Substitute};
+
+#[derive(DeBruijnIndexed, Substitute, Debug, PartialEq, Clone)]
+enum Term {
+    Var(#[var_index] usize),
+    Lam(#[binding] Box<Term>),
+    App(Box<Term>, Box<Term>),
+}
+
+#[derive(DeBruijnIndexed, Substitute, ResolveVars, Debug, PartialEq, Clone)]
+enum Value {
+    Neutral {
+        #[var_name]
+        name: String,
+        #[var_level]
+        level: usize,
+    },
+    Lam {
+        #[binding_name]
+        name: String,
+        #[binding]
+        body: Box<Value>,
+    },
+    App(Box<Value>, Box<Value>),
+}
+
+fn var(index: usize) -> Term {
+    Term::Var(index)
+}
+
+fn lam(body: Term) -> Term {
+    Term::Lam(Box::new(body))
+}
+
+fn app(f: Term, x: Term) -> Term {
+    Term::App(Box::new(f), Box::new(x))
+}
+
+fn neutral(name: &str, level: usize) -> Value {
+    Value::Neutral {
+        name: name.to_string(),
+        level,
+    }
+}
+
+fn value_lam(name: &str, body: Value) -> Value {
+    Value::Lam {
+        name: name.to_string(),
+        body: Box::new(body),
+    }
+}
+
+fn value_app(f: Value, x: Value) -> Value {
+    Value::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn converts_indices_to_levels() {
+    let x = app(var(0), lam(app(var(0), var(1))));
+    assert_eq!(
+        x.indices_to_levels(1),
+        app(var(0), lam(app(var(1), var(0))))
+    );
+    assert_eq!(
+        x.indices_to_levels(3),
+        app(var(2), lam(app(var(3), var(2))))
+    );
+}
+
+#[test]
+fn converts_levels_to_indices() {
+    let x = value_lam("y", value_app(neutral("x", 0), neutral("y", 1)));
+    assert_eq!(
+        x.levels_to_indices(1),
+        value_lam("y", value_app(neutral("x", 1), neutral("y", 0)))
+    );
+}
+
+#[test]
+fn levels_are_not_shifted() {
+    let x = value_lam("y", value_app(neutral("x", 0), neutral("y", 1)));
+    assert_eq!(x.increment_indices(), x);
+    assert!(x.is_closed());
+    assert_eq!(x.substitute(&neutral("z", 5), 0), Ok(x.clone()));
+}
+
+#[test]
+fn resolves_names_to_levels() {
+    let unresolved = |name| neutral(name, usize::MAX);
+    let x = value_lam("y", value_app(unresolved("x"), unresolved("y")));
+    let ctx = ttt::NameContext::new().append("x".to_string());
+    assert_eq!(
+        x.resolve_vars(&ctx).unwrap(),
+        value_lam("y", value_app(neutral("x", 0), neutral("y", 1)))
+    );
+}
+
+#[test]
+fn looks_up_context_by_level() {
+    let ctx = ListContext::empty().append(var(0)).append(var(1));
+    assert_eq!(ctx.get_level(0), Some(var(0)));
+    assert_eq!(ctx.get_level(1), Some(var(1)));
+    assert_eq!(ctx.get_level(2), None);
+    assert_eq!(ctx.get_level_shifted(0), Some(var(2)));
+}
+
+#[test]
+fn out_of_scope_variables_are_unchanged() {
+    use ttt::debruijn_indexed::{index_to_level, level_to_index};
+
+    assert_eq!(index_to_level(3, 2), None);
+    assert_eq!(level_to_index(2, 2), None);
+    assert_eq!(index_to_level(0, 2), Some(1));
+
+    let x = app(var(0), lam(var(3)));
+    assert_eq!(x.indices_to_levels(2), app(var(1), lam(var(3))));
+}
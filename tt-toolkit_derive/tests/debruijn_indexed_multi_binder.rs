@@ -0,0 +1,66 @@
+use ttt::DeBruijnIndexed;
+
+#[derive(DeBruijnIndexed, Debug, PartialEq, Clone)]
+enum Expr {
+    Var(#[var_index] usize),
+    LetPair(Box<Expr>, #[binding(2)] Box<Expr>),
+    Case {
+        scrutinee: Box<Expr>,
+        #[metadata]
+        arity: usize,
+        #[binding(count = self.arity)]
+        body: Box<Expr>,
+    },
+}
+
+#[test]
+fn fixed_count() {
+    use Expr::*;
+    let x = LetPair(Var(0).into(), Var(2).into());
+    let x = x.increment_indices();
+    assert_eq!(x, LetPair(Var(1).into(), Var(3).into()));
+
+    let x = LetPair(Var(0).into(), Var(1).into());
+    let x = x.increment_indices();
+    assert_eq!(x, LetPair(Var(1).into(), Var(1).into()));
+}
+
+#[test]
+fn count_from_sibling_field() {
+    use Expr::*;
+    let case = |arity, body| Case {
+        scrutinee: Var(0).into(),
+        arity,
+        body: Box::new(body),
+    };
+
+    let x = case(3, Var(3)).increment_indices();
+    assert_eq!(
+        x,
+        Case {
+            scrutinee: Var(1).into(),
+            arity: 3,
+            body: Var(4).into(),
+        }
+    );
+
+    let x = case(0, Var(0)).increment_indices();
+    assert_eq!(
+        x,
+        Case {
+            scrutinee: Var(1).into(),
+            arity: 0,
+            body: Var(1).into(),
+        }
+    );
+
+    let x = case(3, Var(2)).increment_indices();
+    assert_eq!(
+        x,
+        Case {
+            scrutinee: Var(1).into(),
+            arity: 3,
+            body: Var(2).into(),
+        }
+    );
+}
@@ -0,0 +1,77 @@
+use ttt::{DeBruijnIndexed, Renaming};
+
+#[derive(DeBruijnIndexed, Debug, PartialEq, Clone)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+fn var(index: usize) -> LambdaExpr {
+    LambdaExpr::Var(index)
+}
+
+fn lam(body: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn renames_free_variables() {
+    let x = app(var(0), lam(app(var(0), var(2))));
+    assert_eq!(
+        x.rename(&Renaming::swap(0, 1)),
+        app(var(1), lam(app(var(0), var(1))))
+    );
+    assert_eq!(x.rename(&Renaming::weaken(2)), x.increment_indices_by(2));
+    assert_eq!(x.rename(&Renaming::identity()), x);
+}
+
+#[test]
+fn permutes_variables() {
+    let x = app(var(0), app(var(1), var(2)));
+    let permutation = Renaming::permute(&[2, 0, 1]);
+    assert_eq!(x.rename(&permutation), app(var(2), app(var(0), var(1))));
+    assert_eq!(
+        x.rename(&permutation)
+            .rename(&permutation.inverse().unwrap()),
+        x
+    );
+}
+
+#[test]
+fn composes_renamings() {
+    let x = app(var(0), lam(app(var(1), var(3))));
+    let first = Renaming::swap(0, 2);
+    let second = Renaming::weaken(1);
+    assert_eq!(
+        x.rename(&first.compose(&second)),
+        x.rename(&first).rename(&second)
+    );
+    assert_eq!(
+        Renaming::swap(0, 1).compose(&Renaming::swap(0, 1)),
+        Renaming::identity()
+    );
+    assert!(Renaming::swap(3, 3).is_identity());
+}
+
+#[test]
+fn lifts_renamings() {
+    let renaming = Renaming::swap(0, 1);
+    let lifted = renaming.lift();
+    assert_eq!(lifted.apply(0), 0);
+    assert_eq!(lifted.apply(1), 2);
+    assert_eq!(lifted.apply(2), 1);
+    assert_eq!(lifted.apply(3), 3);
+    assert_eq!(lam(var(1)).rename(&renaming), lam(var(1).rename(&lifted)));
+}
+
+#[test]
+fn inverts_only_bijections() {
+    assert_eq!(Renaming::weaken(1).inverse(), None);
+    assert_eq!(Renaming::swap(0, 2).inverse(), Some(Renaming::swap(0, 2)));
+    assert_eq!(Renaming::swap(1, 0).to_string(), "[1, 0, ↑2]");
+}
@@ -0,0 +1,60 @@
+use ttt::{DeBruijnIndexed, StrengthenError};
+
+#[derive(DeBruijnIndexed, Debug, PartialEq, Clone)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+fn var(index: usize) -> LambdaExpr {
+    LambdaExpr::Var(index)
+}
+
+fn lam(body: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn strengthens_unused_variable() {
+    let x = app(var(0), lam(app(var(0), var(3))));
+    assert_eq!(
+        x.try_strengthen(1),
+        Ok(app(var(0), lam(app(var(0), var(2)))))
+    );
+}
+
+#[test]
+fn reports_occurring_variable() {
+    let x = app(var(0), lam(app(var(0), var(2))));
+    assert_eq!(x.try_strengthen(1), Err(StrengthenError { var: 1 }));
+}
+
+#[test]
+fn fallible_map_reports_first_error() {
+    let x = app(var(1), lam(var(4)));
+    let checked_decrement = |i: usize| i.checked_sub(2).ok_or(i);
+    assert_eq!(x.try_map_indices_from(0, checked_decrement), Err(1));
+    assert_eq!(x.try_map_indices_from(1, checked_decrement), Err(1));
+    assert_eq!(
+        lam(var(4)).try_map_indices_from(0, checked_decrement),
+        Ok(lam(var(2)))
+    );
+}
+
+#[test]
+fn fallible_map_stops_after_first_error() {
+    let x = app(var(0), app(var(1), var(2)));
+    let mut seen = Vec::new();
+    let result = x.try_map_indices_from(0, |i| {
+        seen.push(i);
+        if i == 1 { Err(i) } else { Ok(i) }
+    });
+
+    assert_eq!(result, Err(1));
+    assert_eq!(seen, vec![0, 1]);
+}
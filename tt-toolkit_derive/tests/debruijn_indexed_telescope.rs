@@ -0,0 +1,149 @@
+use ttt::{
+    Context, DeBruijnIndexed, Evaluate, ListContext, ResolveVars, Substitute,
+};
+
+#[derive(Clone, DeBruijnIndexed, Substitute, ResolveVars, PartialEq, Debug)]
+enum Ty {
+    Var {
+        #[var_name]
+        name: String,
+        #[var_index]
+        index: usize,
+    },
+    Pi {
+        #[binding_name]
+        names: Vec<String>,
+        #[telescope]
+        params: Vec<Ty>,
+        #[binding]
+        body: Box<Ty>,
+    },
+}
+
+fn var(name: &str, index: usize) -> Ty {
+    Ty::Var {
+        name: name.to_string(),
+        index,
+    }
+}
+
+// Pi (x : A) (y : B x) -> C x y
+fn pi(a: Ty, b: Ty, c: Ty) -> Ty {
+    Ty::Pi {
+        names: vec!["x".to_string(), "y".to_string()],
+        params: vec![a, b],
+        body: Box::new(c),
+    }
+}
+
+#[test]
+fn shifts_telescope_elements() {
+    let ty = pi(var("a", 0), var("b", 1), var("c", 2));
+    assert_eq!(
+        ty.increment_indices(),
+        pi(var("a", 1), var("b", 2), var("c", 3))
+    );
+
+    let ty = pi(var("a", 0), var("x", 0), var("y", 1));
+    assert_eq!(
+        ty.increment_indices(),
+        pi(var("a", 1), var("x", 0), var("y", 1))
+    );
+}
+
+#[test]
+fn substitutes_under_telescope() {
+    let ty = pi(var("a", 0), var("a", 1), var("a", 2));
+    let arg = var("z", 4);
+    assert_eq!(
+        ty.substitute(&arg, 0),
+        Ok(pi(var("z", 4), var("z", 5), var("z", 6)))
+    );
+}
+
+#[test]
+fn resolves_telescope_names() {
+    let unresolved = |name| var(name, usize::MAX);
+    let ty = pi(unresolved("a"), unresolved("x"), unresolved("y"));
+    let ctx = ttt::NameContext::new().append("a".to_string());
+    assert_eq!(
+        ty.resolve_vars(&ctx).unwrap(),
+        pi(var("a", 0), var("x", 0), var("y", 0))
+    );
+
+    let ty = pi(unresolved("a"), unresolved("a"), unresolved("x"));
+    assert_eq!(
+        ty.resolve_vars(&ctx).unwrap(),
+        pi(var("a", 0), var("a", 1), var("x", 1))
+    );
+}
+
+#[derive(Clone, DeBruijnIndexed, Substitute, Evaluate, PartialEq, Debug)]
+enum Term {
+    Var(#[var_index] usize),
+    Unit,
+    Pi {
+        #[telescope]
+        params: Vec<Term>,
+        #[binding]
+        body: Box<Term>,
+    },
+    PiThen {
+        #[telescope]
+        params: Vec<Term>,
+        #[binding(1)]
+        body: Box<Term>,
+    },
+}
+
+#[test]
+fn plain_binding_after_telescope_binds_only_the_telescope() {
+    use Term::*;
+    let pi = Pi {
+        params: vec![Var(0), Var(1)],
+        body: Box::new(Var(2)),
+    };
+    assert_eq!(
+        pi.increment_indices(),
+        Pi {
+            params: vec![Var(1), Var(2)],
+            body: Box::new(Var(3)),
+        }
+    );
+
+    let pi = PiThen {
+        params: vec![Var(0), Var(1)],
+        body: Box::new(Var(2)),
+    };
+    assert_eq!(
+        pi.increment_indices(),
+        PiThen {
+            params: vec![Var(1), Var(2)],
+            body: Box::new(Var(2)),
+        }
+    );
+}
+
+#[test]
+fn evaluates_under_telescope() {
+    use Term::*;
+    let ctx = ListContext::empty().append(Some(Unit));
+    let pi = Pi {
+        params: vec![Var(0), Var(0), Var(2)],
+        body: Box::new(Var(3)),
+    };
+    assert_eq!(
+        pi.evaluate(&ctx, &(), true),
+        Ok(Pi {
+            params: vec![Unit, Var(0), Unit],
+            body: Box::new(Unit),
+        })
+    );
+    assert_eq!(
+        pi.evaluate(&ctx, &(), false),
+        Ok(Pi {
+            params: vec![Unit, Var(0), Var(2)],
+            body: Box::new(Var(3)),
+        })
+    );
+}
@@ -26,3 +26,34 @@ fn evaluate_app() {
     let evalled = expr.evaluate_closed(false);
     assert_eq!(evalled, Ok(arg));
 }
+
+mod instantiated {
+    use ttt::{DeBruijnIndexed, Evaluate, Instantiate, Substitute};
+
+    #[derive(
+        Clone, DeBruijnIndexed, Substitute, Evaluate, PartialEq, Debug,
+    )]
+    enum LambdaExpr {
+        Var(#[var_index] usize),
+        Lambda(#[binding] Box<LambdaExpr>),
+        #[evaluate_pattern {
+            (Lambda(body), arg) => body.instantiate(&arg)?
+        }]
+        App(Box<LambdaExpr>, Box<LambdaExpr>),
+    }
+
+    use LambdaExpr::*;
+
+    #[test]
+    fn evaluate_app_by_instantiating() {
+        // (\x. \y. x) (\z. z)
+        let arg = Lambda(Var(0).into());
+        let expr = App(
+            Lambda(Lambda(Var(1).into()).into()).into(),
+            arg.clone().into(),
+        );
+
+        let evalled = expr.evaluate_closed(false);
+        assert_eq!(evalled, Ok(Lambda(arg.into())));
+    }
+}
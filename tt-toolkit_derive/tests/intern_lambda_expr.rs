@@ -0,0 +1,152 @@
+use ttt::{
+    DeBruijnIndexed, Instantiate, Interned, Sort, Substitute, SubstituteInPlace,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, DeBruijnIndexed, Substitute)]
+#[ttt(in_place)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Interned<LambdaExpr>),
+    App(Interned<LambdaExpr>, Interned<LambdaExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, DeBruijnIndexed, Substitute)]
+#[subst_types(Ty, Term)]
+enum Ty {
+    Var(#[var_index(sort = Ty)] usize),
+    Arrow(Interned<Ty>, Interned<Ty>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, DeBruijnIndexed, Substitute)]
+#[subst_types(Term, Ty)]
+enum Term {
+    Var(#[var_index(sort = Term)] usize),
+    Lam(Interned<Ty>, #[binding(sort = Term)] Interned<Term>),
+    TyLam(#[binding(sort = Ty)] Interned<Term>),
+}
+
+use LambdaExpr::*;
+
+fn var(index: usize) -> Interned<LambdaExpr> {
+    Interned::new(Var(index))
+}
+
+fn lambda(body: Interned<LambdaExpr>) -> Interned<LambdaExpr> {
+    Interned::new(Lambda(body))
+}
+
+fn app(
+    f: Interned<LambdaExpr>,
+    x: Interned<LambdaExpr>,
+) -> Interned<LambdaExpr> {
+    Interned::new(App(f, x))
+}
+
+#[test]
+fn equal_terms_share_a_node() {
+    let id = lambda(var(0));
+    let term = app(id.clone(), lambda(var(0)));
+    assert_eq!(id, lambda(var(0)));
+    assert_eq!(term, app(lambda(var(0)), id.clone()));
+    assert_ne!(term, app(id.clone(), lambda(var(1))));
+    match &*term {
+        App(f, x) => assert_eq!(f, x),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn caches_metadata() {
+    let term = app(lambda(app(var(0), var(3))), var(1));
+    assert_eq!(term.size(), 6);
+    assert_eq!(term.max_free_index(), Some(2));
+    assert_eq!(lambda(var(0)).max_free_index(), None);
+    assert!(lambda(var(0)).is_closed());
+    assert_eq!(term.free_vars().into_iter().collect::<Vec<_>>(), vec![1, 2]);
+}
+
+#[test]
+fn metadata_is_computed_from_shared_subterms() {
+    let mut term = lambda(var(2));
+    for _ in 0..50 {
+        term = app(term.clone(), lambda(term));
+    }
+    assert_eq!(term.max_free_index(), Some(1));
+    assert_eq!(
+        term.max_sort_free_index(Some(Sort::of::<LambdaExpr>())),
+        Some(1)
+    );
+    assert_eq!(term.increment_indices_from_by(2, 1), term);
+}
+
+#[test]
+fn shifting_interns_the_result() {
+    let term = app(lambda(var(0)), var(0));
+    let shifted = term.increment_indices();
+    assert_eq!(shifted, app(lambda(var(0)), var(1)));
+    assert_eq!(term.increment_indices_from_by(1, 2), term);
+}
+
+#[test]
+fn unchanged_terms_keep_their_node() {
+    let term = app(lambda(var(0)), var(1));
+    let shifted = term.increment_indices_from_by(2, 3);
+    assert_eq!(shifted, term);
+    let renamed = term.map_vars_at_depth(0, |_, var, _| var);
+    assert_eq!(renamed, term);
+}
+
+#[test]
+fn substitution_in_place_replaces_the_handle() {
+    let mut term = app(lambda(var(1)), var(2));
+    term.substitute_in_place(&Var(3), 0).unwrap();
+    assert_eq!(term, app(lambda(var(4)), var(1)));
+
+    let closed = lambda(var(0));
+    let mut substituted = closed.clone();
+    substituted.substitute_in_place(&Var(3), 0).unwrap();
+    assert_eq!(substituted, closed);
+}
+
+#[test]
+fn substitution_interns_the_result() {
+    let body = app(var(0), lambda(var(1)));
+    let arg = lambda(var(0));
+    assert_eq!(
+        body.substitute(&(*arg).clone(), 0),
+        Ok(app(arg.clone(), lambda(arg.clone())))
+    );
+    assert_eq!(
+        (*body).instantiate(&Var(4)),
+        Ok(App(var(4), lambda(var(5))))
+    );
+}
+
+#[test]
+fn metadata_respects_sorts() {
+    let ty = Interned::new(Ty::Arrow(
+        Interned::new(Ty::Var(0)),
+        Interned::new(Ty::Var(2)),
+    ));
+    let term = Interned::new(Term::TyLam(Interned::new(Term::Lam(
+        ty.clone(),
+        Interned::new(Term::Var(1)),
+    ))));
+
+    assert_eq!(term.max_sort_free_index(Some(Sort::of::<Term>())), Some(0));
+    assert_eq!(term.max_sort_free_index(Some(Sort::of::<Ty>())), Some(1));
+    assert_eq!(term.max_sort_free_index(None), Some(1));
+
+    let substituted =
+        Substitute::<Term>::substitute(&term, &Term::Var(5), 0).unwrap();
+    assert_eq!(
+        substituted,
+        Interned::new(Term::TyLam(Interned::new(Term::Lam(
+            ty,
+            Interned::new(Term::Var(6)),
+        ))))
+    );
+    let substituted =
+        Substitute::<Ty>::substitute(&term, &Ty::Var(7), 3).unwrap();
+    assert_eq!(substituted, term);
+}
@@ -0,0 +1,129 @@
+use ttt::{
+    Attributed, AutoContextualEq, CheckAttribute, Context, DeBruijnIndexed,
+    Def, DefaultError, Evaluate, LetEntry, ListContext, PartialSynthAttribute,
+    Substitute, contextual_eq::SyntacticEq,
+};
+
+#[derive(Clone, PartialEq, Debug, DeBruijnIndexed)]
+enum Ty {
+    Nat,
+    Func(Box<Ty>, Box<Ty>),
+}
+
+impl<E, C: Context<E>> AutoContextualEq<E, C> for Ty {
+    type Impl = SyntacticEq<Ty>;
+}
+
+#[derive(
+    Clone, PartialEq, Debug, DeBruijnIndexed, Substitute, Evaluate, Attributed,
+)]
+#[bidir_type(Ty, context_entry = ttt::Def<Ty, Expr>)]
+enum Expr {
+    #[synth(Ty; var => lookup(*var).ty)]
+    Var(#[var_index] usize),
+
+    #[synth(Ty; _ => Ty::Nat)]
+    Zero,
+
+    #[synth(Ty; n =>
+        let Some(Ty::Nat) = try_synth(n);
+        Ty::Nat
+    )]
+    Succ(Box<Expr>),
+
+    #[check(Ty; body : Ty::Func(src, tgt) =>
+        bind Def::assumption((**src).clone()) { check(body, tgt) }
+    )]
+    Lam(#[binding] Box<Expr>),
+
+    Let {
+        value: Box<Expr>,
+        #[let_binding(value = value)]
+        body: Box<Expr>,
+    },
+}
+
+use Expr::*;
+
+fn succ(expr: Expr) -> Expr {
+    Succ(Box::new(expr))
+}
+
+fn let_in(value: Expr, body: Expr) -> Expr {
+    Let {
+        value: Box::new(value),
+        body: Box::new(body),
+    }
+}
+
+#[test]
+fn let_body_is_under_a_binder() {
+    let expr = let_in(Var(0), Var(1));
+    assert_eq!(expr.increment_indices(), let_in(Var(1), Var(2)));
+    assert_eq!(expr.free_vars().into_iter().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(expr.substitute(&Zero, 0), Ok(let_in(Zero, Zero)));
+}
+
+#[test]
+fn evaluate_unfolds_definitions() {
+    let expr = let_in(succ(Zero), succ(Var(0)));
+    assert_eq!(
+        expr.evaluate_closed(false),
+        Ok(let_in(succ(Zero), succ(succ(Zero))))
+    );
+
+    let expr = let_in(Zero, let_in(succ(Var(0)), succ(Var(0))));
+    assert_eq!(
+        expr.evaluate_closed(false),
+        Ok(let_in(Zero, let_in(succ(Zero), succ(succ(Zero)))))
+    );
+
+    let expr = Lam(Box::new(let_in(Var(0), Var(0))));
+    assert_eq!(
+        expr.evaluate_closed(true),
+        Ok(Lam(Box::new(let_in(Var(0), Var(1)))))
+    );
+}
+
+#[test]
+fn synth_through_definitions() {
+    let ctx = ListContext::empty();
+    assert_eq!(
+        let_in(Zero, succ(Var(0))).try_synth(&ctx, &()),
+        Ok(Some(Ty::Nat))
+    );
+
+    let func = Ty::Func(Box::new(Ty::Nat), Box::new(Ty::Nat));
+    let ctx = ctx.append(Def::assumption(func.clone()));
+    assert_eq!(
+        let_in(Zero, Var(1)).try_synth(&ctx, &()),
+        Ok(Some(func.clone()))
+    );
+
+    let undefined = let_in(Lam(Box::new(Var(0))), Zero);
+    assert_eq!(undefined.try_synth(&ctx, &()), Ok(None));
+}
+
+#[test]
+fn check_through_definitions() {
+    let ty = Ty::Func(Box::new(Ty::Nat), Box::new(Ty::Nat));
+    let expr = let_in(Zero, Lam(Box::new(Var(1))));
+    assert_eq!(expr.check(&ty, &ListContext::empty(), &()), Ok(true));
+
+    let expr = let_in(Zero, Zero);
+    assert_eq!(expr.check(&ty, &ListContext::empty(), &()), Ok(false));
+
+    let expr = let_in(Lam(Box::new(Var(0))), Zero);
+    assert_eq!(
+        expr.check(&Ty::Nat, &ListContext::empty(), &()),
+        Err(DefaultError::UnsynthesizedDefinition)
+    );
+}
+
+#[test]
+fn definitions_are_shifted_with_the_context() {
+    let def =
+        <Def<Ty, Expr> as LetEntry<Ty, Expr>>::let_entry(Ty::Nat, &Var(0));
+    assert_eq!(def, Def::definition(Ty::Nat, Var(0)));
+    assert_eq!(def.increment_indices_by(2).value, Some(Var(2)));
+}
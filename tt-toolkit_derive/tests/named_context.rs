@@ -0,0 +1,97 @@
+use std::cell::RefCell;
+
+use ttt::{
+    Attributed, CheckAttribute, Context, ContextualEq, DeBruijnIndexed,
+    NamedContext, Never,
+};
+
+#[test]
+fn freshens_clashing_names() {
+    let ctx = NamedContext::empty()
+        .append_named("x", 0)
+        .append_named("y", 1)
+        .append_named("x", 2)
+        .append(3)
+        .append_named("x", 4);
+
+    assert_eq!(ctx.len(), 5);
+    assert_eq!(ctx.get(0), Some(4));
+    assert_eq!(ctx.name_of(0), Some("x2"));
+    assert_eq!(ctx.name_of(1), None);
+    assert_eq!(ctx.name_of(2), Some("x1"));
+    assert_eq!(ctx.name_of(4), Some("x"));
+    assert_eq!(ctx.name_of(5), None);
+    assert_eq!(ctx.index_of("y"), Some(3));
+    assert_eq!(ctx.index_of("x"), Some(0));
+    assert_eq!(ctx.index_of("x1"), None);
+    assert_eq!(ctx.fresh_name("y"), "y1");
+    assert_eq!(ctx.fresh_name("z"), "z");
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Ty {
+    Func(Box<Ty>, Box<Ty>),
+    Unit,
+}
+
+thread_local! {
+    /// The names in scope each time two types are compared.
+    static SEEN_NAMES: RefCell<Vec<Vec<Option<String>>>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+impl ContextualEq<Ty, NamedContext<Ty>> for Ty {
+    type Check = bool;
+    type Error = Never;
+
+    fn equiv(
+        ctx: &NamedContext<Ty>,
+        _sig: &(),
+        lhs: &Self,
+        rhs: &Self,
+    ) -> Result<bool, Never> {
+        let names = ctx.names().map(|name| name.map(str::to_string));
+        SEEN_NAMES.with_borrow_mut(|seen| seen.push(names.collect()));
+        Ok(lhs == rhs)
+    }
+}
+
+#[derive(Clone, Attributed, DeBruijnIndexed)]
+#[bidir_type(Ty, context = ttt::NamedContext<Ty>)]
+enum Expr {
+    #[synth(Ty; var => lookup(*var))]
+    Var(#[var_index] usize),
+
+    #[check(Ty; (_, body) : Ty::Func(src, tgt) =>
+        bind src { check(body, tgt) }
+    )]
+    Lam {
+        #[binding_name]
+        name: String,
+        #[binding]
+        body: Box<Expr>,
+    },
+}
+
+fn lam(name: &str, body: Expr) -> Expr {
+    Expr::Lam {
+        name: name.to_string(),
+        body: Box::new(body),
+    }
+}
+
+fn func(src: Ty, tgt: Ty) -> Ty {
+    Ty::Func(Box::new(src), Box::new(tgt))
+}
+
+#[test]
+fn bind_records_binding_names() {
+    let expr = lam("x", lam("x", Expr::Var(1)));
+    let ty = func(Ty::Unit, func(Ty::Unit, Ty::Unit));
+
+    assert!(expr.check(&ty, &NamedContext::empty(), &()).unwrap());
+    assert_eq!(
+        SEEN_NAMES.take(),
+        vec![vec![Some("x1".to_string()), Some("x".to_string())]]
+    );
+}
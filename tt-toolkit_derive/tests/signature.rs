@@ -0,0 +1,131 @@
+use ttt::{
+    Attributed, AutoContextualEq, CheckAttribute, Context, DeBruijnIndexed,
+    Def, DefaultError, EvalError, Evaluate, ListContext, PartialSynthAttribute,
+    Signature, UnboundGlobal, contextual_eq::SyntacticEq,
+};
+
+#[derive(Clone, PartialEq, Debug, DeBruijnIndexed)]
+enum Ty {
+    Nat,
+    Func(Box<Ty>, Box<Ty>),
+}
+
+impl<E, C: Context<E>, S> AutoContextualEq<E, C, S> for Ty {
+    type Impl = SyntacticEq<Ty>;
+}
+
+type Prelude = Signature<String, Def<Ty, Expr>>;
+
+#[derive(Clone, PartialEq, Debug, DeBruijnIndexed, Evaluate, Attributed)]
+#[signature_type(Prelude)]
+#[bidir_type(Ty, signature = Prelude)]
+enum Expr {
+    #[synth(Ty; var => lookup(*var))]
+    Var(#[var_index] usize),
+
+    #[synth(Ty; name => global(name).ty)]
+    Global(#[global_ref] String),
+
+    #[check(Ty; body : Ty::Func(src, tgt) =>
+        bind src { check(body, tgt) }
+    )]
+    Lam(#[binding] Box<Expr>),
+
+    #[synth(Ty; (fun, arg) =>
+        let Some(Ty::Func(src, tgt)) = try_synth(fun);
+        let true = check(arg, &src);
+        Some(*tgt)
+    )]
+    App(Box<Expr>, Box<Expr>),
+}
+
+use Expr::*;
+
+fn func(src: Ty, tgt: Ty) -> Ty {
+    Ty::Func(Box::new(src), Box::new(tgt))
+}
+
+fn global(name: &str) -> Expr {
+    Global(name.to_string())
+}
+
+fn lam(body: Expr) -> Expr {
+    Lam(Box::new(body))
+}
+
+fn app(fun: Expr, arg: Expr) -> Expr {
+    App(Box::new(fun), Box::new(arg))
+}
+
+fn prelude() -> Prelude {
+    [
+        ("zero".to_string(), Def::assumption(Ty::Nat)),
+        ("succ".to_string(), Def::assumption(func(Ty::Nat, Ty::Nat))),
+        (
+            "id".to_string(),
+            Def::definition(func(Ty::Nat, Ty::Nat), lam(Var(0))),
+        ),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn globals_are_looked_up_in_the_signature() {
+    let expr = app(global("succ"), global("zero"));
+
+    assert_eq!(
+        expr.try_synth(&ListContext::empty(), &prelude()),
+        Ok(Some(Ty::Nat))
+    );
+}
+
+#[test]
+fn signature_is_kept_under_binders() {
+    let expr = lam(app(global("succ"), Var(0)));
+
+    assert_eq!(
+        expr.check(&func(Ty::Nat, Ty::Nat), &ListContext::empty(), &prelude()),
+        Ok(true)
+    );
+}
+
+#[test]
+fn unbound_global_is_an_error() {
+    assert_eq!(
+        global("pred").try_synth(&ListContext::empty(), &prelude()),
+        Err(DefaultError::UnboundGlobal(UnboundGlobal {
+            name: "pred".to_string()
+        }))
+    );
+    assert_eq!(
+        global("pred").evaluate(&ListContext::empty(), &prelude(), false),
+        Err(EvalError::UnboundGlobal(UnboundGlobal {
+            name: "pred".to_string()
+        }))
+    );
+}
+
+#[test]
+fn evaluate_unfolds_global_definitions() {
+    let ctx = ListContext::empty();
+    let expr = app(global("id"), global("zero"));
+    assert_eq!(
+        expr.evaluate(&ctx, &prelude(), false),
+        Ok(app(lam(Var(0)), global("zero")))
+    );
+
+    let expr = lam(app(global("id"), Var(0)));
+    assert_eq!(
+        expr.evaluate(&ctx, &prelude(), true),
+        Ok(lam(app(lam(Var(0)), Var(0))))
+    );
+    assert_eq!(expr.evaluate(&ctx, &prelude(), false), Ok(expr.clone()));
+}
+
+#[test]
+fn global_refs_are_not_shifted() {
+    let expr = app(global("succ"), Var(0));
+
+    assert_eq!(expr.increment_indices_by(3), app(global("succ"), Var(3)));
+}
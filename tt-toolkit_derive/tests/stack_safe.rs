@@ -0,0 +1,125 @@
+use ttt::{
+    DeBruijnIndexed, Evaluate, ResolveVars, Substitute, SubstituteInPlace,
+};
+
+/// Deep enough to overflow the stack of a test thread without growing it,
+/// while keeping unoptimised builds of the tests quick.
+const DEPTH: usize = if cfg!(debug_assertions) {
+    100_000
+} else {
+    1_000_000
+};
+
+#[derive(
+    Clone, Debug, PartialEq, DeBruijnIndexed, Substitute, Evaluate, ResolveVars,
+)]
+#[ttt(stack_safe, in_place)]
+enum Nat {
+    Var {
+        #[var_name]
+        name: String,
+        #[var_index]
+        index: usize,
+    },
+    Zero,
+    Succ(Box<Nat>),
+    Lam {
+        #[binding_name]
+        name: String,
+        #[binding]
+        body: Box<Nat>,
+    },
+}
+
+use Nat::*;
+
+fn var(name: &str, index: usize) -> Nat {
+    Var {
+        name: name.to_string(),
+        index,
+    }
+}
+
+fn lam(name: &str, body: Nat) -> Nat {
+    Lam {
+        name: name.to_string(),
+        body: Box::new(body),
+    }
+}
+
+/// `depth` successors of `base`, built without recursion.
+fn succs(depth: usize, base: Nat) -> Nat {
+    (0..depth).fold(base, |nat, _| Succ(Box::new(nat)))
+}
+
+/// The number of successors around a term, and the term inside of them.
+fn count_succs(mut nat: &Nat) -> (usize, &Nat) {
+    let mut count = 0;
+    while let Succ(pred) = nat {
+        count += 1;
+        nat = pred;
+    }
+    (count, nat)
+}
+
+/// Drops a term without recursing on the stack.
+fn dismantle(mut nat: Nat) {
+    loop {
+        nat = match nat {
+            Succ(pred) => *pred,
+            Lam { body, .. } => *body,
+            _ => return,
+        }
+    }
+}
+
+#[test]
+fn deep_shifting() {
+    let nat = lam("x", succs(DEPTH, var("y", 1)));
+    assert_eq!(nat.max_free_index(), Some(0));
+
+    let shifted = nat.increment_indices_by(2);
+    let Lam { body, .. } = &shifted else {
+        unreachable!()
+    };
+    assert_eq!(count_succs(body), (DEPTH, &var("y", 3)));
+    dismantle(shifted);
+
+    let mut nat = nat;
+    nat.increment_indices_in_place_by(1);
+    assert_eq!(nat.max_free_index(), Some(1));
+    dismantle(nat);
+}
+
+#[test]
+fn deep_substitution() {
+    let nat = succs(DEPTH, var("x", 0));
+    let substituted = nat.substitute(&Zero, 0).unwrap();
+    assert_eq!(count_succs(&substituted), (DEPTH, &Zero));
+    dismantle(substituted);
+
+    let substituted = nat.substitute_owned(&succs(2, Zero), 0).unwrap();
+    assert_eq!(count_succs(&substituted), (DEPTH + 2, &Zero));
+    dismantle(substituted);
+}
+
+#[test]
+fn deep_evaluation() {
+    let nat = succs(DEPTH, Zero);
+    let evaluated = nat.evaluate_closed(false).unwrap();
+    assert_eq!(count_succs(&evaluated), (DEPTH, &Zero));
+    dismantle(evaluated);
+    dismantle(nat);
+}
+
+#[test]
+fn deep_name_resolution() {
+    let nat = lam("x", succs(DEPTH, var("x", 7)));
+    let resolved = nat.resolve_vars(&ttt::NameContext::new()).unwrap();
+    let Lam { body, .. } = &resolved else {
+        unreachable!()
+    };
+    assert_eq!(count_succs(body), (DEPTH, &var("x", 0)));
+    dismantle(resolved);
+    dismantle(nat);
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ttt::{Context, DeBruijnIndexed, Evaluate, ListContext, Substitute};
+
+#[derive(Clone, DeBruijnIndexed, Substitute, Evaluate, PartialEq, Debug)]
+enum Expr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<Expr>),
+    App(Box<Expr>, Vec<Expr>),
+    Record(BTreeMap<String, Expr>),
+    Pairs(Vec<(Expr, Expr)>),
+    Rows(Vec<[Expr; 2]>),
+    Shared(Arc<Expr>),
+}
+
+#[derive(Clone, DeBruijnIndexed, Substitute, PartialEq, Debug)]
+enum Annotated {
+    Var(#[var_index] usize),
+    Annotated(Box<Annotated>, Option<Box<Annotated>>),
+}
+
+use Expr::*;
+
+fn record(fields: impl IntoIterator<Item = (&'static str, Expr)>) -> Expr {
+    Record(
+        fields
+            .into_iter()
+            .map(|(name, expr)| (name.to_string(), expr))
+            .collect(),
+    )
+}
+
+#[test]
+fn shifts_container_fields() {
+    let expr = App(
+        Lambda(Var(1).into()).into(),
+        vec![Var(0), Pairs(vec![(Var(1), Rows(vec![[Var(0), Var(2)]]))])],
+    );
+    assert_eq!(
+        expr.increment_indices(),
+        App(
+            Lambda(Var(2).into()).into(),
+            vec![Var(1), Pairs(vec![(Var(2), Rows(vec![[Var(1), Var(3)]]))])],
+        )
+    );
+
+    let expr = record([("x", Var(0)), ("y", Shared(Arc::new(Var(3))))]);
+    assert_eq!(
+        expr.increment_indices_by(2),
+        record([("x", Var(2)), ("y", Shared(Arc::new(Var(5))))])
+    );
+}
+
+#[test]
+fn substitutes_container_fields() {
+    let expr = record([("x", Var(0)), ("y", Lambda(Var(1).into()))]);
+    assert_eq!(
+        expr.substitute(&Var(7), 0),
+        Ok(record([("x", Var(7)), ("y", Lambda(Var(8).into()))]))
+    );
+
+    let expr = Annotated::Annotated(
+        Annotated::Var(0).into(),
+        Some(Annotated::Var(1).into()),
+    );
+    assert_eq!(
+        expr.substitute(&Annotated::Var(5), 1),
+        Ok(Annotated::Annotated(
+            Annotated::Var(0).into(),
+            Some(Annotated::Var(5).into()),
+        ))
+    );
+}
+
+#[test]
+fn evaluates_container_fields() {
+    let expr = App(
+        Var(0).into(),
+        vec![Pairs(vec![(Var(1), Var(2))]), Rows(vec![[Var(0), Var(1)]])],
+    );
+    let ctx = ListContext::empty().append(None).append(None).append(None);
+    assert_eq!(expr.evaluate(&ctx, &(), false), Ok(expr.clone()));
+}
+
+/// A hand-written implementation, which doesn't need to name the entries of
+/// its context.
+#[derive(Clone, PartialEq, Debug)]
+struct Literal(u32);
+
+impl Evaluate for Literal {
+    type Target = Literal;
+    type Error = ttt::Never;
+    type Context = ListContext<Option<Literal>>;
+    type Signature = ();
+
+    fn evaluate(
+        &self,
+        _ctx: &Self::Context,
+        _sig: &(),
+        _under_binders: bool,
+    ) -> Result<Literal, ttt::Never> {
+        Ok(self.clone())
+    }
+}
+
+#[test]
+fn evaluates_containers_of_hand_written_impls() {
+    let literals = vec![Some(Literal(1)), None];
+    assert_eq!(
+        literals.evaluate(&ListContext::empty(), &(), false),
+        Ok(literals)
+    );
+    assert_eq!(Literal(2).evaluate_closed(false), Ok(Literal(2)));
+}
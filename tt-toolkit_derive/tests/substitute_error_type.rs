@@ -0,0 +1,97 @@
+use ttt::{DeBruijnIndexed, SubstError, Substitute};
+
+#[derive(Debug, PartialEq)]
+enum MyError {
+    Subst(SubstError),
+    NotAValue,
+}
+
+impl From<SubstError> for MyError {
+    fn from(value: SubstError) -> Self {
+        MyError::Subst(value)
+    }
+}
+
+// A call-by-value syntax, where only values may be substituted for variables.
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_error_type(MyError)]
+enum Term {
+    Val(Value),
+    App(Box<Term>, Box<Term>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Term)]
+#[subst_error_type(MyError)]
+enum Value {
+    #[subst_with(as_value)]
+    Var(#[variable] Variable),
+    Lam(#[binding] Box<Term>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed)]
+struct Variable(#[var_index] usize);
+
+fn as_value(term: &Term) -> Result<Value, MyError> {
+    match term {
+        Term::Val(value) => Ok(value.clone()),
+        _ => Err(MyError::NotAValue),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Label(&'static str);
+
+// Labels can't be converted into trees, so can't be substituted into them.
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Label)]
+#[subst_error_type(MyError)]
+enum Tree {
+    Leaf(#[var_index] usize),
+    Node(Box<Tree>, Box<Tree>),
+}
+
+fn var(index: usize) -> Term {
+    Term::Val(Value::Var(Variable(index)))
+}
+
+fn lam(body: Term) -> Term {
+    Term::Val(Value::Lam(Box::new(body)))
+}
+
+fn app(f: Term, x: Term) -> Term {
+    Term::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn substitutes_values() {
+    let term = app(var(0), lam(var(1)));
+    let arg = lam(var(0));
+    assert_eq!(
+        term.substitute(&arg, 0),
+        Ok(app(arg.clone(), lam(arg.increment_indices())))
+    );
+}
+
+#[test]
+fn rejects_non_values() {
+    let term = app(var(0), var(1));
+    let arg = app(lam(var(0)), lam(var(0)));
+    assert_eq!(term.substitute(&arg, 0), Err(MyError::NotAValue));
+
+    // Variables which aren't replaced don't need a value
+    assert_eq!(term.substitute(&arg, 2), Ok(app(var(0), var(1))));
+}
+
+#[test]
+fn converts_subst_errors() {
+    let tree = Tree::Node(Box::new(Tree::Leaf(0)), Box::new(Tree::Leaf(2)));
+    assert!(matches!(
+        tree.substitute(&Label("x"), 0),
+        Err(MyError::Subst(_))
+    ));
+    assert_eq!(
+        tree.substitute(&Label("x"), 1),
+        Ok(Tree::Node(Box::new(Tree::Leaf(0)), Box::new(Tree::Leaf(1))))
+    );
+}
@@ -0,0 +1,234 @@
+use std::rc::Rc;
+
+use ttt::{DeBruijnIndexed, Subst, SubstError, Substitute, SubstituteInPlace};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[ttt(in_place)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[ttt(in_place)]
+enum Ty {
+    Var(#[var_index] usize),
+    Pi {
+        #[telescope]
+        params: Vec<Ty>,
+        #[binding]
+        body: Rc<Ty>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[ttt(in_place)]
+#[subst_types(Ty2, Term)]
+enum Ty2 {
+    Var(#[var_index(sort = Ty2)] usize),
+    Arrow(Box<Ty2>, Box<Ty2>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[ttt(in_place)]
+#[subst_types(Term, Ty2)]
+enum Term {
+    Var(#[var_index(sort = Term)] usize),
+    Lam(Ty2, #[binding(sort = Term)] Box<Term>),
+    TyLam(#[binding(sort = Ty2)] Box<Term>),
+}
+
+use LambdaExpr::*;
+
+fn lambda(body: LambdaExpr) -> LambdaExpr {
+    Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    App(Box::new(f), Box::new(x))
+}
+
+fn body_ptr(expr: &LambdaExpr) -> *const LambdaExpr {
+    match expr {
+        Lambda(body) => &**body,
+        _ => panic!("Expected a lambda, found {expr:?}"),
+    }
+}
+
+#[test]
+fn map_indices_in_place_agrees() {
+    let expr = lambda(app(app(Var(0), Var(1)), lambda(Var(3))));
+    for start in 0..3 {
+        let mut in_place = expr.clone();
+        in_place.map_indices_in_place_from(start, |i| i + 2);
+        assert_eq!(in_place, expr.map_indices_from(start, |i| i + 2));
+    }
+
+    let mut weakened = expr.clone();
+    weakened.increment_indices_in_place_by(3);
+    assert_eq!(weakened, expr.increment_indices_by(3));
+}
+
+#[test]
+fn map_indices_in_place_reuses_boxes() {
+    let mut expr = lambda(app(Var(0), Var(1)));
+    let body = body_ptr(&expr);
+    expr.increment_indices_in_place_by(1);
+    assert_eq!(expr, lambda(app(Var(0), Var(2))));
+    assert_eq!(body_ptr(&expr), body);
+}
+
+#[test]
+fn substitute_in_place_agrees() {
+    let expr = lambda(app(app(Var(0), Var(1)), lambda(app(Var(2), Var(3)))));
+    let arg = lambda(app(Var(0), Var(4)));
+    for var in 0..3 {
+        let mut in_place = expr.clone();
+        in_place.substitute_in_place(&arg, var).unwrap();
+        assert_eq!(Ok(in_place), expr.substitute(&arg, var));
+    }
+
+    let subst = Subst::instantiate(vec![Var(7), lambda(Var(1))]);
+    let mut in_place = expr.clone();
+    in_place.subst_all_in_place(&subst).unwrap();
+    assert_eq!(Ok(in_place), expr.subst_all(&subst));
+}
+
+#[test]
+fn substitute_owned_reuses_boxes() {
+    let expr = lambda(app(Var(1), Var(0)));
+    let body = body_ptr(&expr);
+    let substituted = expr.substitute_owned(&Var(5), 0).unwrap();
+    assert_eq!(substituted, lambda(app(Var(6), Var(0))));
+    assert_eq!(body_ptr(&substituted), body);
+}
+
+#[test]
+fn in_place_telescopes() {
+    // Pi (x : A) (y : B x) -> C x y
+    let ty = Ty::Pi {
+        params: vec![Ty::Var(0), Ty::Var(0)],
+        body: Rc::new(Ty::Var(3)),
+    };
+
+    let mut shifted = ty.clone();
+    shifted.increment_indices_in_place_by(1);
+    assert_eq!(shifted, ty.increment_indices());
+
+    let mut substituted = ty.clone();
+    substituted.substitute_in_place(&Ty::Var(4), 0).unwrap();
+    assert_eq!(Ok(substituted), ty.substitute(&Ty::Var(4), 0));
+}
+
+#[test]
+fn in_place_respects_sorts() {
+    let ty = Ty2::Arrow(Box::new(Ty2::Var(0)), Box::new(Ty2::Var(1)));
+    let term =
+        Term::TyLam(Box::new(Term::Lam(ty.clone(), Box::new(Term::Var(1)))));
+
+    for var in 0..2 {
+        let mut in_place = term.clone();
+        SubstituteInPlace::<Ty2>::substitute_in_place(
+            &mut in_place,
+            &Ty2::Var(5),
+            var,
+        )
+        .unwrap();
+        assert_eq!(
+            Ok(in_place),
+            Substitute::<Ty2>::substitute(&term, &Ty2::Var(5), var)
+        );
+
+        let in_place = SubstituteInPlace::<Term>::substitute_owned(
+            term.clone(),
+            &Term::Var(5),
+            var,
+        );
+        assert_eq!(
+            in_place,
+            Substitute::<Term>::substitute(&term, &Term::Var(5), var)
+        );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[ttt(in_place)]
+enum NamedExpr {
+    Var(#[variable] Variable),
+    Lambda(#[binding] Box<NamedExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(NamedExpr)]
+struct Variable {
+    #[var_index]
+    index: usize,
+    #[metadata]
+    name: String,
+}
+
+impl From<Variable> for NamedExpr {
+    fn from(value: Variable) -> Self {
+        NamedExpr::Var(value)
+    }
+}
+
+#[test]
+fn in_place_variable_wrappers() {
+    let var = |index| {
+        NamedExpr::Var(Variable {
+            index,
+            name: "x".to_string(),
+        })
+    };
+    let expr = NamedExpr::Lambda(Box::new(var(2)));
+    for var_index in 0..3 {
+        let in_place = expr.clone().substitute_owned(&var(7), var_index);
+        assert_eq!(in_place, expr.substitute(&var(7), var_index));
+    }
+}
+
+/// A constant with a hand-written substitution, which doesn't implement
+/// `SubstituteInPlace`.
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed)]
+struct Const(#[metadata] String);
+
+impl Substitute<Annotated> for Const {
+    type Target = Const;
+    type Error = SubstError;
+
+    fn substitute(&self, _: &Annotated, _: usize) -> Result<Const, SubstError> {
+        Ok(self.clone())
+    }
+
+    fn subst_all_from<S: std::borrow::Borrow<Annotated>>(
+        &self,
+        _: &Subst<S>,
+        _: usize,
+    ) -> Result<Const, SubstError> {
+        Ok(self.clone())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+enum Annotated {
+    Var(#[var_index] usize),
+    Const(Const),
+    App(Box<Annotated>, Box<Annotated>),
+}
+
+#[test]
+fn in_place_is_opt_in() {
+    let expr = Annotated::App(
+        Box::new(Annotated::Const(Const("f".to_string()))),
+        Box::new(Annotated::Var(0)),
+    );
+    assert_eq!(
+        expr.substitute(&Annotated::Var(3), 0),
+        Ok(Annotated::App(
+            Box::new(Annotated::Const(Const("f".to_string()))),
+            Box::new(Annotated::Var(3)),
+        ))
+    );
+}
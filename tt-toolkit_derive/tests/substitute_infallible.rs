@@ -0,0 +1,113 @@
+use std::convert::Infallible;
+
+use ttt::{DeBruijnIndexed, Instantiate, Substitute};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_infallible]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Ty, Term)]
+#[subst_infallible]
+enum Ty {
+    Var(#[var_index(sort = Ty)] usize),
+    Arrow(Box<Ty>, Box<Ty>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Term, Ty)]
+#[subst_infallible]
+enum Term {
+    Var(#[var_index(sort = Term)] usize),
+    Lam(Ty, #[binding(sort = Term)] Box<Term>),
+    TyLam(#[binding(sort = Ty)] Box<Term>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Self)]
+#[subst_infallible]
+enum Kind {
+    Var(#[var_index(sort = crate::Kind)] usize),
+    Star,
+    Arrow(Box<Kind>, Box<Kind>),
+}
+
+fn lambda(body: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    LambdaExpr::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn error_type_is_infallible() {
+    let expr = LambdaExpr::Var(0);
+    let result: Result<LambdaExpr, Infallible> =
+        expr.substitute(&LambdaExpr::Var(3), 0);
+    assert_eq!(result, Ok(LambdaExpr::Var(3)));
+}
+
+#[test]
+fn instantiate_returns_target() {
+    // (\x. x 0) instantiated with \y. y
+    let body = app(LambdaExpr::Var(0), lambda(LambdaExpr::Var(1)));
+    let arg = lambda(LambdaExpr::Var(0));
+    assert_eq!(
+        body.instantiate(&arg),
+        app(arg.clone(), lambda(arg.increment_indices()))
+    );
+}
+
+#[test]
+fn instantiate_decrements_outer_variables() {
+    let body = app(LambdaExpr::Var(0), LambdaExpr::Var(2));
+    assert_eq!(
+        body.instantiate(&LambdaExpr::Var(5)),
+        app(LambdaExpr::Var(5), LambdaExpr::Var(1))
+    );
+}
+
+#[test]
+fn other_sorts_are_never_replaced() {
+    let ty = Ty::Arrow(Box::new(Ty::Var(0)), Box::new(Ty::Var(1)));
+    let term = Term::Lam(ty.clone(), Box::new(Term::Var(0)));
+
+    assert_eq!(
+        term.instantiate(&Term::Var(4)),
+        Term::Lam(ty.clone(), Box::new(Term::Var(0)))
+    );
+    assert_eq!(Instantiate::<Term>::instantiate(&ty, &Term::Var(4)), ty);
+    assert_eq!(
+        Instantiate::<Ty>::instantiate(&term, &Ty::Var(7)),
+        Term::Lam(
+            Ty::Arrow(Box::new(Ty::Var(7)), Box::new(Ty::Var(0))),
+            Box::new(Term::Var(0))
+        )
+    );
+
+    // Type variables are shifted under type binders, but not term binders.
+    assert_eq!(
+        Instantiate::<Ty>::instantiate(
+            &Term::TyLam(Box::new(term)),
+            &Ty::Var(7)
+        ),
+        Term::TyLam(Box::new(Term::Lam(
+            Ty::Arrow(Box::new(Ty::Var(0)), Box::new(Ty::Var(8))),
+            Box::new(Term::Var(0))
+        )))
+    );
+}
+
+#[test]
+fn sorts_are_compared_by_path() {
+    let kind = Kind::Arrow(Box::new(Kind::Var(0)), Box::new(Kind::Var(1)));
+    assert_eq!(
+        kind.instantiate(&Kind::Star),
+        Kind::Arrow(Box::new(Kind::Star), Box::new(Kind::Var(0)))
+    );
+}
@@ -0,0 +1,77 @@
+use ttt::{DeBruijnIndexed, Instantiate, Substitute};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+use LambdaExpr::*;
+
+fn lambda(body: LambdaExpr) -> LambdaExpr {
+    Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn instantiate_closed_argument() {
+    // (\x. x x) (\y. y)
+    let body = app(Var(0), Var(0));
+    let arg = lambda(Var(0));
+    assert_eq!(body.instantiate(&arg), Ok(app(arg.clone(), arg)));
+}
+
+#[test]
+fn instantiate_shifts_open_argument() {
+    // (\x. \y. x y) z, where z has index 0
+    let body = lambda(app(Var(1), Var(0)));
+    assert_eq!(body.instantiate(&Var(0)), Ok(lambda(app(Var(1), Var(0)))));
+}
+
+#[test]
+fn instantiate_removes_binder() {
+    // The body of \x. x w, where w has index 0 outside of the binder
+    let body = app(Var(0), Var(1));
+    assert_eq!(body.instantiate(&Var(7)), Ok(app(Var(7), Var(0))));
+}
+
+#[test]
+fn instantiate_through_box() {
+    let body: Box<LambdaExpr> = Box::new(app(Var(0), Var(1)));
+    assert_eq!(body.instantiate(&Var(7)), Ok(app(Var(7), Var(0))));
+}
+
+#[test]
+fn instantiate_many_binds_innermost_first() {
+    // The body of \x. \y. x y, under a binder w
+    let body = app(app(Var(1), Var(0)), lambda(Var(3)));
+    assert_eq!(
+        body.instantiate_many(&[Var(5), Var(6)]),
+        Ok(app(app(Var(6), Var(5)), lambda(Var(1))))
+    );
+}
+
+#[test]
+fn instantiate_many_with_one_argument() {
+    let body = app(Var(0), lambda(Var(2)));
+    assert_eq!(body.instantiate_many(&[Var(7)]), body.instantiate(&Var(7)));
+}
+
+#[test]
+fn instantiate_many_agrees_with_instantiate() {
+    let body = app(app(Var(1), Var(0)), lambda(app(Var(1), Var(3))));
+    let (x, y) = (Var(4), lambda(Var(1)));
+
+    // Instantiating the innermost binder first leaves the argument for the
+    // outer binder under one less variable.
+    let one_at_a_time = body
+        .instantiate(&y.increment_indices())
+        .unwrap()
+        .instantiate(&x)
+        .unwrap();
+    assert_eq!(body.instantiate_many(&[y, x]), Ok(one_at_a_time));
+}
@@ -0,0 +1,87 @@
+use ttt::{DeBruijnIndexed, Subst, Substitute};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Box<LambdaExpr>),
+    App(Box<LambdaExpr>, Box<LambdaExpr>),
+}
+
+use LambdaExpr::*;
+
+fn lambda(body: LambdaExpr) -> LambdaExpr {
+    Lambda(Box::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    App(Box::new(f), Box::new(x))
+}
+
+/// Substitution which shifts the expression at every binder.
+fn eager_substitute(
+    term: &LambdaExpr,
+    expr: &LambdaExpr,
+    var: usize,
+) -> LambdaExpr {
+    match term {
+        Var(index) if *index == var => expr.clone(),
+        Var(index) if *index > var => Var(index - 1),
+        Var(index) => Var(*index),
+        Lambda(body) => {
+            lambda(eager_substitute(body, &expr.increment_indices(), var + 1))
+        }
+        App(f, x) => app(
+            eager_substitute(f, expr, var),
+            eager_substitute(x, expr, var),
+        ),
+    }
+}
+
+/// A term with every variable from 0 to `depth + 1` under each of `depth`
+/// binders.
+fn deep_term(depth: usize) -> LambdaExpr {
+    let mut term = Var(0);
+    for level in 0..=depth + 1 {
+        term = app(term, Var(level));
+    }
+    for _ in 0..depth {
+        term = lambda(app(term, Var(depth + 1)));
+    }
+    term
+}
+
+#[test]
+fn agrees_with_eager_shifting() {
+    let term = deep_term(6);
+    let exprs = [Var(0), Var(3), lambda(app(Var(0), Var(2))), lambda(Var(0))];
+    for expr in &exprs {
+        for var in 0..4 {
+            assert_eq!(
+                term.substitute(expr, var),
+                Ok(eager_substitute(&term, expr, var)),
+                "substituting {expr:?} for {var}"
+            );
+        }
+    }
+}
+
+#[test]
+fn closed_terms_are_unchanged_under_binders() {
+    let id = lambda(Var(0));
+    let term = lambda(lambda(app(Var(2), Var(1))));
+    assert_eq!(
+        term.substitute(&id, 0),
+        Ok(lambda(lambda(app(id.clone(), Var(1)))))
+    );
+}
+
+#[test]
+fn single_substitution_keeps_earlier_variables() {
+    let subst = Subst::single(1, Var(0));
+    assert_eq!(subst.lookup(0), Err(0));
+    assert_eq!(subst.lookup(1), Ok(&Var(0)));
+    assert_eq!(subst.lookup(2), Err(1));
+
+    let term = lambda(app(app(Var(1), Var(2)), Var(3)));
+    assert_eq!(term.subst_all(&subst), term.substitute(&Var(0), 1));
+}
@@ -0,0 +1,150 @@
+use ttt::{
+    Context, DeBruijnIndexed, EvalError, Evaluate, ListContext, ResolveVars,
+    Substitute, UnboundVariable,
+};
+
+#[derive(Clone, DeBruijnIndexed, Substitute, Evaluate, PartialEq, Debug)]
+enum Expr {
+    Var(#[var_index] usize),
+    Unit,
+    LetPair(Box<Expr>, #[binding(2)] Box<Expr>),
+}
+
+#[test]
+fn substitute_under_multi_binder() {
+    use Expr::*;
+    let expr = LetPair(Var(0).into(), Var(2).into());
+
+    let substituted = expr.substitute(&Var(5), 0);
+    assert_eq!(substituted, Ok(LetPair(Var(5).into(), Var(7).into())));
+
+    let expr = LetPair(Unit.into(), Var(1).into());
+    let substituted = expr.substitute(&Unit, 0);
+    assert_eq!(substituted, Ok(expr));
+}
+
+#[test]
+fn evaluate_under_multi_binder() {
+    use Expr::*;
+    // Both bound variables are left alone, and the variable bound outside
+    // of them is found by skipping both.
+    let ctx = ListContext::empty().append(Some(Unit));
+    let expr =
+        LetPair(Var(0).into(), LetPair(Var(1).into(), Var(4).into()).into());
+    assert_eq!(
+        expr.evaluate(&ctx, &(), true),
+        Ok(LetPair(
+            Unit.into(),
+            LetPair(Var(1).into(), Unit.into()).into()
+        ))
+    );
+    assert_eq!(
+        LetPair(Unit.into(), Var(2).into()).evaluate_closed(true),
+        Err(EvalError::UnboundVariable(UnboundVariable {
+            var: 2,
+            len: 2
+        }))
+    );
+}
+
+#[derive(Clone, ResolveVars, PartialEq, Debug)]
+enum Named {
+    Var {
+        #[var_name]
+        name: String,
+        #[var_index]
+        index: usize,
+    },
+    LetPair {
+        value: Box<Named>,
+        #[binding_name]
+        names: Vec<String>,
+        #[binding(2)]
+        body: Box<Named>,
+    },
+    Let {
+        value: Box<Named>,
+        #[binding_name]
+        name: String,
+        #[binding(1)]
+        body: Box<Named>,
+    },
+}
+
+#[test]
+fn resolve_multi_binder_names() {
+    let var = |name: &str| Named::Var {
+        name: name.to_string(),
+        index: usize::MAX,
+    };
+    let resolved = |name: &str, index| Named::Var {
+        name: name.to_string(),
+        index,
+    };
+    let expr = Named::LetPair {
+        value: var("z").into(),
+        names: vec!["x".to_string(), "y".to_string()],
+        body: var("x").into(),
+    };
+
+    let ctx = ttt::NameContext::new().append("z".to_string());
+    assert_eq!(
+        expr.resolve_vars(&ctx).unwrap(),
+        Named::LetPair {
+            value: resolved("z", 0).into(),
+            names: vec!["x".to_string(), "y".to_string()],
+            body: resolved("x", 1).into(),
+        }
+    );
+}
+
+#[test]
+fn resolve_multi_binder_name_count_mismatch() {
+    let expr = Named::LetPair {
+        value: Named::Var {
+            name: "z".to_string(),
+            index: usize::MAX,
+        }
+        .into(),
+        names: vec!["x".to_string()],
+        body: Named::Var {
+            name: "x".to_string(),
+            index: usize::MAX,
+        }
+        .into(),
+    };
+
+    let ctx = ttt::NameContext::new().append("z".to_string());
+    assert!(matches!(
+        expr.resolve_vars(&ctx),
+        Err(ttt::ResolveVarsError::BinderCountMismatch {
+            binders: 2,
+            names: 1
+        })
+    ));
+}
+
+#[test]
+fn resolve_single_binder_count_takes_one_name() {
+    let expr = Named::Let {
+        value: Named::Var {
+            name: "z".to_string(),
+            index: usize::MAX,
+        }
+        .into(),
+        name: "x".to_string(),
+        body: Named::Var {
+            name: "z".to_string(),
+            index: usize::MAX,
+        }
+        .into(),
+    };
+
+    let ctx = ttt::NameContext::new().append("z".to_string());
+    let Named::Let { value, body, .. } = expr.resolve_vars(&ctx).unwrap()
+    else {
+        unreachable!()
+    };
+    assert!(matches!(*value, Named::Var { index: 0, .. }));
+    assert!(matches!(*body, Named::Var { index: 1, .. }));
+}
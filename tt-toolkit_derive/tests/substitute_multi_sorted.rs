@@ -0,0 +1,141 @@
+use ttt::{DeBruijnIndexed, Sort, Substitute};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Ty, Term)]
+enum Ty {
+    Var(#[var_index(sort = Ty)] usize),
+    Arrow(Box<Ty>, Box<Ty>),
+    Forall(#[binding(sort = Ty)] Box<Ty>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Term, Ty)]
+enum Term {
+    Var(#[var_index(sort = Term)] usize),
+    Lam(Ty, #[binding(sort = Term)] Box<Term>),
+    App(Box<Term>, Box<Term>),
+    TyLam(#[binding(sort = Ty)] Box<Term>),
+    TyApp(Box<Term>, Ty),
+}
+
+fn ty_var(index: usize) -> Ty {
+    Ty::Var(index)
+}
+
+fn arrow(a: Ty, b: Ty) -> Ty {
+    Ty::Arrow(Box::new(a), Box::new(b))
+}
+
+fn var(index: usize) -> Term {
+    Term::Var(index)
+}
+
+fn lam(ty: Ty, body: Term) -> Term {
+    Term::Lam(ty, Box::new(body))
+}
+
+fn ty_lam(body: Term) -> Term {
+    Term::TyLam(Box::new(body))
+}
+
+fn ty_app(term: Term, ty: Ty) -> Term {
+    Term::TyApp(Box::new(term), ty)
+}
+
+#[test]
+fn shifts_each_sort_separately() {
+    // /\. \(x : 1). x 0
+    let term = ty_lam(lam(
+        ty_var(1),
+        Term::App(Box::new(var(0)), Box::new(var(1))),
+    ));
+
+    assert_eq!(
+        term.increment_sort_indices_by(Some(Sort::of::<Term>()), 1),
+        ty_lam(lam(
+            ty_var(1),
+            Term::App(Box::new(var(0)), Box::new(var(2)))
+        ))
+    );
+    assert_eq!(
+        term.increment_sort_indices_by(Some(Sort::of::<Ty>()), 1),
+        ty_lam(lam(
+            ty_var(2),
+            Term::App(Box::new(var(0)), Box::new(var(1)))
+        ))
+    );
+}
+
+#[test]
+fn substitutes_types_for_type_variables() {
+    // \(x : 0). x
+    let term = lam(ty_var(0), var(0));
+    assert_eq!(
+        Substitute::<Ty>::substitute(&term, &arrow(ty_var(3), ty_var(3)), 0),
+        Ok(lam(arrow(ty_var(3), ty_var(3)), var(0)))
+    );
+
+    // Term binders don't affect type indices
+    let term = lam(ty_var(0), lam(ty_var(0), var(1)));
+    assert_eq!(
+        Substitute::<Ty>::substitute(&term, &ty_var(5), 0),
+        Ok(lam(ty_var(5), lam(ty_var(5), var(1))))
+    );
+
+    // Type binders shift type indices
+    let term = ty_lam(ty_app(var(0), ty_var(1)));
+    assert_eq!(
+        Substitute::<Ty>::substitute(&term, &ty_var(5), 0),
+        Ok(ty_lam(ty_app(var(0), ty_var(6))))
+    );
+
+    // Including binders inside types
+    let ty = Ty::Forall(Box::new(arrow(ty_var(0), ty_var(1))));
+    assert_eq!(
+        Substitute::<Ty>::substitute(&ty, &ty_var(5), 0),
+        Ok(Ty::Forall(Box::new(arrow(ty_var(0), ty_var(6)))))
+    );
+}
+
+#[test]
+fn substitutes_terms_for_term_variables() {
+    // /\. (x : 0) where x has index 0 outside of the type binder
+    let term = ty_lam(lam(ty_var(0), var(1)));
+    let arg = ty_app(var(2), ty_var(0));
+    assert_eq!(
+        Substitute::<Term>::substitute(&term, &arg, 0),
+        Ok(ty_lam(lam(ty_var(0), ty_app(var(3), ty_var(1)))))
+    );
+}
+
+#[test]
+fn substitutes_all_in_one_sort() {
+    // /\. \(x : 0). x 1 2
+    let term = ty_lam(lam(
+        ty_var(0),
+        Term::App(
+            Box::new(var(0)),
+            Box::new(Term::App(Box::new(var(1)), Box::new(var(2)))),
+        ),
+    ));
+    let subst = ttt::Subst::instantiate(vec![ty_app(var(4), ty_var(0))]);
+    assert_eq!(
+        Substitute::<Term>::subst_all(&term, &subst),
+        Ok(ty_lam(lam(
+            ty_var(0),
+            Term::App(
+                Box::new(var(0)),
+                Box::new(Term::App(
+                    Box::new(ty_app(var(5), ty_var(1))),
+                    Box::new(var(1))
+                )),
+            ),
+        )))
+    );
+
+    let subst = ttt::Subst::instantiate(vec![arrow(ty_var(0), ty_var(1))]);
+    assert_eq!(
+        Substitute::<Ty>::subst_all(&ty_lam(lam(ty_var(1), var(0))), &subst),
+        Ok(ty_lam(lam(arrow(ty_var(1), ty_var(2)), var(0))))
+    );
+}
@@ -0,0 +1,164 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use ttt::{DeBruijnIndexed, Sort, Substitute};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+enum LambdaExpr {
+    Var(#[var_index] usize),
+    Lambda(#[binding] Rc<LambdaExpr>),
+    App(Rc<LambdaExpr>, Rc<LambdaExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Ty, Term)]
+enum Ty {
+    Var(#[var_index(sort = Ty)] usize),
+    Arrow(Arc<Ty>, Arc<Ty>),
+}
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+#[subst_types(Term, Ty)]
+enum Term {
+    Var(#[var_index(sort = Term)] usize),
+    Lam(Arc<Ty>, #[binding(sort = Term)] Arc<Term>),
+    TyLam(#[binding(sort = Ty)] Arc<Term>),
+}
+
+/// A variable wrapper, whose substitution has a different type.
+#[derive(Clone, Debug, PartialEq, Substitute)]
+#[subst_types(LambdaExpr)]
+struct Hole(#[var_index] usize);
+
+impl From<Hole> for LambdaExpr {
+    fn from(value: Hole) -> Self {
+        Var(value.0)
+    }
+}
+
+use LambdaExpr::*;
+
+fn lambda(body: LambdaExpr) -> LambdaExpr {
+    Lambda(Rc::new(body))
+}
+
+fn app(f: LambdaExpr, x: LambdaExpr) -> LambdaExpr {
+    App(Rc::new(f), Rc::new(x))
+}
+
+fn children(expr: &LambdaExpr) -> (&Rc<LambdaExpr>, &Rc<LambdaExpr>) {
+    match expr {
+        App(f, x) => (f, x),
+        _ => panic!("Expected an application, found {expr:?}"),
+    }
+}
+
+#[test]
+fn shifting_shares_closed_subterms() {
+    let expr = app(lambda(Var(0)), Var(0));
+    let shifted = expr.increment_indices();
+    assert_eq!(shifted, app(lambda(Var(0)), Var(1)));
+
+    let (f, x) = children(&expr);
+    let (shifted_f, shifted_x) = children(&shifted);
+    assert!(Rc::ptr_eq(f, shifted_f));
+    assert!(!Rc::ptr_eq(x, shifted_x));
+}
+
+#[test]
+fn shifting_shares_subterms_below_start() {
+    let expr = app(Var(0), Var(2));
+    let shifted = expr.increment_indices_from_by(1, 1);
+    assert_eq!(shifted, app(Var(0), Var(3)));
+    assert!(Rc::ptr_eq(children(&expr).0, children(&shifted).0));
+}
+
+#[test]
+fn substitution_shares_unchanged_subterms() {
+    let expr = app(lambda(app(Var(0), Var(0))), Var(0));
+    let substituted = expr.substitute(&Var(5), 0).unwrap();
+    assert_eq!(substituted, app(lambda(app(Var(0), Var(0))), Var(5)));
+    assert!(Rc::ptr_eq(children(&expr).0, children(&substituted).0));
+
+    // Substituting for a variable which doesn't occur shares the whole term,
+    // as long as no variables are renumbered.
+    let shared = Rc::new(app(Var(0), Var(1)));
+    assert!(Rc::ptr_eq(&shared, &shared.substitute(&Var(5), 2).unwrap()));
+    assert!(!Rc::ptr_eq(
+        &shared,
+        &shared.substitute(&Var(5), 1).unwrap()
+    ));
+}
+
+#[test]
+fn sharing_respects_sorts() {
+    // The term variable is free under the type binder
+    let term = Arc::new(Term::TyLam(Arc::new(Term::Var(0))));
+    assert!(term.has_sort_indices_from(Some(Sort::of::<Term>()), 0));
+    assert!(!term.has_sort_indices_from(Some(Sort::of::<Ty>()), 0));
+
+    let shifted = term.increment_sort_indices_by(Some(Sort::of::<Term>()), 1);
+    assert_eq!(*shifted, Term::TyLam(Arc::new(Term::Var(1))));
+    let shifted = term.increment_sort_indices_by(Some(Sort::of::<Ty>()), 1);
+    assert!(Arc::ptr_eq(&term, &shifted));
+
+    let ty = Arc::new(Ty::Arrow(Arc::new(Ty::Var(0)), Arc::new(Ty::Var(1))));
+    let term = Arc::new(Term::Lam(ty.clone(), Arc::new(Term::Var(1))));
+    let substituted =
+        Substitute::<Term>::substitute(&term, &Term::Var(3), 0).unwrap();
+    assert_eq!(*substituted, Term::Lam(ty.clone(), Arc::new(Term::Var(4))));
+    match &*substituted {
+        Term::Lam(substituted_ty, _) => {
+            assert!(Arc::ptr_eq(&ty, substituted_ty))
+        }
+        _ => unreachable!(),
+    }
+
+    // Substitution under a binder of another sort still replaces the variable.
+    let term = Arc::new(Term::TyLam(Arc::new(Term::Var(0))));
+    let substituted =
+        Substitute::<Term>::substitute(&term, &Term::Var(7), 0).unwrap();
+    assert_eq!(*substituted, Term::TyLam(Arc::new(Term::Var(7))));
+}
+
+#[test]
+fn only_changed_terms_are_rebuilt() {
+    let expr = app(lambda(Var(0)), Var(2));
+    assert_eq!(expr.map_changed_sort_indices_from(None, 0, |i| i), None);
+    assert_eq!(expr.map_changed_sort_indices_from(None, 3, |i| i + 1), None);
+    assert_eq!(
+        expr.map_changed_sort_indices_from(None, 1, |i| i + 1),
+        Some(app(lambda(Var(0)), Var(3)))
+    );
+
+    let exprs = vec![Rc::new(Var(0)), Rc::new(Var(1)), Rc::new(Var(0))];
+    let shifted = exprs.increment_indices_from_by(1, 1);
+    assert!(Rc::ptr_eq(&exprs[0], &shifted[0]));
+    assert_eq!(*shifted[1], Var(2));
+    assert!(Rc::ptr_eq(&exprs[2], &shifted[2]));
+}
+
+#[test]
+fn only_changed_terms_are_substituted() {
+    let expr = app(lambda(Var(0)), Var(0));
+    let subst = ttt::Subst::single(1, Var(5));
+    assert_eq!(expr.subst_changed_from(&subst, 0), Ok(None));
+    let subst = ttt::Subst::single(0, Var(5));
+    assert_eq!(
+        expr.subst_changed_from(&subst, 0),
+        Ok(Some(app(lambda(Var(0)), Var(5))))
+    );
+
+    let exprs = vec![Rc::new(Var(0)), Rc::new(Var(1)), Rc::new(Var(0))];
+    let substituted = exprs.substitute(&Var(5), 1).unwrap();
+    assert!(Rc::ptr_eq(&exprs[0], &substituted[0]));
+    assert_eq!(*substituted[1], Var(5));
+    assert!(Rc::ptr_eq(&exprs[2], &substituted[2]));
+}
+
+#[test]
+fn shared_substitution_can_change_type() {
+    let hole = Rc::new(Hole(0));
+    let filled: Rc<LambdaExpr> = hole.substitute(&lambda(Var(0)), 0).unwrap();
+    assert_eq!(*filled, lambda(Var(0)));
+}
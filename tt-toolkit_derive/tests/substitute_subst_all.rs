@@ -0,0 +1,149 @@
+use ttt::{DeBruijnIndexed, Subst, SubstError, Substitute};
+
+#[derive(Clone, Debug, PartialEq, DeBruijnIndexed, Substitute)]
+enum Expr {
+    Var(#[var_index] usize),
+    Lam(#[binding] Box<Expr>),
+    App(Box<Expr>, Box<Expr>),
+    LetPair(Box<Expr>, #[binding(2)] Box<Expr>),
+    Pi {
+        #[telescope]
+        params: Vec<Expr>,
+        #[binding]
+        body: Box<Expr>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Substitute)]
+#[subst_types(Literal)]
+enum Scoped {
+    Var(#[var_index] usize),
+    Lit,
+    Lam(#[binding] Box<Scoped>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Literal(String);
+
+impl From<Literal> for Scoped {
+    fn from(_: Literal) -> Self {
+        Scoped::Lit
+    }
+}
+
+fn var(index: usize) -> Expr {
+    Expr::Var(index)
+}
+
+fn lam(body: Expr) -> Expr {
+    Expr::Lam(Box::new(body))
+}
+
+fn app(f: Expr, x: Expr) -> Expr {
+    Expr::App(Box::new(f), Box::new(x))
+}
+
+#[test]
+fn agrees_with_single_substitution() {
+    let x = app(var(0), lam(app(var(1), var(2))));
+    let arg = lam(var(3));
+    assert_eq!(
+        x.subst_all(&Subst::instantiate(vec![arg.clone()])),
+        x.substitute(&arg, 0)
+    );
+}
+
+#[test]
+fn replaces_several_variables_at_once() {
+    // 0 1 2 3, replacing 0 and 1 and renumbering the rest from 0
+    let x = app(app(var(0), var(1)), app(var(2), var(3)));
+    let subst = Subst::instantiate(vec![var(5), lam(var(0))]);
+    assert_eq!(
+        x.subst_all(&subst),
+        Ok(app(app(var(5), lam(var(0))), app(var(0), var(1))))
+    );
+
+    // The substituted terms are not themselves substituted into
+    let subst = Subst::instantiate(vec![var(1), var(0)]);
+    assert_eq!(
+        app(var(0), var(1)).subst_all(&subst),
+        Ok(app(var(1), var(0)))
+    );
+}
+
+#[test]
+fn lifts_under_binders() {
+    let x = lam(app(var(0), app(var(1), var(3))));
+    let subst = Subst::new(vec![var(4)], 2);
+    assert_eq!(
+        x.subst_all(&subst),
+        Ok(lam(app(var(0), app(var(5), var(4)))))
+    );
+
+    let x = Expr::LetPair(Box::new(var(0)), Box::new(app(var(1), var(2))));
+    let subst = Subst::instantiate(vec![var(7)]);
+    assert_eq!(
+        x.subst_all(&subst),
+        Ok(Expr::LetPair(
+            Box::new(var(7)),
+            Box::new(app(var(1), var(9)))
+        ))
+    );
+}
+
+#[test]
+fn lifts_under_telescopes() {
+    let x = Expr::Pi {
+        params: vec![var(0), var(1)],
+        body: Box::new(var(2)),
+    };
+    let subst = Subst::instantiate(vec![var(3)]);
+    assert_eq!(
+        x.subst_all(&subst),
+        Ok(Expr::Pi {
+            params: vec![var(3), var(4)],
+            body: Box::new(var(5)),
+        })
+    );
+}
+
+#[test]
+fn weakens_and_identity() {
+    let x = lam(app(var(0), var(1)));
+    assert_eq!(x.subst_all(&Subst::identity()), Ok(x.clone()));
+    assert_eq!(
+        x.subst_all(&Subst::weaken(3)),
+        Ok(x.increment_indices_by(3))
+    );
+}
+
+#[test]
+fn cons_adds_the_innermost_variable() {
+    let x = app(app(var(0), var(1)), var(2));
+    let subst = Subst::instantiate(vec![var(5)]).cons(var(6)).cons(var(7));
+    assert_eq!(x.subst_all(&subst), Ok(app(app(var(7), var(6)), var(5))));
+    assert_eq!(
+        subst.terms().collect::<Vec<_>>(),
+        vec![&var(7), &var(6), &var(5)]
+    );
+}
+
+#[test]
+fn unshiftable_terms_are_an_error_under_binders() {
+    let lit = Literal("x".to_string());
+    let subst = Subst::instantiate(vec![lit]);
+    assert_eq!(Scoped::Var(0).subst_all(&subst), Ok(Scoped::Lit));
+    assert_eq!(
+        Scoped::Lam(Box::new(Scoped::Var(1))).subst_all(&subst),
+        Err(SubstError::not_shiftable::<Literal>())
+    );
+}
+
+#[test]
+fn single_substitutions_extend_like_others() {
+    let subst = Subst::single(0, var(3)).cons(var(4));
+    assert_eq!(subst, Subst::instantiate(vec![var(4), var(3)]));
+
+    let x = lam(app(var(1), var(2)));
+    assert_eq!(x.subst_all(&subst), Ok(lam(app(var(5), var(4)))));
+}
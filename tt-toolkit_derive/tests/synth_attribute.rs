@@ -45,7 +45,7 @@ fn check_lambda() {
     let ty = Ty::Func(Ty::Unit.into(), Ty::Unit.into());
 
     assert_eq!(
-        SynthAttribute::<Ty>::synth(&expr, &Context::empty()).unwrap(),
+        SynthAttribute::<Ty>::synth(&expr, &Context::empty(), &()).unwrap(),
         ty
     )
 }
@@ -61,7 +61,7 @@ fn check_pair() {
         Prod(Unit.into(), Unit.into())
     };
     assert_eq!(
-        SynthAttribute::<Ty>::synth(&expr, &Context::empty()).unwrap(),
+        SynthAttribute::<Ty>::synth(&expr, &Context::empty(), &()).unwrap(),
         ty
     )
 }
@@ -71,7 +71,7 @@ fn check_lambda_app() {
     use Expr::*;
     let expr = App(Lam(Ty::Unit.into(), Unit.into()).into(), Unit.into());
     assert_eq!(
-        SynthAttribute::<Ty>::synth(&expr, &Context::empty()).unwrap(),
+        SynthAttribute::<Ty>::synth(&expr, &Context::empty(), &()).unwrap(),
         Ty::Unit
     )
 }